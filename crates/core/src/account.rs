@@ -155,7 +155,10 @@ pub struct AccountPipe<T: Send> {
 ///
 /// `AccountPipes` defines the `run` method for processing account updates in
 /// the pipeline. Implementations should handle the decoding and processing of
-/// the account data, and update metrics as needed.
+/// the account data, and update metrics as needed. The `rollback` method is
+/// called when a slot is abandoned by a fork, and the `initialize`, `flush`
+/// and `shutdown` methods at the matching points of the pipeline's lifecycle.
+/// All of them do nothing by default.
 ///
 /// # Example
 ///
@@ -185,6 +188,26 @@ pub trait AccountPipes: Send + Sync {
         account_with_metadata: (AccountMetadata, solana_sdk::account::Account),
         metrics: Arc<MetricsCollection>,
     ) -> CarbonResult<()>;
+
+    async fn rollback(&mut self, slot: u64, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = (slot, metrics);
+        Ok(())
+    }
+
+    async fn initialize(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+
+    async fn flush(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+
+    async fn shutdown(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -199,11 +222,51 @@ impl<T: Send> AccountPipes for AccountPipe<T> {
             account_with_metadata,
         );
 
-        if let Some(decoded_account) = self.decoder.decode_account(&account_with_metadata.1) {
-            self.processor
-                .process((account_with_metadata.0, decoded_account), metrics)
-                .await?;
+        let decoded_account = {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!("decode").entered();
+
+            self.decoder.decode_account(&account_with_metadata.1)
+        };
+
+        if let Some(decoded_account) = decoded_account {
+            let process = self
+                .processor
+                .process((account_with_metadata.0, decoded_account), metrics);
+
+            #[cfg(feature = "tracing")]
+            let process = {
+                use tracing::Instrument;
+
+                process.instrument(tracing::info_span!("process"))
+            };
+
+            process.await?;
         }
         Ok(())
     }
+
+    async fn rollback(&mut self, slot: u64, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("AccountPipe::rollback(slot: {:?}, metrics)", slot);
+
+        self.processor.rollback(slot, metrics).await
+    }
+
+    async fn initialize(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("AccountPipe::initialize(metrics)");
+
+        self.processor.initialize(metrics).await
+    }
+
+    async fn flush(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("AccountPipe::flush(metrics)");
+
+        self.processor.flush(metrics).await
+    }
+
+    async fn shutdown(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("AccountPipe::shutdown(metrics)");
+
+        self.processor.shutdown(metrics).await
+    }
 }
@@ -0,0 +1,132 @@
+//! Provides structures and traits for handling account deletion events within
+//! the pipeline.
+//!
+//! This module defines the `AccountDeletionPipe` structure and the
+//! `AccountDeletionPipes` trait, which allow for the processing of account
+//! deletion events as they occur in the pipeline. By implementing
+//! `AccountDeletionPipes`, you can create custom logic to handle account
+//! deletions, such as cleaning up data or updating records.
+//!
+//! # Example
+//!
+//! ```rust
+//! struct MyAccountDeletionProcessor;
+//!
+//! #[async_trait]
+//! impl Processor for MyAccountDeletionProcessor {
+//!     type InputType = AccountDeletion;
+//!
+//!     async fn process(
+//!         &mut self,
+//!         account_deletion: AccountDeletion,
+//!         metrics: Arc<MetricsCollection>,
+//!     ) -> CarbonResult<()> {
+//!         log::info!("account {} deleted", account_deletion.pubkey);
+//!         Ok(())
+//!     }
+//! }
+//! ```
+//!
+//! # Notes
+//!
+//! - Account deletions are delivered by datasources that report them, such as
+//!   Geyser based datasources, as `Update::AccountDeletion`.
+
+use {
+    crate::{
+        datasource::AccountDeletion, error::CarbonResult, metrics::MetricsCollection,
+        processor::Processor,
+    },
+    async_trait::async_trait,
+    std::sync::Arc,
+};
+
+/// A processing pipe that passes account deletion events to a `Processor`.
+///
+/// # Fields
+///
+/// - `processor`: A `Processor` that handles `AccountDeletion` events.
+pub struct AccountDeletionPipe {
+    pub processor: Box<dyn Processor<InputType = AccountDeletion> + Send + Sync>,
+}
+
+/// A trait for processing account deletion events in the pipeline
+/// asynchronously.
+///
+/// `AccountDeletionPipes` defines the `run` method for processing account
+/// deletions. The `rollback` method is called when a slot is abandoned by a
+/// fork, and the `initialize`, `flush` and `shutdown` methods at the matching
+/// points of the pipeline's lifecycle. All of them do nothing by default.
+///
+/// # Parameters
+///
+/// - `account_deletion`: The `AccountDeletion` event to process.
+/// - `metrics`: A list of `Metrics` objects for recording and tracking metrics.
+#[async_trait]
+pub trait AccountDeletionPipes: Send + Sync {
+    async fn run(
+        &mut self,
+        account_deletion: AccountDeletion,
+        metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()>;
+
+    async fn rollback(&mut self, slot: u64, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = (slot, metrics);
+        Ok(())
+    }
+
+    async fn initialize(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+
+    async fn flush(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+
+    async fn shutdown(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AccountDeletionPipes for AccountDeletionPipe {
+    async fn run(
+        &mut self,
+        account_deletion: AccountDeletion,
+        metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()> {
+        log::trace!(
+            "AccountDeletionPipe::run(account_deletion: {:?}, metrics)",
+            account_deletion,
+        );
+
+        self.processor.process(account_deletion, metrics).await
+    }
+
+    async fn rollback(&mut self, slot: u64, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("AccountDeletionPipe::rollback(slot: {:?}, metrics)", slot);
+
+        self.processor.rollback(slot, metrics).await
+    }
+
+    async fn initialize(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("AccountDeletionPipe::initialize(metrics)");
+
+        self.processor.initialize(metrics).await
+    }
+
+    async fn flush(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("AccountDeletionPipe::flush(metrics)");
+
+        self.processor.flush(metrics).await
+    }
+
+    async fn shutdown(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("AccountDeletionPipe::shutdown(metrics)");
+
+        self.processor.shutdown(metrics).await
+    }
+}
@@ -0,0 +1,164 @@
+//! Provides structures and traits for processing block-level data within the
+//! pipeline.
+//!
+//! Datasources that observe whole blocks send their block-level details, such
+//! as the blockhash, parent slot, block time and rewards, as `Update::Block`.
+//! These updates are routed through block pipes, independently of the
+//! transactions contained in the block.
+//!
+//! # Overview
+//!
+//! - **`BlockDetails`**: The block-level data of a single slot.
+//! - **`BlockPipe`**: Passes `BlockDetails` to a `Processor`.
+//! - **`BlockPipes`**: The trait through which the pipeline runs block pipes.
+//!
+//! # Example
+//!
+//! ```rust
+//! struct BlockTimeProcessor;
+//!
+//! #[async_trait]
+//! impl Processor for BlockTimeProcessor {
+//!     type InputType = BlockDetails;
+//!
+//!     async fn process(
+//!         &mut self,
+//!         block_details: BlockDetails,
+//!         metrics: Arc<MetricsCollection>,
+//!     ) -> CarbonResult<()> {
+//!         log::info!("slot {} at {:?}", block_details.slot, block_details.block_time);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let builder = PipelineBuilder::new()
+//!     .datasource(MyDatasource::new())
+//!     .block(BlockTimeProcessor);
+//! ```
+//!
+//! # Notes
+//!
+//! - Fields a datasource cannot provide are left as `None`.
+
+use {
+    crate::{error::CarbonResult, metrics::MetricsCollection, processor::Processor},
+    async_trait::async_trait,
+    serde::{Deserialize, Serialize},
+    solana_sdk::hash::Hash,
+    solana_transaction_status::Reward,
+    std::sync::Arc,
+};
+
+/// Holds the block-level data of a slot.
+///
+/// # Fields
+///
+/// - `slot`: The slot of the block.
+/// - `parent_slot`: The slot of the parent block.
+/// - `blockhash`: The hash of the block.
+/// - `previous_blockhash`: The hash of the parent block.
+/// - `block_time`: The estimated production time of the block, as a Unix
+///   timestamp.
+/// - `block_height`: The number of blocks beneath this block.
+/// - `rewards`: The rewards paid out in the block.
+/// - `transaction_count`: The number of transactions in the block.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlockDetails {
+    pub slot: u64,
+    pub parent_slot: Option<u64>,
+    pub blockhash: Option<Hash>,
+    pub previous_blockhash: Option<Hash>,
+    pub block_time: Option<i64>,
+    pub block_height: Option<u64>,
+    pub rewards: Option<Vec<Reward>>,
+    pub transaction_count: Option<u64>,
+}
+
+/// A processing pipe that passes block details to a `Processor`.
+///
+/// # Fields
+///
+/// - `processor`: A `Processor` that handles `BlockDetails`.
+pub struct BlockPipe {
+    pub processor: Box<dyn Processor<InputType = BlockDetails> + Send + Sync>,
+}
+
+/// A trait for processing block updates in the pipeline asynchronously.
+///
+/// `BlockPipes` defines the `run` method for processing block details. The
+/// `rollback` method is called when a slot is abandoned by a fork, and the
+/// `initialize`, `flush` and `shutdown` methods at the matching points of the
+/// pipeline's lifecycle. All of them do nothing by default.
+///
+/// # Parameters
+///
+/// - `block_details`: The `BlockDetails` of the block.
+/// - `metrics`: A list of `Metrics` objects for recording and tracking metrics.
+#[async_trait]
+pub trait BlockPipes: Send + Sync {
+    async fn run(
+        &mut self,
+        block_details: BlockDetails,
+        metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()>;
+
+    async fn rollback(&mut self, slot: u64, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = (slot, metrics);
+        Ok(())
+    }
+
+    async fn initialize(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+
+    async fn flush(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+
+    async fn shutdown(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BlockPipes for BlockPipe {
+    async fn run(
+        &mut self,
+        block_details: BlockDetails,
+        metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()> {
+        log::trace!(
+            "BlockPipe::run(block_details: {:?}, metrics)",
+            block_details,
+        );
+
+        self.processor.process(block_details, metrics).await
+    }
+
+    async fn rollback(&mut self, slot: u64, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("BlockPipe::rollback(slot: {:?}, metrics)", slot);
+
+        self.processor.rollback(slot, metrics).await
+    }
+
+    async fn initialize(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("BlockPipe::initialize(metrics)");
+
+        self.processor.initialize(metrics).await
+    }
+
+    async fn flush(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("BlockPipe::flush(metrics)");
+
+        self.processor.flush(metrics).await
+    }
+
+    async fn shutdown(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("BlockPipe::shutdown(metrics)");
+
+        self.processor.shutdown(metrics).await
+    }
+}
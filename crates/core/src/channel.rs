@@ -0,0 +1,803 @@
+//! Provides the update channel that connects datasources to the pipeline.
+//!
+//! Datasources push updates into an `UpdateSender` while the pipeline pulls
+//! them out of the matching `UpdateReceiver`. The channel can be bounded, in
+//! which case an `OverflowPolicy` decides what happens when a datasource
+//! produces updates faster than the pipeline can process them.
+//!
+//! # Overview
+//!
+//! - **`channel`**: Creates a connected `UpdateSender` / `UpdateReceiver` pair
+//!   with an optional capacity and an `OverflowPolicy`.
+//! - **`OverflowPolicy`**: Determines how a full channel handles new updates:
+//!   blocking the sender, dropping the oldest or newest update, or spilling
+//!   the overflow to a file on disk.
+//! - **`UpdateSender`**: The cloneable sending half handed to datasources.
+//! - **`UpdateReceiver`**: The receiving half owned by the pipeline.
+//! - **`SenderActivity`**: Tracks when the datasource holding an
+//!   `UpdateSender` last sent an update, so that stalls can be told apart
+//!   from backpressure.
+//!
+//! # Notes
+//!
+//! - Without a capacity the channel is unbounded and the overflow policy is
+//!   never consulted.
+//! - Spilled updates are read back in the order they were sent, before any
+//!   update sent after them, so ordering is preserved across the spill file.
+//! - `UpdateReceiver::len` counts the updates held in memory, while
+//!   `UpdateReceiver::spilled` counts those waiting in the spill file.
+//! - Updates are encoded and the spill file is read and written without the
+//!   channel's queue locked, so a slow disk only holds up senders that spill.
+//! - Every update is stamped with the time it was sent, which
+//!   `UpdateReceiver::recv_with_timestamp` returns along with the update.
+//!   The timestamp is kept when an update is spilled to disk.
+
+use {
+    crate::{
+        codec::UpdateCodec,
+        error::{CarbonResult, Error},
+    },
+    std::{
+        collections::VecDeque,
+        fs::{File, OpenOptions},
+        io::{BufReader, Read, Seek, SeekFrom, Write},
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicU64, AtomicUsize, Ordering},
+            Arc, Mutex, MutexGuard,
+        },
+        time::{Duration, SystemTime},
+    },
+    tokio::{sync::Notify, time::Instant},
+};
+
+/// Defines how a bounded update channel behaves once it is full.
+///
+/// # Variants
+///
+/// - `Block`: Waits until the pipeline has made room. This applies
+///   backpressure to the datasource and is the default behavior.
+/// - `DropOldest`: Discards the oldest queued update to make room for the new
+///   one.
+/// - `DropNewest`: Discards the update that is being sent.
+/// - `SpillToDisk`: Appends overflowing updates to the file at `path`, encoded
+///   with `codec`, and feeds them back to the pipeline once there is room.
+///
+/// # Notes
+///
+/// - Dropped updates are counted and reported by the pipeline through the
+///   `updates_dropped` counter.
+/// - Spilled updates are reported through the `updates_spilled` gauge,
+///   separately from the `updates_queued` gauge of updates held in memory,
+///   which can be compared with the `update_channel_capacity` gauge.
+/// - `DropOldest` and `DropNewest` do not hold back checkpoints. A pipeline
+///   with a `Checkpointer` can commit a checkpoint past a dropped update, which
+///   is then not delivered again after a restart. Use `Block` or `SpillToDisk`
+///   when every update must be processed.
+/// - The spill file is truncated when the channel is created and removed when
+///   the receiver is dropped.
+#[derive(Default)]
+pub enum OverflowPolicy<T> {
+    #[default]
+    Block,
+    DropOldest,
+    DropNewest,
+    SpillToDisk {
+        path: PathBuf,
+        codec: Arc<dyn UpdateCodec<T>>,
+    },
+}
+
+impl<T> Clone for OverflowPolicy<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Block => Self::Block,
+            Self::DropOldest => Self::DropOldest,
+            Self::DropNewest => Self::DropNewest,
+            Self::SpillToDisk { path, codec } => Self::SpillToDisk {
+                path: path.clone(),
+                codec: codec.clone(),
+            },
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for OverflowPolicy<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Block => f.write_str("Block"),
+            Self::DropOldest => f.write_str("DropOldest"),
+            Self::DropNewest => f.write_str("DropNewest"),
+            Self::SpillToDisk { path, .. } => f
+                .debug_struct("SpillToDisk")
+                .field("path", path)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+/// Creates a new update channel, returning the sending and receiving halves.
+///
+/// # Parameters
+///
+/// - `capacity`: The maximum number of updates held in memory, or `None` for
+///   an unbounded channel.
+/// - `policy`: The `OverflowPolicy` applied when a bounded channel is full.
+///
+/// # Returns
+///
+/// Returns a `CarbonResult` containing the `UpdateSender` and
+/// `UpdateReceiver`, or an error if the spill file cannot be created.
+///
+/// # Example
+///
+/// ```rust
+/// let (sender, mut receiver) = channel::<Update>(Some(10_000), OverflowPolicy::Block)?;
+/// ```
+pub fn channel<T>(
+    capacity: Option<usize>,
+    policy: OverflowPolicy<T>,
+) -> CarbonResult<(UpdateSender<T>, UpdateReceiver<T>)> {
+    log::trace!("channel(capacity: {:?}, policy: {:?})", capacity, policy);
+
+    if capacity == Some(0) {
+        return Err(Error::Custom(
+            "update channel capacity must be greater than zero".to_string(),
+        ));
+    }
+
+    let spill = match &policy {
+        OverflowPolicy::SpillToDisk { path, .. } if capacity.is_some() => {
+            Some(SpillFile::create(path.clone())?)
+        }
+        _ => None,
+    };
+
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            spilled: 0,
+            receiver_closed: false,
+        }),
+        spills: spill.is_some(),
+        spill: Mutex::new(spill),
+        capacity,
+        policy,
+        senders: AtomicUsize::new(1),
+        dropped: AtomicU64::new(0),
+        update_available: Notify::new(),
+        space_available: Notify::new(),
+    });
+
+    Ok((
+        UpdateSender {
+            shared: shared.clone(),
+            activity: None,
+        },
+        UpdateReceiver { shared },
+    ))
+}
+
+/// The sending half of an update channel.
+///
+/// `UpdateSender` is cheap to clone; each datasource receives its own clone.
+/// The channel is closed for the receiver once every sender has been dropped.
+pub struct UpdateSender<T> {
+    shared: Arc<Shared<T>>,
+    activity: Option<Arc<SenderActivity>>,
+}
+
+impl<T> UpdateSender<T> {
+    /// Returns a clone of the sender that records its sends in `activity`.
+    ///
+    /// Clones of the returned sender record their sends in `activity` too.
+    pub fn with_activity(&self, activity: Arc<SenderActivity>) -> Self {
+        let mut sender = self.clone();
+        sender.activity = Some(activity);
+        sender
+    }
+
+    /// Sends an update into the channel.
+    ///
+    /// If the channel is full, the configured `OverflowPolicy` is applied.
+    /// With `OverflowPolicy::Block` this waits until the pipeline has made
+    /// room, during which the sender counts as active in its
+    /// `SenderActivity`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::UpdateChannelClosed` if the receiver has been dropped,
+    /// or an error if an update could not be written to the spill file.
+    pub async fn send(&self, update: T) -> CarbonResult<()> {
+        let sent_at = SystemTime::now();
+        let _sending = self.activity.as_deref().map(SenderActivity::start_send);
+
+        loop {
+            let notified = self.shared.space_available.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            {
+                let mut state = self.shared.lock();
+
+                if state.receiver_closed {
+                    return Err(Error::UpdateChannelClosed);
+                }
+
+                let full = self
+                    .shared
+                    .capacity
+                    .is_some_and(|capacity| state.queue.len() >= capacity);
+
+                if self.shared.spills && (full || state.spilled > 0) {
+                    drop(state);
+                    return self.spill(&update, sent_at);
+                }
+
+                if !full {
+                    state.queue.push_back((update, sent_at));
+                    drop(state);
+                    self.shared.update_available.notify_one();
+                    return Ok(());
+                }
+
+                match self.shared.policy {
+                    OverflowPolicy::DropOldest => {
+                        state.queue.pop_front();
+                        state.queue.push_back((update, sent_at));
+                        self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                        return Ok(());
+                    }
+                    OverflowPolicy::DropNewest => {
+                        self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                        return Ok(());
+                    }
+                    _ => {}
+                }
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Returns `true` if the receiving half has been dropped.
+    pub fn is_closed(&self) -> bool {
+        self.shared.lock().receiver_closed
+    }
+
+    /// Encodes an update and appends it to the spill file.
+    ///
+    /// Only the spill file is locked while it is written. The update is counted
+    /// as spilled once it has been written in full, so the receiver never
+    /// reads a partial record.
+    fn spill(&self, update: &T, sent_at: SystemTime) -> CarbonResult<()> {
+        let OverflowPolicy::SpillToDisk { codec, .. } = &self.shared.policy else {
+            unreachable!("spill file without spill policy");
+        };
+        let bytes = codec.encode(update)?;
+
+        let mut spill = self.shared.lock_spill();
+        let Some(spill_file) = spill.as_mut() else {
+            return Err(Error::UpdateChannelClosed);
+        };
+        spill_file.write(sent_at, &bytes)?;
+
+        let mut state = self.shared.lock();
+        if state.receiver_closed {
+            return Err(Error::UpdateChannelClosed);
+        }
+        state.spilled += 1;
+        drop(state);
+        drop(spill);
+
+        self.shared.update_available.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Clone for UpdateSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: self.shared.clone(),
+            activity: self.activity.clone(),
+        }
+    }
+}
+
+impl<T> Drop for UpdateSender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.update_available.notify_one();
+        }
+    }
+}
+
+/// Tracks when the datasource holding an `UpdateSender` was last active.
+///
+/// A sender is active while it is sending an update, including while it waits
+/// for room in a full channel, and was last active when its latest send
+/// returned. Activity is thus recorded where updates are produced rather than
+/// where they are consumed, so that a datasource held back by a slow pipeline
+/// does not look stalled.
+#[derive(Debug)]
+pub struct SenderActivity {
+    last_active: Mutex<Instant>,
+    sending: AtomicUsize,
+}
+
+impl Default for SenderActivity {
+    fn default() -> Self {
+        Self {
+            last_active: Mutex::new(Instant::now()),
+            sending: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl SenderActivity {
+    /// Marks the sender as active now.
+    pub fn touch(&self) {
+        *self
+            .last_active
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Instant::now();
+    }
+
+    /// Returns how long the sender has been idle, which is zero while it is
+    /// sending an update.
+    pub fn idle(&self) -> Duration {
+        if self.sending.load(Ordering::Acquire) > 0 {
+            return Duration::ZERO;
+        }
+
+        self.last_active
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .elapsed()
+    }
+
+    fn start_send(&self) -> SendGuard<'_> {
+        self.sending.fetch_add(1, Ordering::AcqRel);
+        SendGuard { activity: self }
+    }
+}
+
+/// Marks a `SenderActivity` as sending until dropped, including when the send
+/// is cancelled.
+struct SendGuard<'a> {
+    activity: &'a SenderActivity,
+}
+
+impl Drop for SendGuard<'_> {
+    fn drop(&mut self) {
+        self.activity.touch();
+        self.activity.sending.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// The receiving half of an update channel, owned by the pipeline.
+pub struct UpdateReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> UpdateReceiver<T> {
+    /// Receives the next update from the channel.
+    ///
+    /// Returns `None` once all senders have been dropped and every queued or
+    /// spilled update has been received.
+    pub async fn recv(&mut self) -> Option<T> {
+        self.recv_with_timestamp().await.map(|(update, _)| update)
+    }
+
+    /// Receives the next update from the channel, along with the time at
+    /// which it was sent.
+    ///
+    /// Returns `None` once all senders have been dropped and every queued or
+    /// spilled update has been received.
+    pub async fn recv_with_timestamp(&mut self) -> Option<(T, SystemTime)> {
+        loop {
+            let notified = self.shared.update_available.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let refill = {
+                let mut state = self.shared.lock();
+
+                if let Some(received) = state.queue.pop_front() {
+                    let refill = state.spilled > 0;
+                    drop(state);
+                    if refill {
+                        self.shared.refill();
+                    }
+                    self.shared.space_available.notify_one();
+                    return Some(received);
+                }
+
+                if state.spilled == 0 && self.shared.senders.load(Ordering::Acquire) == 0 {
+                    return None;
+                }
+
+                state.spilled > 0
+            };
+
+            if refill {
+                self.shared.refill();
+            } else {
+                notified.await;
+            }
+        }
+    }
+
+    /// Returns the number of updates waiting in memory, which is at most the
+    /// channel's capacity.
+    pub fn len(&self) -> usize {
+        self.shared.lock().queue.len()
+    }
+
+    /// Returns the number of updates waiting in the spill file.
+    pub fn spilled(&self) -> usize {
+        self.shared.lock().spilled
+    }
+
+    /// Returns `true` if no updates are waiting in the channel, in memory or
+    /// in the spill file.
+    pub fn is_empty(&self) -> bool {
+        let state = self.shared.lock();
+        state.queue.is_empty() && state.spilled == 0
+    }
+
+    /// Returns the in-memory capacity of the channel, or `None` if it is
+    /// unbounded.
+    pub fn capacity(&self) -> Option<usize> {
+        self.shared.capacity
+    }
+
+    /// Returns the number of updates dropped by the overflow policy since the
+    /// last call, resetting the count to zero.
+    pub fn take_dropped(&self) -> u64 {
+        self.shared.dropped.swap(0, Ordering::Relaxed)
+    }
+}
+
+impl<T> Drop for UpdateReceiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.receiver_closed = true;
+        state.queue.clear();
+        state.spilled = 0;
+        drop(state);
+        self.shared.lock_spill().take();
+        self.shared.space_available.notify_waiters();
+    }
+}
+
+/// The state shared by both halves of a channel.
+///
+/// The spill file has its own lock, which is always taken before `state`'s
+/// when both are held.
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    spills: bool,
+    spill: Mutex<Option<SpillFile>>,
+    capacity: Option<usize>,
+    policy: OverflowPolicy<T>,
+    senders: AtomicUsize,
+    dropped: AtomicU64,
+    update_available: Notify,
+    space_available: Notify,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lock_spill(&self) -> MutexGuard<'_, Option<SpillFile>> {
+        self.spill
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Moves spilled updates back into memory while there is room.
+    ///
+    /// The updates are read and decoded with only the spill file locked, which
+    /// keeps senders from spilling, and thus from overtaking them, until they
+    /// have been queued.
+    fn refill(&self) {
+        let OverflowPolicy::SpillToDisk { codec, .. } = &self.policy else {
+            return;
+        };
+        let mut spill = self.lock_spill();
+        let Some(spill_file) = spill.as_mut() else {
+            return;
+        };
+
+        let (spilled, room) = {
+            let state = self.lock();
+            let capacity = self.capacity.unwrap_or(usize::MAX);
+            (state.spilled, capacity.saturating_sub(state.queue.len()))
+        };
+
+        let mut received = Vec::with_capacity(spilled.min(room));
+        let mut failed = false;
+        while received.len() < spilled.min(room) {
+            match spill_file
+                .read()
+                .and_then(|(sent_at, bytes)| Ok((codec.decode(&bytes)?, sent_at)))
+            {
+                Ok(update) => received.push(update),
+                Err(error) => {
+                    let discarded = spilled - received.len();
+                    log::error!(
+                        "failed to read spilled update, discarding {} spilled updates: {:?}",
+                        discarded,
+                        error
+                    );
+                    self.dropped.fetch_add(discarded as u64, Ordering::Relaxed);
+                    if let Err(error) = spill_file.reset() {
+                        log::error!("failed to reset spill file: {:?}", error);
+                    }
+                    failed = true;
+                    break;
+                }
+            }
+        }
+
+        let mut state = self.lock();
+        state.spilled = if failed { 0 } else { spilled - received.len() };
+        state.queue.extend(received);
+    }
+}
+
+struct State<T> {
+    queue: VecDeque<(T, SystemTime)>,
+    spilled: usize,
+    receiver_closed: bool,
+}
+
+/// A file of length-prefixed records holding updates that did not fit in
+/// memory, each along with the time it was sent.
+struct SpillFile {
+    path: PathBuf,
+    writer: File,
+    reader: BufReader<File>,
+    pending: usize,
+    len: u64,
+}
+
+impl SpillFile {
+    fn create(path: PathBuf) -> CarbonResult<Self> {
+        let open = |options: &mut OpenOptions| {
+            options
+                .open(&path)
+                .map_err(|e| Error::transient("failed to open spill file", e))
+        };
+
+        File::create(&path).map_err(|e| Error::transient("failed to create spill file", e))?;
+        let writer = open(OpenOptions::new().append(true))?;
+        let reader = open(OpenOptions::new().read(true))?;
+
+        Ok(Self {
+            path,
+            writer,
+            reader: BufReader::new(reader),
+            pending: 0,
+            len: 0,
+        })
+    }
+
+    /// Appends a record, truncating the file back to its previous length if
+    /// the record could only be written in part, so that the records after it
+    /// stay readable.
+    fn write(&mut self, sent_at: SystemTime, bytes: &[u8]) -> CarbonResult<()> {
+        let length = u32::try_from(bytes.len())
+            .map_err(|_| Error::Custom("spilled update is too large".to_string()))?;
+        let sent_at = sent_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+
+        let written = self
+            .writer
+            .write_all(&length.to_le_bytes())
+            .and_then(|_| self.writer.write_all(&sent_at.to_le_bytes()))
+            .and_then(|_| self.writer.write_all(bytes));
+        if let Err(error) = written {
+            if let Err(error) = self.writer.set_len(self.len) {
+                log::error!(
+                    "failed to truncate partially written spill file: {:?}",
+                    error
+                );
+            }
+            return Err(Error::transient("failed to write spill file", error));
+        }
+
+        self.len += 4 + 8 + bytes.len() as u64;
+        self.pending += 1;
+        Ok(())
+    }
+
+    fn read(&mut self) -> CarbonResult<(SystemTime, Vec<u8>)> {
+        let mut length = [0u8; 4];
+        let mut sent_at = [0u8; 8];
+        self.reader
+            .read_exact(&mut length)
+            .and_then(|_| self.reader.read_exact(&mut sent_at))
+            .map_err(|e| Error::transient("failed to read spill file", e))?;
+
+        let mut bytes = vec![0u8; u32::from_le_bytes(length) as usize];
+        self.reader
+            .read_exact(&mut bytes)
+            .map_err(|e| Error::transient("failed to read spill file", e))?;
+        let sent_at = SystemTime::UNIX_EPOCH + Duration::from_micros(u64::from_le_bytes(sent_at));
+
+        self.pending -= 1;
+        if self.pending == 0 {
+            self.reset()?;
+        }
+        Ok((sent_at, bytes))
+    }
+
+    fn reset(&mut self) -> CarbonResult<()> {
+        self.pending = 0;
+        self.len = 0;
+        self.writer
+            .set_len(0)
+            .and_then(|_| self.reader.seek(SeekFrom::Start(0)))
+            .map(|_| ())
+            .map_err(|e| Error::transient("failed to truncate spill file", e))
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        if let Err(error) = std::fs::remove_file(&self.path) {
+            log::warn!("failed to remove spill file {:?}: {:?}", self.path, error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::codec::JsonCodec};
+
+    async fn drain(mut receiver: UpdateReceiver<u32>) -> Vec<u32> {
+        let mut received = Vec::new();
+        while let Some(update) = receiver.recv().await {
+            received.push(update);
+        }
+        received
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_keeps_the_latest_updates() {
+        let (sender, receiver) = channel(Some(2), OverflowPolicy::DropOldest).unwrap();
+        for update in 0..5 {
+            sender.send(update).await.unwrap();
+        }
+
+        assert_eq!(receiver.len(), 2);
+        assert_eq!(receiver.take_dropped(), 3);
+        assert_eq!(receiver.take_dropped(), 0);
+        drop(sender);
+        assert_eq!(drain(receiver).await, vec![3, 4]);
+    }
+
+    #[tokio::test]
+    async fn drop_newest_keeps_the_earliest_updates() {
+        let (sender, receiver) = channel(Some(2), OverflowPolicy::DropNewest).unwrap();
+        for update in 0..5 {
+            sender.send(update).await.unwrap();
+        }
+
+        assert_eq!(receiver.take_dropped(), 3);
+        drop(sender);
+        assert_eq!(drain(receiver).await, vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn block_waits_for_room() {
+        let (sender, mut receiver) = channel(Some(1), OverflowPolicy::Block).unwrap();
+        sender.send(0).await.unwrap();
+
+        let blocked = tokio::spawn(async move { sender.send(1).await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!blocked.is_finished());
+        assert_eq!(receiver.len(), 1);
+
+        assert_eq!(receiver.recv().await, Some(0));
+        blocked.await.unwrap().unwrap();
+        assert_eq!(receiver.take_dropped(), 0);
+        assert_eq!(drain(receiver).await, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn spill_to_disk_preserves_order() {
+        let path = std::env::temp_dir().join(format!("carbon-spill-{}", std::process::id()));
+        let (sender, receiver) = channel(
+            Some(2),
+            OverflowPolicy::SpillToDisk {
+                path: path.clone(),
+                codec: Arc::new(JsonCodec),
+            },
+        )
+        .unwrap();
+        for update in 0..10 {
+            sender.send(update).await.unwrap();
+        }
+
+        assert_eq!(receiver.len(), 2);
+        assert_eq!(receiver.spilled(), 8);
+        assert!(path.exists());
+        drop(sender);
+        assert_eq!(drain(receiver).await, (0..10).collect::<Vec<_>>());
+        assert!(!path.exists());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_spills_keep_each_senders_order() {
+        let path =
+            std::env::temp_dir().join(format!("carbon-spill-concurrent-{}", std::process::id()));
+        let (sender, receiver) = channel(
+            Some(4),
+            OverflowPolicy::SpillToDisk {
+                path: path.clone(),
+                codec: Arc::new(JsonCodec),
+            },
+        )
+        .unwrap();
+        let senders: Vec<_> = (0..4)
+            .map(|base| {
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    for update in 0..250 {
+                        sender.send(base * 1000 + update).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        let receiving = tokio::spawn(drain(receiver));
+        for sender in senders {
+            sender.await.unwrap();
+        }
+        let received = receiving.await.unwrap();
+
+        assert_eq!(received.len(), 1000);
+        for base in 0..4 {
+            let sent: Vec<_> = received
+                .iter()
+                .filter(|update| **update / 1000 == base)
+                .copied()
+                .collect();
+            assert_eq!(
+                sent,
+                (0..250)
+                    .map(|update| base * 1000 + update)
+                    .collect::<Vec<_>>()
+            );
+        }
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn sends_fail_once_the_receiver_is_dropped() {
+        let (sender, receiver) = channel(Some(1), OverflowPolicy::Block).unwrap();
+        drop(receiver);
+
+        assert!(sender.is_closed());
+        assert!(matches!(
+            sender.send(0).await,
+            Err(Error::UpdateChannelClosed)
+        ));
+    }
+
+    #[test]
+    fn zero_capacity_is_rejected() {
+        assert!(channel::<u32>(Some(0), OverflowPolicy::Block).is_err());
+    }
+}
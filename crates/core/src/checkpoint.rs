@@ -0,0 +1,422 @@
+//! Provides persistent checkpoints so that pipelines can resume where they
+//! stopped.
+//!
+//! A checkpoint records, per datasource, the highest slot whose updates have
+//! all been processed by the pipeline. On startup the pipeline loads the
+//! checkpoint of each datasource and hands it to `Datasource::consume` as the
+//! resume point, so that a restart neither reprocesses hours of data nor skips
+//! any.
+//!
+//! # Overview
+//!
+//! - **`Checkpoint`**: The resume point of a datasource: a slot and, when
+//!   known, the signature of the last transaction processed in that slot.
+//! - **`Checkpointer`**: A trait for loading and committing checkpoints.
+//! - **`FileCheckpointer`**: Stores checkpoints as JSON in a local file.
+//! - **`SqliteCheckpointer`**: Stores checkpoints in a SQLite database.
+//!   Requires the `sqlite` feature.
+//!
+//! # Notes
+//!
+//! - Checkpoints are keyed by datasource id, so ids must stay stable across
+//!   restarts for a checkpoint to be found again.
+//! - Resuming from a checkpoint may deliver some updates of the checkpointed
+//!   slot again. Processors should tolerate seeing an update more than once.
+
+use {
+    crate::error::{CarbonResult, Error},
+    async_trait::async_trait,
+    serde::{Deserialize, Serialize},
+    solana_sdk::signature::Signature,
+    std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+        str::FromStr,
+    },
+    tokio::io::AsyncWriteExt,
+};
+
+#[cfg(feature = "sqlite")]
+use std::sync::{Arc, Mutex};
+
+/// The resume point of a datasource.
+///
+/// # Fields
+///
+/// - `slot`: The highest slot whose updates have all been processed.
+/// - `signature`: The signature of the last transaction processed in `slot`,
+///   if the checkpoint was committed after a transaction update.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub slot: u64,
+    pub signature: Option<Signature>,
+}
+
+/// A trait for persisting checkpoints across pipeline restarts.
+///
+/// The pipeline calls `load` once per datasource when it starts, and `commit`
+/// periodically and on shutdown with the latest checkpoint of each datasource.
+///
+/// # Example
+///
+/// ```rust
+/// struct RedisCheckpointer { /* ... */ }
+///
+/// #[async_trait]
+/// impl Checkpointer for RedisCheckpointer {
+///     async fn load(&self, datasource_id: &str) -> CarbonResult<Option<Checkpoint>> {
+///         // Read the checkpoint stored for `datasource_id`
+///     }
+///
+///     async fn commit(&self, datasource_id: &str, checkpoint: &Checkpoint) -> CarbonResult<()> {
+///         // Store `checkpoint` for `datasource_id`
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait Checkpointer: Send + Sync {
+    /// Loads the checkpoint stored for `datasource_id`, if any.
+    async fn load(&self, datasource_id: &str) -> CarbonResult<Option<Checkpoint>>;
+
+    /// Stores `checkpoint` for `datasource_id`, replacing any previous one.
+    async fn commit(&self, datasource_id: &str, checkpoint: &Checkpoint) -> CarbonResult<()>;
+}
+
+/// A `Checkpointer` that stores checkpoints as JSON in a local file.
+///
+/// All datasources share a single file. Each commit writes a temporary file,
+/// syncs it to disk and renames it over the previous file before syncing the
+/// directory, so a crash never leaves a partially written checkpoint behind.
+///
+/// # Notes
+///
+/// - The file is read and written with `tokio::fs`, so commits do not block
+///   the runtime. Commits are serialized, and a commit only returns once the
+///   checkpoint is on disk.
+pub struct FileCheckpointer {
+    path: PathBuf,
+    checkpoints: tokio::sync::Mutex<Option<HashMap<String, CheckpointRecord>>>,
+}
+
+impl FileCheckpointer {
+    /// Creates a `FileCheckpointer` storing checkpoints in the file at `path`.
+    ///
+    /// The file is created on the first commit if it does not exist.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            checkpoints: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    async fn checkpoints(
+        &self,
+    ) -> CarbonResult<tokio::sync::MutexGuard<'_, Option<HashMap<String, CheckpointRecord>>>> {
+        let mut checkpoints = self.checkpoints.lock().await;
+
+        if checkpoints.is_none() {
+            let loaded = match tokio::fs::read(&self.path).await {
+                Ok(bytes) => serde_json::from_slice(&bytes)
+                    .map_err(|e| Error::fatal("failed to parse checkpoint file", e))?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+                Err(e) => return Err(Error::transient("failed to read checkpoint file", e)),
+            };
+            *checkpoints = Some(loaded);
+        }
+
+        Ok(checkpoints)
+    }
+}
+
+/// Replaces the file at `path` with `bytes` through a temporary file, syncing
+/// both the file and, on Unix, its directory to disk.
+pub(crate) async fn write_durably(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let temporary_path = path.with_extension("tmp");
+
+    let mut file = tokio::fs::File::create(&temporary_path).await?;
+    file.write_all(bytes).await?;
+    file.sync_all().await?;
+    drop(file);
+
+    tokio::fs::rename(&temporary_path, path).await?;
+
+    #[cfg(unix)]
+    {
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        tokio::fs::File::open(directory).await?.sync_all().await?;
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl Checkpointer for FileCheckpointer {
+    async fn load(&self, datasource_id: &str) -> CarbonResult<Option<Checkpoint>> {
+        let checkpoints = self.checkpoints().await?;
+
+        checkpoints
+            .as_ref()
+            .and_then(|checkpoints| checkpoints.get(datasource_id))
+            .map(Checkpoint::try_from)
+            .transpose()
+    }
+
+    async fn commit(&self, datasource_id: &str, checkpoint: &Checkpoint) -> CarbonResult<()> {
+        let mut checkpoints = self.checkpoints().await?;
+        let checkpoints = checkpoints.get_or_insert_with(HashMap::new);
+        checkpoints.insert(datasource_id.to_string(), checkpoint.into());
+
+        let bytes = serde_json::to_vec_pretty(checkpoints)
+            .map_err(|e| Error::fatal("failed to encode checkpoints", e))?;
+
+        write_durably(&self.path, &bytes)
+            .await
+            .map_err(|e| Error::transient("failed to write checkpoint file", e))
+    }
+}
+
+/// A `Checkpointer` that stores checkpoints in a SQLite database.
+///
+/// Checkpoints are kept in a `carbon_checkpoints` table, which is created when
+/// the database is opened. Queries run on tokio's blocking thread pool, so
+/// that a slow disk does not hold up the pipeline's tasks.
+#[cfg(feature = "sqlite")]
+pub struct SqliteCheckpointer {
+    connection: Arc<Mutex<rusqlite::Connection>>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteCheckpointer {
+    /// Opens or creates the SQLite database at `path`.
+    pub fn open(path: impl AsRef<std::path::Path>) -> CarbonResult<Self> {
+        let connection = rusqlite::Connection::open(path)
+            .map_err(|e| Error::transient("failed to open checkpoint database", e))?;
+
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS carbon_checkpoints (
+                    datasource_id TEXT PRIMARY KEY,
+                    slot INTEGER NOT NULL,
+                    signature TEXT
+                )",
+                (),
+            )
+            .map_err(|e| Error::transient("failed to create checkpoint table", e))?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Runs `query` on the connection in a blocking task.
+    async fn run<T: Send + 'static>(
+        &self,
+        query: impl FnOnce(&rusqlite::Connection) -> CarbonResult<T> + Send + 'static,
+    ) -> CarbonResult<T> {
+        let connection = self.connection.clone();
+
+        tokio::task::spawn_blocking(move || {
+            query(
+                &connection
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            )
+        })
+        .await
+        .map_err(|e| Error::transient("checkpoint database task failed", e))?
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl Checkpointer for SqliteCheckpointer {
+    async fn load(&self, datasource_id: &str) -> CarbonResult<Option<Checkpoint>> {
+        use rusqlite::OptionalExtension;
+
+        let datasource_id = datasource_id.to_string();
+        let record = self
+            .run(move |connection| {
+                connection
+                    .query_row(
+                        "SELECT slot, signature FROM carbon_checkpoints WHERE datasource_id = ?1",
+                        [datasource_id],
+                        |row| {
+                            Ok(CheckpointRecord {
+                                slot: row.get::<_, i64>(0)? as u64,
+                                signature: row.get(1)?,
+                            })
+                        },
+                    )
+                    .optional()
+                    .map_err(|e| Error::transient("failed to load checkpoint", e))
+            })
+            .await?;
+
+        record.as_ref().map(Checkpoint::try_from).transpose()
+    }
+
+    async fn commit(&self, datasource_id: &str, checkpoint: &Checkpoint) -> CarbonResult<()> {
+        let datasource_id = datasource_id.to_string();
+        let record = CheckpointRecord::from(checkpoint);
+
+        self.run(move |connection| {
+            connection
+                .execute(
+                    "INSERT INTO carbon_checkpoints (datasource_id, slot, signature)
+                    VALUES (?1, ?2, ?3)
+                    ON CONFLICT (datasource_id) DO UPDATE SET
+                        slot = excluded.slot,
+                        signature = excluded.signature",
+                    rusqlite::params![datasource_id, record.slot as i64, record.signature],
+                )
+                .map_err(|e| Error::transient("failed to commit checkpoint", e))?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// The stored form of a `Checkpoint`, with the signature in base58.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CheckpointRecord {
+    slot: u64,
+    signature: Option<String>,
+}
+
+impl From<&Checkpoint> for CheckpointRecord {
+    fn from(checkpoint: &Checkpoint) -> Self {
+        Self {
+            slot: checkpoint.slot,
+            signature: checkpoint.signature.map(|signature| signature.to_string()),
+        }
+    }
+}
+
+impl TryFrom<&CheckpointRecord> for Checkpoint {
+    type Error = Error;
+
+    fn try_from(record: &CheckpointRecord) -> Result<Self, Self::Error> {
+        Ok(Self {
+            slot: record.slot,
+            signature: record
+                .signature
+                .as_deref()
+                .map(Signature::from_str)
+                .transpose()
+                .map_err(|e| Error::fatal("invalid checkpoint signature", e))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "carbon-checkpoint-{}-{}.json",
+            std::process::id(),
+            name
+        ))
+    }
+
+    #[tokio::test]
+    async fn file_checkpoints_survive_a_reload() {
+        let path = temporary_path("reload");
+        let checkpoint = Checkpoint {
+            slot: 42,
+            signature: Some(Signature::new_unique()),
+        };
+
+        let checkpointer = FileCheckpointer::new(&path);
+        assert_eq!(checkpointer.load("rpc").await.unwrap(), None);
+        checkpointer.commit("rpc", &checkpoint).await.unwrap();
+        checkpointer
+            .commit(
+                "ws",
+                &Checkpoint {
+                    slot: 7,
+                    signature: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let reloaded = FileCheckpointer::new(&path);
+        assert_eq!(reloaded.load("rpc").await.unwrap(), Some(checkpoint));
+        assert_eq!(reloaded.load("ws").await.unwrap().map(|c| c.slot), Some(7));
+        assert!(!path.with_extension("tmp").exists());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn commits_replace_previous_checkpoints() {
+        let path = temporary_path("replace");
+
+        let checkpointer = FileCheckpointer::new(&path);
+        for slot in [1, 2, 3] {
+            checkpointer
+                .commit(
+                    "rpc",
+                    &Checkpoint {
+                        slot,
+                        signature: None,
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        let reloaded = FileCheckpointer::new(&path);
+        assert_eq!(reloaded.load("rpc").await.unwrap().map(|c| c.slot), Some(3));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn corrupt_checkpoint_files_are_fatal() {
+        let path = temporary_path("corrupt");
+        std::fs::write(&path, b"{ not json").unwrap();
+
+        let error = FileCheckpointer::new(&path).load("rpc").await.unwrap_err();
+        assert!(!error.is_retryable());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_checkpoints_are_loaded_committed_and_replaced() {
+        let path = temporary_path("sqlite").with_extension("db");
+        let checkpoint = Checkpoint {
+            slot: 42,
+            signature: Some(Signature::new_unique()),
+        };
+
+        let checkpointer = SqliteCheckpointer::open(&path).unwrap();
+        assert_eq!(checkpointer.load("rpc").await.unwrap(), None);
+        checkpointer.commit("rpc", &checkpoint).await.unwrap();
+        assert_eq!(
+            checkpointer.load("rpc").await.unwrap(),
+            Some(checkpoint.clone())
+        );
+
+        let replacement = Checkpoint {
+            slot: 43,
+            signature: None,
+        };
+        checkpointer.commit("rpc", &replacement).await.unwrap();
+        checkpointer.commit("ws", &checkpoint).await.unwrap();
+        drop(checkpointer);
+
+        let reopened = SqliteCheckpointer::open(&path).unwrap();
+        assert_eq!(reopened.load("rpc").await.unwrap(), Some(replacement));
+        assert_eq!(reopened.load("ws").await.unwrap(), Some(checkpoint));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,71 @@
+//! Defines the `UpdateCodec` trait for turning pipeline values into bytes and
+//! back.
+//!
+//! Some parts of the pipeline need to move values out of memory, for example
+//! when the update channel spills overflowing updates to disk. The encoding is
+//! left to a pluggable codec rather than being hard-wired to a particular
+//! serialization format, so that applications can trade readability for
+//! compactness.
+//!
+//! # Overview
+//!
+//! - **`UpdateCodec`**: A trait with `encode` and `decode` methods for a value
+//!   type `T`.
+//! - **`JsonCodec`**: A ready-made codec for any type implementing `serde`'s
+//!   `Serialize` and `DeserializeOwned`, backed by `serde_json`. This includes
+//!   `datasource::Update`, so `JsonCodec` can be used wherever the pipeline
+//!   asks for an `UpdateCodec<Update>`.
+//!
+//! # Notes
+//!
+//! - Codecs are shared between tasks, so implementations must be `Send` and
+//!   `Sync`.
+//! - The encoded bytes only need to be readable by the same codec; they are
+//!   not meant as a stable interchange format.
+
+use {
+    crate::error::{CarbonResult, Error},
+    serde::{de::DeserializeOwned, Serialize},
+};
+
+/// A trait for encoding values of type `T` into bytes and decoding them back.
+///
+/// # Example
+///
+/// ```rust
+/// struct AccountUpdateCodec;
+///
+/// impl UpdateCodec<AccountUpdate> for AccountUpdateCodec {
+///     fn encode(&self, value: &AccountUpdate) -> CarbonResult<Vec<u8>> {
+///         // Custom encoding logic here
+///     }
+///
+///     fn decode(&self, bytes: &[u8]) -> CarbonResult<AccountUpdate> {
+///         // Custom decoding logic here
+///     }
+/// }
+/// ```
+pub trait UpdateCodec<T>: Send + Sync {
+    /// Encodes `value` into a byte vector.
+    fn encode(&self, value: &T) -> CarbonResult<Vec<u8>>;
+
+    /// Decodes a value previously produced by `encode`.
+    fn decode(&self, bytes: &[u8]) -> CarbonResult<T>;
+}
+
+/// An `UpdateCodec` that encodes values as JSON using `serde_json`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonCodec;
+
+impl<T> UpdateCodec<T> for JsonCodec
+where
+    T: Serialize + DeserializeOwned,
+{
+    fn encode(&self, value: &T) -> CarbonResult<Vec<u8>> {
+        serde_json::to_vec(value).map_err(|e| Error::fatal("failed to encode", e))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> CarbonResult<T> {
+        serde_json::from_slice(bytes).map_err(|e| Error::fatal("failed to decode", e))
+    }
+}
@@ -0,0 +1,45 @@
+//! Defines the `InstructionDecoderCollection` trait, which groups the
+//! instruction decoders of several programs behind a single type.
+//!
+//! Transaction pipes decode every instruction of a transaction with an
+//! `InstructionDecoderCollection`, so that a `TransactionSchema` can match
+//! instructions of different programs against each other. Collections are
+//! usually generated with the `instruction_decoder_collection!` macro rather
+//! than implemented by hand.
+//!
+//! # Example
+//!
+//! ```rust
+//! instruction_decoder_collection!(
+//!     AllInstructions, AllInstructionTypes, AllPrograms,
+//!     JupSwap => JupiterDecoder => JupiterInstruction,
+//!     MeteoraSwap => MeteoraDecoder => MeteoraInstruction
+//! );
+//! ```
+
+use crate::instruction::DecodedInstruction;
+
+/// A collection of instruction decoders for several programs.
+///
+/// # Associated Types
+///
+/// - `InstructionType`: The type of the instructions in the collection,
+///   without their data. Used to match instructions against a
+///   `TransactionSchema`.
+///
+/// # Required Methods
+///
+/// - `parse_instruction`: Decodes `instruction` with the first decoder of the
+///   collection that recognizes it.
+/// - `get_type`: Returns the `InstructionType` of a decoded instruction.
+pub trait InstructionDecoderCollection:
+    Clone + std::fmt::Debug + Send + Sync + Eq + std::hash::Hash + serde::Serialize + 'static
+{
+    type InstructionType: Clone + std::fmt::Debug + PartialEq + Eq + Send + Sync + 'static;
+
+    fn parse_instruction(
+        instruction: &solana_sdk::instruction::Instruction,
+    ) -> Option<DecodedInstruction<Self>>;
+
+    fn get_type(&self) -> Self::InstructionType;
+}
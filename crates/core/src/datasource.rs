@@ -0,0 +1,385 @@
+//! Provides traits and structures for managing and consuming data updates from
+//! various sources.
+//!
+//! The `datasource` module defines the `Datasource` trait and associated data
+//! types for handling updates related to accounts, transactions, account
+//! deletions, blocks and slots. This module allows for flexible data ingestion
+//! from various Solana data sources, enabling integration with the
+//! `carbon-core` processing pipeline.
+//!
+//! # Overview
+//!
+//! The core component of this module is the `Datasource` trait, which
+//! represents an interface for consuming data updates asynchronously.
+//! Implementations of `Datasource` provide the logic for fetching data updates
+//! and delivering them through the pipeline's update channel. Each datasource
+//! is identified within its pipeline by a `DatasourceId`. The module also
+//! defines several enums and structs:
+//!
+//! - **`Update`**: An enum representing different types of data updates,
+//!   including account updates, transaction updates, account deletions, block
+//!   details and slot status updates.
+//! - **`UpdateType`**: An enum indicating the type of update, used to specify
+//!   the kinds of updates a datasource can provide.
+//! - **`AccountUpdate`**: A struct containing data related to an account
+//!   update, including the account's public key, slot, and account data.
+//! - **`TransactionUpdate`**: A struct representing a transaction update,
+//!   including transaction details, status metadata, slot, and block time.
+//! - **`AccountDeletion`**: A struct representing the deletion of an account,
+//!   containing the account's public key and slot.
+//!
+//! Block details and slot status updates are defined in the `block` and `slot`
+//! modules.
+//!
+//! # Example
+//!
+//! ```rust
+//! struct MyDatasource;
+//!
+//! #[async_trait]
+//! impl Datasource for MyDatasource {
+//!     async fn consume(
+//!         &self,
+//!         id: DatasourceId,
+//!         sender: &UpdateSender<(Update, DatasourceId)>,
+//!         resume_from: Option<Checkpoint>,
+//!         cancellation_token: CancellationToken,
+//!         metrics: Arc<MetricsCollection>,
+//!     ) -> CarbonResult<()> {
+//!         // Fetch updates starting after `resume_from` and send them
+//!         // through `sender`, tagged with `id`.
+//!         Ok(())
+//!     }
+//!
+//!     fn update_types(&self) -> Vec<UpdateType> {
+//!         vec![UpdateType::AccountUpdate, UpdateType::Transaction]
+//!     }
+//! }
+//! ```
+//!
+//! # Notes
+//!
+//! - The `Datasource` trait is asynchronous and should be used within a Tokio
+//!   runtime.
+//! - `Update` implements `serde`'s `Serialize` and `Deserialize`, so it can be
+//!   encoded with `codec::JsonCodec` wherever the pipeline needs an
+//!   `UpdateCodec<Update>`.
+
+use {
+    crate::{
+        block::BlockDetails, channel::UpdateSender, checkpoint::Checkpoint, error::CarbonResult,
+        metrics::MetricsCollection, slot::SlotStatusUpdate,
+    },
+    async_trait::async_trait,
+    serde::{Deserialize, Serialize},
+    solana_sdk::{pubkey::Pubkey, signature::Signature},
+    std::sync::Arc,
+    tokio_util::sync::CancellationToken,
+};
+
+/// Defines the interface for data sources that produce updates for accounts,
+/// transactions, and account deletions.
+///
+/// The `Datasource` trait represents a data source that can be consumed
+/// asynchronously within a pipeline. Implementations of this trait are
+/// responsible for fetching updates and sending them through the provided
+/// `sender` channel, tagged with the id they were given. The `update_types`
+/// method specifies the types of updates that the datasource can provide.
+///
+/// # Required Methods
+///
+/// - `consume`: Initiates the asynchronous consumption of updates. This method
+///   should send updates through the `sender` until the `cancellation_token`
+///   is cancelled. If `resume_from` is set, the datasource should start after
+///   the given checkpoint rather than from the tip of the chain.
+/// - `update_types`: Returns a list of `UpdateType` variants indicating the
+///   types of updates the datasource can provide.
+///
+/// # Notes
+///
+/// - This trait is marked with `async_trait`, so implementations must be
+///   asynchronous.
+/// - The `consume` method should handle errors and retries to ensure robust
+///   update delivery. Returning an error hands it to the pipeline's
+///   `RestartPolicy`, if one is set.
+#[async_trait]
+pub trait Datasource: Send + Sync {
+    async fn consume(
+        &self,
+        id: DatasourceId,
+        sender: &UpdateSender<(Update, DatasourceId)>,
+        resume_from: Option<Checkpoint>,
+        cancellation_token: CancellationToken,
+        metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()>;
+
+    fn update_types(&self) -> Vec<UpdateType>;
+}
+
+/// Identifies a datasource within a pipeline.
+///
+/// Every update is tagged with the id of the datasource that produced it, and
+/// checkpoints are stored per datasource id. Datasources added with
+/// `PipelineBuilder::datasource` get an id based on the order in which they
+/// were added; use `PipelineBuilder::datasource_with_id` to choose one that
+/// stays stable when the pipeline configuration changes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DatasourceId(String);
+
+impl DatasourceId {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for DatasourceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Represents a data update in the `carbon-core` pipeline, encompassing
+/// different update types.
+///
+/// - `Account`: An update to an account's data.
+/// - `Transaction`: A transaction update, including transaction and status
+///   metadata.
+/// - `AccountDeletion`: An event representing the deletion of an account.
+/// - `Block`: The details of a block, such as its blockhash and rewards.
+/// - `SlotStatus`: A change in the progress of a slot towards finality.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Update {
+    Account(AccountUpdate),
+    Transaction(Box<TransactionUpdate>),
+    AccountDeletion(AccountDeletion),
+    Block(BlockDetails),
+    SlotStatus(SlotStatusUpdate),
+}
+
+/// Enumerates the types of updates a datasource can provide.
+///
+/// The `UpdateType` enum categorizes updates into the following types:
+/// - `AccountUpdate`: Indicates an update to account data.
+/// - `Transaction`: Represents a transaction-related update.
+/// - `AccountDeletion`: Signals the deletion of an account.
+/// - `BlockDetails`: Represents the details of a block.
+/// - `SlotStatus`: Signals a change in the status of a slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateType {
+    AccountUpdate,
+    Transaction,
+    AccountDeletion,
+    BlockDetails,
+    SlotStatus,
+}
+
+/// Represents an update to a Solana account, including its public key, data,
+/// and slot information.
+///
+/// The `AccountUpdate` struct encapsulates the essential information for an
+/// account update, containing the account's `pubkey`, `account` data, and the
+/// `slot` at which the update occurred.
+///
+/// - `pubkey`: The public key of the account being updated.
+/// - `account`: The new state of the account.
+/// - `slot`: The slot number in which this account update was recorded.
+/// - `write_version`: The write version of the update, if the datasource
+///   reports one. It orders the writes to an account within a slot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountUpdate {
+    pub pubkey: Pubkey,
+    pub account: solana_sdk::account::Account,
+    pub slot: u64,
+    #[serde(default)]
+    pub write_version: Option<u64>,
+}
+
+/// Represents the details of a Solana transaction update, including signature,
+/// metadata, and slot information.
+///
+/// The `TransactionUpdate` struct provides detailed information about a
+/// transaction, including its `signature`, `transaction` data, `meta` status,
+/// and the `slot` where it was recorded. Additionally, it includes a `is_vote`
+/// flag to indicate whether the transaction is a voting transaction.
+///
+/// - `signature`: The unique signature of the transaction.
+/// - `transaction`: The complete `VersionedTransaction` data of the
+///   transaction.
+/// - `meta`: Metadata about the transaction's status, such as fee information
+///   and logs.
+/// - `is_vote`: A boolean indicating whether the transaction is a vote.
+/// - `slot`: The slot number in which the transaction was recorded.
+/// - `block_time`: The Unix timestamp of when the transaction was processed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionUpdate {
+    pub signature: Signature,
+    #[serde(with = "versioned_transaction")]
+    pub transaction: solana_sdk::transaction::VersionedTransaction,
+    #[serde(with = "transaction_status_meta")]
+    pub meta: solana_transaction_status::TransactionStatusMeta,
+    pub is_vote: bool,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+}
+
+/// Represents the deletion of a Solana account, containing the account's
+/// public key and slot information.
+///
+/// The `AccountDeletion` struct represents the deletion of an account,
+/// capturing the public key of the deleted account and the slot in which it
+/// was removed.
+///
+/// - `pubkey`: The public key of the deleted account.
+/// - `slot`: The slot number in which the account was deleted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountDeletion {
+    pub pubkey: Pubkey,
+    pub slot: u64,
+}
+
+/// Encodes `VersionedTransaction` in its wire format, as a base64 string.
+///
+/// The `serde` implementation of `VersionedMessage` can only be read back by
+/// binary formats such as `bincode`, so it cannot be used with `JsonCodec`.
+mod versioned_transaction {
+    use {
+        base64::{engine::general_purpose::STANDARD as BASE64, Engine},
+        serde::{de::Error, ser, Deserialize, Deserializer, Serializer},
+        solana_sdk::transaction::VersionedTransaction,
+    };
+
+    pub fn serialize<S: Serializer>(
+        transaction: &VersionedTransaction,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let bytes = bincode::serialize(transaction).map_err(ser::Error::custom)?;
+
+        serializer.serialize_str(&BASE64.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<VersionedTransaction, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        let bytes = BASE64.decode(encoded).map_err(D::Error::custom)?;
+
+        bincode::deserialize(&bytes).map_err(D::Error::custom)
+    }
+}
+
+/// Encodes `TransactionStatusMeta`, which does not implement `serde` traits,
+/// through a mirror of its fields.
+mod transaction_status_meta {
+    use {
+        serde::{Deserialize, Deserializer, Serialize, Serializer},
+        solana_account_decoder::parse_token::UiTokenAmount,
+        solana_sdk::{
+            message::v0::LoadedAddresses, transaction::TransactionError,
+            transaction_context::TransactionReturnData,
+        },
+        solana_transaction_status::{
+            InnerInstructions, Reward, TransactionStatusMeta, TransactionTokenBalance,
+        },
+    };
+
+    #[derive(Serialize, Deserialize)]
+    struct Meta {
+        status: Result<(), TransactionError>,
+        fee: u64,
+        pre_balances: Vec<u64>,
+        post_balances: Vec<u64>,
+        inner_instructions: Option<Vec<InnerInstructions>>,
+        log_messages: Option<Vec<String>>,
+        pre_token_balances: Option<Vec<TokenBalance>>,
+        post_token_balances: Option<Vec<TokenBalance>>,
+        rewards: Option<Vec<Reward>>,
+        loaded_addresses: LoadedAddresses,
+        return_data: Option<TransactionReturnData>,
+        compute_units_consumed: Option<u64>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct TokenBalance {
+        account_index: u8,
+        mint: String,
+        ui_token_amount: UiTokenAmount,
+        owner: String,
+        program_id: String,
+    }
+
+    impl From<TransactionTokenBalance> for TokenBalance {
+        fn from(balance: TransactionTokenBalance) -> Self {
+            Self {
+                account_index: balance.account_index,
+                mint: balance.mint,
+                ui_token_amount: balance.ui_token_amount,
+                owner: balance.owner,
+                program_id: balance.program_id,
+            }
+        }
+    }
+
+    impl From<TokenBalance> for TransactionTokenBalance {
+        fn from(balance: TokenBalance) -> Self {
+            Self {
+                account_index: balance.account_index,
+                mint: balance.mint,
+                ui_token_amount: balance.ui_token_amount,
+                owner: balance.owner,
+                program_id: balance.program_id,
+            }
+        }
+    }
+
+    fn convert<T, U: From<T>>(balances: Option<Vec<T>>) -> Option<Vec<U>> {
+        balances.map(|balances| balances.into_iter().map(U::from).collect())
+    }
+
+    pub fn serialize<S: Serializer>(
+        meta: &TransactionStatusMeta,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let meta = meta.clone();
+
+        Meta {
+            status: meta.status,
+            fee: meta.fee,
+            pre_balances: meta.pre_balances,
+            post_balances: meta.post_balances,
+            inner_instructions: meta.inner_instructions,
+            log_messages: meta.log_messages,
+            pre_token_balances: convert(meta.pre_token_balances),
+            post_token_balances: convert(meta.post_token_balances),
+            rewards: meta.rewards,
+            loaded_addresses: meta.loaded_addresses,
+            return_data: meta.return_data,
+            compute_units_consumed: meta.compute_units_consumed,
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<TransactionStatusMeta, D::Error> {
+        let meta = Meta::deserialize(deserializer)?;
+
+        Ok(TransactionStatusMeta {
+            status: meta.status,
+            fee: meta.fee,
+            pre_balances: meta.pre_balances,
+            post_balances: meta.post_balances,
+            inner_instructions: meta.inner_instructions,
+            log_messages: meta.log_messages,
+            pre_token_balances: convert(meta.pre_token_balances),
+            post_token_balances: convert(meta.post_token_balances),
+            rewards: meta.rewards,
+            loaded_addresses: meta.loaded_addresses,
+            return_data: meta.return_data,
+            compute_units_consumed: meta.compute_units_consumed,
+        })
+    }
+}
@@ -0,0 +1,648 @@
+//! Provides dead letters, which capture updates that a pipe failed to process.
+//!
+//! Pipes registered with `ErrorPolicy::DeadLetter` do not drop the updates
+//! they fail on. Instead, the pipeline wraps each failed update in a
+//! `DeadLetter` and hands it to the configured `DeadLetterSink`, so that it can
+//! be inspected and reprocessed once the cause of the failure is fixed.
+//!
+//! # Overview
+//!
+//! - **`DeadLetter`**: A failed update together with the datasource it came
+//!   from, the pipe that failed, the error and the time of the failure.
+//! - **`DeadLetterSink`**: A trait for receiving dead letters from the
+//!   pipeline.
+//! - **`DeadLetterStore`**: A sink that persists dead letters so that they can
+//!   be replayed with `Pipeline::replay_dead_letters`.
+//! - **`FileDeadLetterStore`**: Stores dead letters as JSON lines in a local
+//!   file.
+//! - **`SqliteDeadLetterStore`**: Stores dead letters in a SQLite database.
+//!   Requires the `sqlite` feature.
+//!
+//! # Key Concepts
+//!
+//! - **Encoding**: Updates are encoded with an `UpdateCodec<Update>` supplied
+//!   when the store is created, such as `codec::JsonCodec`. The same codec
+//!   must be used to read the store back.
+//! - **Replay**: Replaying a dead letter runs its update through the pipe
+//!   that failed on it, and only that pipe. For instruction pipes, only the
+//!   instruction that failed is run again. Dead letters that are processed
+//!   successfully are removed from the store, while those that fail again are
+//!   kept.
+//!
+//! # Notes
+//!
+//! - A dead letter is sent once per failing pipe, and for instruction pipes
+//!   once per failing instruction. An update that fails in two pipes produces
+//!   two dead letters.
+//! - If the sink fails to accept a dead letter, the update is treated as
+//!   failed, so that its checkpoint is not advanced past it.
+
+use {
+    crate::{
+        checkpoint::write_durably,
+        codec::UpdateCodec,
+        datasource::{DatasourceId, Update},
+        error::{CarbonResult, Error},
+    },
+    async_trait::async_trait,
+    base64::{engine::general_purpose::STANDARD as BASE64, Engine},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::BTreeMap,
+        path::PathBuf,
+        sync::Arc,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+    tokio::{
+        io::AsyncWriteExt,
+        sync::{Mutex, MutexGuard},
+    },
+};
+
+/// An update that a pipe failed to process.
+///
+/// # Fields
+///
+/// - `update`: The update that failed.
+/// - `datasource_id`: The id of the datasource that produced the update.
+/// - `pipe`: The pipe that failed to process the update.
+/// - `instruction`: The position of the instruction that failed within the
+///   transaction, if the pipe is an instruction pipe.
+/// - `error`: The error returned by the pipe.
+/// - `failed_at`: The time at which the pipe gave up on the update.
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub update: Update,
+    pub datasource_id: DatasourceId,
+    pub pipe: String,
+    pub instruction: Option<usize>,
+    pub error: String,
+    pub failed_at: SystemTime,
+}
+
+/// A trait for receiving dead letters from the pipeline.
+///
+/// # Example
+///
+/// ```rust
+/// struct LogDeadLetterSink;
+///
+/// #[async_trait]
+/// impl DeadLetterSink for LogDeadLetterSink {
+///     async fn send(&self, dead_letter: DeadLetter) -> CarbonResult<()> {
+///         log::error!("dead letter from pipe {}: {}", dead_letter.pipe, dead_letter.error);
+///         Ok(())
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait DeadLetterSink: Send + Sync {
+    /// Accepts a dead letter.
+    async fn send(&self, dead_letter: DeadLetter) -> CarbonResult<()>;
+}
+
+/// A dead letter read back from a `DeadLetterStore`.
+///
+/// # Fields
+///
+/// - `id`: The id of the dead letter within its store.
+/// - `dead_letter`: The dead letter itself.
+#[derive(Debug, Clone)]
+pub struct StoredDeadLetter {
+    pub id: u64,
+    pub dead_letter: DeadLetter,
+}
+
+/// A `DeadLetterSink` that persists dead letters for later replay.
+///
+/// # Example
+///
+/// ```rust
+/// let store = Arc::new(FileDeadLetterStore::new("dead_letters.jsonl", Arc::new(JsonCodec)));
+///
+/// let mut pipeline = Pipeline::builder()
+///     .dead_letter_sink(store.clone())
+///     .error_policy(ErrorPolicy::DeadLetter)
+///     .account(TokenProgramDecoder, TokenAccountProcessor)
+///     .build()?;
+///
+/// // After fixing the processor:
+/// pipeline.replay_dead_letters(store.as_ref()).await?;
+/// ```
+#[async_trait]
+pub trait DeadLetterStore: DeadLetterSink {
+    /// Loads every dead letter in the store, oldest first.
+    async fn load(&self) -> CarbonResult<Vec<StoredDeadLetter>>;
+
+    /// Removes the dead letter with the given id.
+    async fn remove(&self, id: u64) -> CarbonResult<()>;
+}
+
+/// A `DeadLetterStore` that keeps dead letters as JSON lines in a local file.
+///
+/// New dead letters are appended to the file and synced to disk. Removing a
+/// dead letter rewrites the file through a temporary file and a rename, in the
+/// same way as `FileCheckpointer`.
+///
+/// Ids are never reused. The next id is kept in a companion file, named after
+/// the store's file with a `.next_id` suffix, which is written before the
+/// store's file is rewritten, so that removing the newest dead letters does
+/// not hand their ids out again.
+pub struct FileDeadLetterStore {
+    path: PathBuf,
+    codec: Arc<dyn UpdateCodec<Update>>,
+    records: Mutex<Option<FileRecords>>,
+}
+
+/// The dead letters of a `FileDeadLetterStore`, loaded from its file.
+#[derive(Default)]
+struct FileRecords {
+    next_id: u64,
+    records: BTreeMap<u64, DeadLetterRecord>,
+}
+
+impl FileDeadLetterStore {
+    /// Creates a `FileDeadLetterStore` keeping dead letters in the file at
+    /// `path`, encoding updates with `codec`.
+    ///
+    /// The file is created when the first dead letter is stored.
+    pub fn new(path: impl Into<PathBuf>, codec: Arc<dyn UpdateCodec<Update>>) -> Self {
+        Self {
+            path: path.into(),
+            codec,
+            records: Mutex::new(None),
+        }
+    }
+
+    fn next_id_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".next_id");
+        path.into()
+    }
+
+    async fn records(&self) -> CarbonResult<MutexGuard<'_, Option<FileRecords>>> {
+        let mut records = self.records.lock().await;
+
+        if records.is_none() {
+            let contents = match tokio::fs::read_to_string(&self.path).await {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                Err(e) => return Err(Error::transient("failed to read dead-letter file", e)),
+            };
+
+            let loaded: BTreeMap<u64, DeadLetterRecord> = contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| {
+                    serde_json::from_str::<DeadLetterRecord>(line)
+                        .map(|record| (record.id, record))
+                        .map_err(|e| Error::fatal("failed to parse dead letter", e))
+                })
+                .collect::<CarbonResult<_>>()?;
+
+            let stored_next_id = match tokio::fs::read_to_string(self.next_id_path()).await {
+                Ok(contents) => contents
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|e| Error::fatal("failed to parse dead-letter id", e))?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+                Err(e) => return Err(Error::transient("failed to read dead-letter id", e)),
+            };
+
+            let next_id = loaded
+                .last_key_value()
+                .map_or(0, |(id, _)| id + 1)
+                .max(stored_next_id);
+
+            *records = Some(FileRecords {
+                next_id,
+                records: loaded,
+            });
+        }
+
+        Ok(records)
+    }
+}
+
+#[async_trait]
+impl DeadLetterSink for FileDeadLetterStore {
+    async fn send(&self, dead_letter: DeadLetter) -> CarbonResult<()> {
+        let mut records = self.records().await?;
+        let records = records.get_or_insert_with(FileRecords::default);
+        let id = records.next_id;
+        let record = DeadLetterRecord::encode(id, &dead_letter, self.codec.as_ref())?;
+
+        let mut line = serde_json::to_string(&record)
+            .map_err(|e| Error::fatal("failed to encode dead letter", e))?;
+        line.push('\n');
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| Error::transient("failed to open dead-letter file", e))?;
+        file.write_all(line.as_bytes())
+            .await
+            .map_err(|e| Error::transient("failed to write dead-letter file", e))?;
+        file.sync_data()
+            .await
+            .map_err(|e| Error::transient("failed to write dead-letter file", e))?;
+
+        records.next_id = id + 1;
+        records.records.insert(id, record);
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeadLetterStore for FileDeadLetterStore {
+    async fn load(&self) -> CarbonResult<Vec<StoredDeadLetter>> {
+        let records = self.records().await?;
+
+        records
+            .iter()
+            .flat_map(|records| records.records.values())
+            .map(|record| record.decode(self.codec.as_ref()))
+            .collect()
+    }
+
+    async fn remove(&self, id: u64) -> CarbonResult<()> {
+        let mut records = self.records().await?;
+        let records = records.get_or_insert_with(FileRecords::default);
+        if records.records.remove(&id).is_none() {
+            return Ok(());
+        }
+
+        write_durably(&self.next_id_path(), records.next_id.to_string().as_bytes())
+            .await
+            .map_err(|e| Error::transient("failed to write dead-letter id", e))?;
+
+        let mut contents = String::new();
+        for record in records.records.values() {
+            contents.push_str(
+                &serde_json::to_string(record)
+                    .map_err(|e| Error::fatal("failed to encode dead letter", e))?,
+            );
+            contents.push('\n');
+        }
+
+        write_durably(&self.path, contents.as_bytes())
+            .await
+            .map_err(|e| Error::transient("failed to write dead-letter file", e))
+    }
+}
+
+/// A `DeadLetterStore` that keeps dead letters in a SQLite database.
+///
+/// Dead letters are kept in a `carbon_dead_letters` table, which is created
+/// when the database is opened. Queries run on tokio's blocking thread pool,
+/// while updates are encoded and decoded on the calling task.
+#[cfg(feature = "sqlite")]
+pub struct SqliteDeadLetterStore {
+    connection: Arc<std::sync::Mutex<rusqlite::Connection>>,
+    codec: Arc<dyn UpdateCodec<Update>>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteDeadLetterStore {
+    /// Opens or creates the SQLite database at `path`, encoding updates with
+    /// `codec`.
+    pub fn open(
+        path: impl AsRef<std::path::Path>,
+        codec: Arc<dyn UpdateCodec<Update>>,
+    ) -> CarbonResult<Self> {
+        let connection = rusqlite::Connection::open(path)
+            .map_err(|e| Error::transient("failed to open dead-letter database", e))?;
+
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS carbon_dead_letters (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    update_data BLOB NOT NULL,
+                    datasource_id TEXT NOT NULL,
+                    pipe TEXT NOT NULL,
+                    instruction INTEGER,
+                    error TEXT NOT NULL,
+                    failed_at INTEGER NOT NULL
+                )",
+                (),
+            )
+            .map_err(|e| Error::transient("failed to create dead-letter table", e))?;
+
+        Ok(Self {
+            connection: Arc::new(std::sync::Mutex::new(connection)),
+            codec,
+        })
+    }
+
+    /// Runs `query` on the connection in a blocking task.
+    async fn run<T: Send + 'static>(
+        &self,
+        query: impl FnOnce(&rusqlite::Connection) -> CarbonResult<T> + Send + 'static,
+    ) -> CarbonResult<T> {
+        let connection = self.connection.clone();
+
+        tokio::task::spawn_blocking(move || {
+            query(
+                &connection
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            )
+        })
+        .await
+        .map_err(|e| Error::transient("dead-letter database task failed", e))?
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl DeadLetterSink for SqliteDeadLetterStore {
+    async fn send(&self, dead_letter: DeadLetter) -> CarbonResult<()> {
+        let update_data = self.codec.encode(&dead_letter.update)?;
+
+        self.run(move |connection| {
+            connection
+                .execute(
+                    "INSERT INTO carbon_dead_letters
+                    (update_data, datasource_id, pipe, instruction, error, failed_at)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    rusqlite::params![
+                        update_data,
+                        dead_letter.datasource_id.as_str(),
+                        dead_letter.pipe,
+                        dead_letter
+                            .instruction
+                            .map(|instruction| instruction as i64),
+                        dead_letter.error,
+                        to_unix_millis(dead_letter.failed_at) as i64,
+                    ],
+                )
+                .map_err(|e| Error::transient("failed to store dead letter", e))?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl DeadLetterStore for SqliteDeadLetterStore {
+    async fn load(&self) -> CarbonResult<Vec<StoredDeadLetter>> {
+        let rows = self
+            .run(|connection| {
+                let mut statement = connection
+                    .prepare(
+                        "SELECT id, update_data, datasource_id, pipe, instruction, error, failed_at
+                        FROM carbon_dead_letters ORDER BY id",
+                    )
+                    .map_err(|e| Error::transient("failed to load dead letters", e))?;
+
+                let rows = statement
+                    .query_map((), |row| {
+                        Ok((
+                            row.get::<_, i64>(0)? as u64,
+                            row.get::<_, Vec<u8>>(1)?,
+                            row.get::<_, String>(2)?,
+                            row.get::<_, String>(3)?,
+                            row.get::<_, Option<i64>>(4)?,
+                            row.get::<_, String>(5)?,
+                            row.get::<_, i64>(6)? as u64,
+                        ))
+                    })
+                    .map_err(|e| Error::transient("failed to load dead letters", e))?;
+
+                rows.collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| Error::transient("failed to load dead letter", e))
+            })
+            .await?;
+
+        rows.into_iter()
+            .map(
+                |(id, update_data, datasource_id, pipe, instruction, error, failed_at)| {
+                    Ok(StoredDeadLetter {
+                        id,
+                        dead_letter: DeadLetter {
+                            update: self.codec.decode(&update_data)?,
+                            datasource_id: DatasourceId::new(datasource_id),
+                            pipe,
+                            instruction: instruction.map(|instruction| instruction as usize),
+                            error,
+                            failed_at: from_unix_millis(failed_at),
+                        },
+                    })
+                },
+            )
+            .collect()
+    }
+
+    async fn remove(&self, id: u64) -> CarbonResult<()> {
+        self.run(move |connection| {
+            connection
+                .execute("DELETE FROM carbon_dead_letters WHERE id = ?1", [id as i64])
+                .map_err(|e| Error::transient("failed to remove dead letter", e))?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// The stored form of a `DeadLetter`, with the encoded update in base64.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DeadLetterRecord {
+    id: u64,
+    update: String,
+    datasource_id: String,
+    pipe: String,
+    instruction: Option<usize>,
+    error: String,
+    failed_at: u64,
+}
+
+impl DeadLetterRecord {
+    fn encode(
+        id: u64,
+        dead_letter: &DeadLetter,
+        codec: &dyn UpdateCodec<Update>,
+    ) -> CarbonResult<Self> {
+        Ok(Self {
+            id,
+            update: BASE64.encode(codec.encode(&dead_letter.update)?),
+            datasource_id: dead_letter.datasource_id.as_str().to_string(),
+            pipe: dead_letter.pipe.clone(),
+            instruction: dead_letter.instruction,
+            error: dead_letter.error.clone(),
+            failed_at: to_unix_millis(dead_letter.failed_at),
+        })
+    }
+
+    fn decode(&self, codec: &dyn UpdateCodec<Update>) -> CarbonResult<StoredDeadLetter> {
+        let update_data = BASE64
+            .decode(&self.update)
+            .map_err(|e| Error::fatal("invalid dead-letter update", e))?;
+
+        Ok(StoredDeadLetter {
+            id: self.id,
+            dead_letter: DeadLetter {
+                update: codec.decode(&update_data)?,
+                datasource_id: DatasourceId::new(self.datasource_id.clone()),
+                pipe: self.pipe.clone(),
+                instruction: self.instruction,
+                error: self.error.clone(),
+                failed_at: from_unix_millis(self.failed_at),
+            },
+        })
+    }
+}
+
+fn to_unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+fn from_unix_millis(millis: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{codec::JsonCodec, datasource::AccountUpdate},
+        solana_sdk::pubkey::Pubkey,
+    };
+
+    fn temporary_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "carbon-dead-letters-{}-{}.jsonl",
+            std::process::id(),
+            name
+        ))
+    }
+
+    fn remove_store(store: &FileDeadLetterStore) {
+        let _ = std::fs::remove_file(&store.path);
+        let _ = std::fs::remove_file(store.next_id_path());
+    }
+
+    fn dead_letter(slot: u64) -> DeadLetter {
+        DeadLetter {
+            update: Update::Account(AccountUpdate {
+                pubkey: Pubkey::new_unique(),
+                account: Default::default(),
+                slot,
+                write_version: Some(slot * 10),
+            }),
+            datasource_id: DatasourceId::new("rpc"),
+            pipe: "instruction_0".to_string(),
+            instruction: Some(2),
+            error: "boom".to_string(),
+            failed_at: from_unix_millis(1_700_000_000_000 + slot),
+        }
+    }
+
+    fn slots(stored: &[StoredDeadLetter]) -> Vec<(u64, u64)> {
+        stored
+            .iter()
+            .map(|stored| match &stored.dead_letter.update {
+                Update::Account(account_update) => (stored.id, account_update.slot),
+                _ => panic!("unexpected update"),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn file_dead_letters_survive_a_reload() {
+        let path = temporary_path("reload");
+        let store = FileDeadLetterStore::new(&path, Arc::new(JsonCodec));
+        let sent = dead_letter(7);
+        store.send(sent.clone()).await.unwrap();
+
+        let reloaded = FileDeadLetterStore::new(&path, Arc::new(JsonCodec));
+        let stored = reloaded.load().await.unwrap();
+        assert_eq!(stored.len(), 1);
+
+        let loaded = &stored[0].dead_letter;
+        let (Update::Account(sent_update), Update::Account(loaded_update)) =
+            (&sent.update, &loaded.update)
+        else {
+            panic!("unexpected update");
+        };
+        assert_eq!(loaded_update.pubkey, sent_update.pubkey);
+        assert_eq!(loaded_update.write_version, Some(70));
+        assert_eq!(loaded.datasource_id, sent.datasource_id);
+        assert_eq!(loaded.pipe, sent.pipe);
+        assert_eq!(loaded.instruction, Some(2));
+        assert_eq!(loaded.error, sent.error);
+        assert_eq!(loaded.failed_at, sent.failed_at);
+
+        remove_store(&store);
+    }
+
+    #[tokio::test]
+    async fn file_dead_letters_are_removed_by_id() {
+        let path = temporary_path("remove");
+        let store = FileDeadLetterStore::new(&path, Arc::new(JsonCodec));
+        for slot in [1, 2, 3] {
+            store.send(dead_letter(slot)).await.unwrap();
+        }
+
+        store.remove(1).await.unwrap();
+        store.remove(42).await.unwrap();
+        assert_eq!(slots(&store.load().await.unwrap()), vec![(0, 1), (2, 3)]);
+
+        let reloaded = FileDeadLetterStore::new(&path, Arc::new(JsonCodec));
+        assert_eq!(slots(&reloaded.load().await.unwrap()), vec![(0, 1), (2, 3)]);
+
+        remove_store(&store);
+    }
+
+    #[tokio::test]
+    async fn file_dead_letter_ids_are_not_reused() {
+        let path = temporary_path("ids");
+        let store = FileDeadLetterStore::new(&path, Arc::new(JsonCodec));
+        store.send(dead_letter(1)).await.unwrap();
+        store.send(dead_letter(2)).await.unwrap();
+        store.remove(1).await.unwrap();
+
+        let reloaded = FileDeadLetterStore::new(&path, Arc::new(JsonCodec));
+        reloaded.send(dead_letter(3)).await.unwrap();
+        assert_eq!(slots(&reloaded.load().await.unwrap()), vec![(0, 1), (2, 3)]);
+
+        remove_store(&store);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_dead_letters_are_stored_loaded_and_removed() {
+        let path = temporary_path("sqlite").with_extension("db");
+        let store = SqliteDeadLetterStore::open(&path, Arc::new(JsonCodec)).unwrap();
+        for slot in [1, 2, 3] {
+            store.send(dead_letter(slot)).await.unwrap();
+        }
+
+        let stored = store.load().await.unwrap();
+        assert_eq!(slots(&stored), vec![(1, 1), (2, 2), (3, 3)]);
+        assert_eq!(stored[0].dead_letter.pipe, "instruction_0");
+        assert_eq!(stored[0].dead_letter.instruction, Some(2));
+        assert_eq!(stored[0].dead_letter.error, "boom");
+        assert_eq!(
+            stored[0].dead_letter.failed_at,
+            from_unix_millis(1_700_000_000_001)
+        );
+
+        store.remove(2).await.unwrap();
+        store.remove(42).await.unwrap();
+        drop(store);
+
+        let reopened = SqliteDeadLetterStore::open(&path, Arc::new(JsonCodec)).unwrap();
+        reopened.send(dead_letter(4)).await.unwrap();
+        assert_eq!(
+            slots(&reopened.load().await.unwrap()),
+            vec![(1, 1), (3, 3), (4, 4)]
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
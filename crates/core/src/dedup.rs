@@ -0,0 +1,337 @@
+//! Provides deduplication of updates received from several datasources.
+//!
+//! Pipelines that consume the same data from more than one datasource, for
+//! example two RPC providers run for redundancy, receive every update once per
+//! datasource. The `Deduplicator` remembers the updates seen within a bounded
+//! window so that the pipeline can drop the copies before they reach any pipe.
+//!
+//! # Overview
+//!
+//! - **`DedupWindow`**: How long an update is remembered, either as a number of
+//!   slots or as a duration.
+//! - **`Deduplicator`**: Tracks the keys of recently seen updates and reports
+//!   whether an update is a duplicate.
+//!
+//! # Key Concepts
+//!
+//! - **Keys**: Transactions are identified by their signature. Account updates
+//!   are identified by pubkey, slot and write version, so that several writes
+//!   to the same account within a slot are all kept while copies of one write
+//!   are dropped. Without a write version, a hash of the account's lamports,
+//!   owner and data stands in for it. Account deletions are identified by
+//!   pubkey and slot, blocks by slot, and slot status updates by slot and
+//!   status.
+//! - **Bounds**: Keys are forgotten once they fall out of the window, and the
+//!   keys of the lowest slots are forgotten early when `max_entries` keys are
+//!   held.
+//!
+//! # Notes
+//!
+//! - A duplicate that arrives after its key was forgotten is processed again.
+//!   The window should cover the largest expected delay between datasources.
+//! - Account updates from datasources that do not report write versions are
+//!   kept once per account, slot and account state. Two writes within a slot
+//!   that leave the account in the same state are treated as copies.
+
+use {
+    crate::{datasource::Update, slot::SlotStatus},
+    solana_sdk::{pubkey::Pubkey, signature::Signature},
+    std::{
+        collections::{hash_map::DefaultHasher, BTreeMap, HashMap, VecDeque},
+        hash::{Hash, Hasher},
+        time::{Duration, Instant},
+    },
+};
+
+/// The default number of keys a `Deduplicator` holds at most.
+pub const DEFAULT_DEDUP_MAX_ENTRIES: usize = 100_000;
+
+/// How long a `Deduplicator` remembers an update.
+///
+/// # Variants
+///
+/// - `Slots`: Updates are remembered until the highest slot seen is this many
+///   slots past their own slot.
+/// - `Duration`: Updates are remembered for this long after they were first
+///   seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupWindow {
+    Slots(u64),
+    Duration(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum DedupKey {
+    Transaction(Signature),
+    Account(Pubkey, u64, AccountWrite),
+    AccountDeletion(Pubkey, u64),
+    Block(u64),
+    SlotStatus(u64, SlotStatus),
+}
+
+/// Tells apart the writes to an account within a slot: by write version when
+/// the datasource reports one, and by a hash of the written state otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum AccountWrite {
+    Version(u64),
+    StateHash(u64),
+}
+
+/// Drops repeated copies of updates within a bounded window.
+///
+/// # Example
+///
+/// ```rust
+/// let mut deduplicator = Deduplicator::new(DedupWindow::Slots(150), DEFAULT_DEDUP_MAX_ENTRIES);
+///
+/// if !deduplicator.is_duplicate(&update) {
+///     // Process the update
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Deduplicator {
+    window: DedupWindow,
+    max_entries: usize,
+    seen: HashMap<DedupKey, Instant>,
+    by_slot: BTreeMap<u64, Vec<DedupKey>>,
+    by_time: VecDeque<(DedupKey, u64, Instant)>,
+    highest_slot: u64,
+}
+
+impl Deduplicator {
+    /// Creates a `Deduplicator` remembering updates for `window`, holding at
+    /// most `max_entries` keys.
+    pub fn new(window: DedupWindow, max_entries: usize) -> Self {
+        Self {
+            window,
+            max_entries,
+            seen: HashMap::new(),
+            by_slot: BTreeMap::new(),
+            by_time: VecDeque::new(),
+            highest_slot: 0,
+        }
+    }
+
+    /// Records `update` and returns `true` if an identical update was
+    /// already seen within the window.
+    pub fn is_duplicate(&mut self, update: &Update) -> bool {
+        let (key, slot) = match update {
+            Update::Transaction(transaction_update) => (
+                DedupKey::Transaction(transaction_update.signature),
+                transaction_update.slot,
+            ),
+            Update::Account(account_update) => (
+                DedupKey::Account(
+                    account_update.pubkey,
+                    account_update.slot,
+                    match account_update.write_version {
+                        Some(write_version) => AccountWrite::Version(write_version),
+                        None => {
+                            let mut hasher = DefaultHasher::new();
+                            account_update.account.lamports.hash(&mut hasher);
+                            account_update.account.owner.hash(&mut hasher);
+                            account_update.account.data.hash(&mut hasher);
+                            AccountWrite::StateHash(hasher.finish())
+                        }
+                    },
+                ),
+                account_update.slot,
+            ),
+            Update::AccountDeletion(account_deletion) => (
+                DedupKey::AccountDeletion(account_deletion.pubkey, account_deletion.slot),
+                account_deletion.slot,
+            ),
+            Update::Block(block_details) => {
+                (DedupKey::Block(block_details.slot), block_details.slot)
+            }
+            Update::SlotStatus(slot_status) => (
+                DedupKey::SlotStatus(slot_status.slot, slot_status.status),
+                slot_status.slot,
+            ),
+        };
+
+        let now = Instant::now();
+        self.highest_slot = self.highest_slot.max(slot);
+        self.evict_expired(now);
+
+        if self.seen.contains_key(&key) {
+            return true;
+        }
+
+        while self.seen.len() >= self.max_entries && self.evict_lowest_slot() {}
+
+        self.seen.insert(key.clone(), now);
+        self.by_slot.entry(slot).or_default().push(key.clone());
+        if let DedupWindow::Duration(_) = self.window {
+            self.by_time.push_back((key, slot, now));
+        }
+
+        false
+    }
+
+    /// Returns the number of keys currently held.
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// Returns `true` if no keys are held.
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    /// Forgets the keys that fell out of the window.
+    fn evict_expired(&mut self, now: Instant) {
+        match self.window {
+            DedupWindow::Slots(slots) => {
+                while let Some(entry) = self.by_slot.first_entry() {
+                    if entry.key().saturating_add(slots) >= self.highest_slot {
+                        break;
+                    }
+
+                    for key in entry.remove() {
+                        self.seen.remove(&key);
+                    }
+                }
+            }
+            DedupWindow::Duration(duration) => {
+                while let Some((_, _, seen_at)) = self.by_time.front() {
+                    if now.duration_since(*seen_at) <= duration {
+                        break;
+                    }
+
+                    let Some((key, slot, seen_at)) = self.by_time.pop_front() else {
+                        break;
+                    };
+
+                    // Keys forgotten early to respect `max_entries` may have
+                    // been seen again since.
+                    if self.seen.get(&key) != Some(&seen_at) {
+                        continue;
+                    }
+
+                    self.seen.remove(&key);
+                    if let Some(keys) = self.by_slot.get_mut(&slot) {
+                        keys.retain(|slot_key| slot_key != &key);
+                        if keys.is_empty() {
+                            self.by_slot.remove(&slot);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Forgets a key of the lowest slot held. Returns `false` if no keys are
+    /// held.
+    fn evict_lowest_slot(&mut self) -> bool {
+        let Some(mut entry) = self.by_slot.first_entry() else {
+            return false;
+        };
+
+        if let Some(key) = entry.get_mut().pop() {
+            self.seen.remove(&key);
+        }
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::datasource::{AccountDeletion, AccountUpdate},
+    };
+
+    fn account_update(pubkey: Pubkey, slot: u64, write_version: Option<u64>) -> Update {
+        Update::Account(AccountUpdate {
+            pubkey,
+            account: solana_sdk::account::Account::default(),
+            slot,
+            write_version,
+        })
+    }
+
+    fn account_state(pubkey: Pubkey, lamports: u64, data: Vec<u8>) -> Update {
+        Update::Account(AccountUpdate {
+            pubkey,
+            account: solana_sdk::account::Account {
+                lamports,
+                data,
+                ..Default::default()
+            },
+            slot: 1,
+            write_version: None,
+        })
+    }
+
+    fn account_deletion(slot: u64) -> Update {
+        Update::AccountDeletion(AccountDeletion {
+            pubkey: Pubkey::default(),
+            slot,
+        })
+    }
+
+    #[test]
+    fn drops_copies_of_an_account_write() {
+        let mut deduplicator = Deduplicator::new(DedupWindow::Slots(10), 100);
+        let pubkey = Pubkey::new_unique();
+
+        assert!(!deduplicator.is_duplicate(&account_update(pubkey, 1, Some(1))));
+        assert!(deduplicator.is_duplicate(&account_update(pubkey, 1, Some(1))));
+        assert!(!deduplicator.is_duplicate(&account_update(pubkey, 1, Some(2))));
+        assert!(!deduplicator.is_duplicate(&account_update(pubkey, 2, Some(1))));
+        assert!(!deduplicator.is_duplicate(&account_update(Pubkey::new_unique(), 1, Some(1))));
+    }
+
+    #[test]
+    fn tells_writes_apart_by_state_without_a_write_version() {
+        let mut deduplicator = Deduplicator::new(DedupWindow::Slots(10), 100);
+        let pubkey = Pubkey::new_unique();
+
+        assert!(!deduplicator.is_duplicate(&account_state(pubkey, 1, vec![1])));
+        assert!(deduplicator.is_duplicate(&account_state(pubkey, 1, vec![1])));
+        assert!(!deduplicator.is_duplicate(&account_state(pubkey, 2, vec![1])));
+        assert!(!deduplicator.is_duplicate(&account_state(pubkey, 1, vec![2])));
+    }
+
+    #[test]
+    fn forgets_keys_outside_the_slot_window() {
+        let mut deduplicator = Deduplicator::new(DedupWindow::Slots(10), 100);
+
+        assert!(!deduplicator.is_duplicate(&account_deletion(1)));
+        assert!(!deduplicator.is_duplicate(&account_deletion(11)));
+        assert!(deduplicator.is_duplicate(&account_deletion(1)));
+
+        assert!(!deduplicator.is_duplicate(&account_deletion(12)));
+        assert_eq!(deduplicator.len(), 2);
+        assert!(!deduplicator.is_duplicate(&account_deletion(1)));
+    }
+
+    #[test]
+    fn forgets_keys_outside_the_duration_window() {
+        let mut deduplicator = Deduplicator::new(DedupWindow::Duration(Duration::ZERO), 100);
+
+        assert!(!deduplicator.is_duplicate(&account_deletion(1)));
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(!deduplicator.is_duplicate(&account_deletion(1)));
+        assert_eq!(deduplicator.len(), 1);
+    }
+
+    #[test]
+    fn evicts_the_lowest_slot_when_full() {
+        let mut deduplicator = Deduplicator::new(DedupWindow::Slots(100), 2);
+
+        assert!(!deduplicator.is_duplicate(&account_deletion(5)));
+        assert!(!deduplicator.is_duplicate(&account_deletion(3)));
+        assert!(!deduplicator.is_duplicate(&account_deletion(4)));
+
+        assert_eq!(deduplicator.len(), 2);
+        assert!(deduplicator.is_duplicate(&account_deletion(5)));
+        assert!(deduplicator.is_duplicate(&account_deletion(4)));
+        assert!(!deduplicator.is_duplicate(&account_deletion(3)));
+    }
+}
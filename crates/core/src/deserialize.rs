@@ -10,6 +10,16 @@
 //!
 //! - **`CarbonDeserialize`**: A trait for custom deserialization of data
 //!   structures from byte slices.
+//! - **`DecodeError`**: Why and where in the data a `try_deserialize` call
+//!   failed.
+//! - **`Discriminator`**: A discriminator held inline, as reported by a
+//!   `DecodeError` when the data belongs to another type.
+//! - **`deserialize_with_discriminator`**: Checks a discriminator and
+//!   deserializes the data following it, as done by the `CarbonDeserialize`
+//!   derive.
+//! - **`set_allow_trailing_bytes`**: Lets every type tolerate bytes left over
+//!   after decoding, such as reserved padding or fields added by later program
+//!   upgrades.
 //! - **`extract_discriminator`**: A function that separates a discriminator
 //!   from the rest of a byte slice, used for parsing data with prefixed
 //!   discriminators.
@@ -21,6 +31,13 @@
 //!
 //! - The `CarbonDeserialize` trait requires implementers to also implement
 //!   `borsh::BorshDeserialize`.
+//! - `CarbonDeserialize::deserialize` is a wrapper around `try_deserialize`
+//!   that discards the `DecodeError`.
+//! - Trailing bytes are an error unless the type is marked with
+//!   `#[carbon(allow_trailing)]`, or has a discriminator and
+//!   `set_allow_trailing_bytes(true)` has been called.
+//!   `try_deserialize_with_trailing` then reports how many bytes were left
+//!   unconsumed.
 //! - Ensure that `extract_discriminator` is used with data slices large enough
 //!   to avoid runtime errors.
 //! - Implement `ArrangeAccounts` when you need to access account metadata for
@@ -29,7 +46,13 @@
 use std::{
     io::{Error, ErrorKind, Read, Result},
     ops::Deref,
+    sync::atomic::{AtomicBool, Ordering},
 };
+
+/// Whether types with a discriminator tolerate trailing bytes, as set by
+/// `set_allow_trailing_bytes`.
+static ALLOW_TRAILING_BYTES: AtomicBool = AtomicBool::new(false);
+
 /// A trait for custom deserialization of types from byte slices.
 ///
 /// The `CarbonDeserialize` trait provides a method for deserializing instances
@@ -44,11 +67,207 @@ use std::{
 ///   which is useful for processing raw blockchain data.
 /// - Ensure the data slice passed to `deserialize` is valid and of appropriate
 ///   length to avoid errors.
+/// - Use `try_deserialize` to find out why the data could not be decoded, for
+///   example to tell a discriminator mismatch from a layout error.
+/// - By default, `try_deserialize` decodes the whole of `data` as Borsh,
+///   without a discriminator. Implementations written by hand that override
+///   `deserialize` should override `try_deserialize` as well.
 pub trait CarbonDeserialize
 where
     Self: Sized + crate::borsh::BorshDeserialize,
 {
-    fn deserialize(data: &[u8]) -> Option<Self>;
+    fn try_deserialize(data: &[u8]) -> std::result::Result<Self, DecodeError> {
+        deserialize_with_discriminator(&[], data, false).map(|(value, _)| value)
+    }
+
+    /// Decodes `data` like `try_deserialize`, also returning the number of
+    /// trailing bytes that were left unconsumed.
+    ///
+    /// The count is always zero unless trailing bytes are allowed for the
+    /// type, as they are an error otherwise.
+    fn try_deserialize_with_trailing(
+        data: &[u8],
+    ) -> std::result::Result<(Self, usize), DecodeError> {
+        Self::try_deserialize(data).map(|value| (value, 0))
+    }
+
+    fn deserialize(data: &[u8]) -> Option<Self> {
+        Self::try_deserialize(data).ok()
+    }
+}
+
+/// Describes why `CarbonDeserialize::try_deserialize` failed.
+///
+/// # Fields
+///
+/// - `offset`: The position in the data, discriminator included, that
+///   decoding had reached when it failed. For layout errors caused by data
+///   that ends too early, this is the length of the data.
+/// - `reason`: Why decoding failed.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{reason} at offset {offset}")]
+pub struct DecodeError {
+    pub offset: usize,
+    pub reason: DecodeErrorReason,
+}
+
+/// A discriminator held inline, so that reporting a mismatch does not
+/// allocate.
+///
+/// Discriminators longer than `Discriminator::MAX_LEN` bytes are cut to their
+/// first `MAX_LEN` bytes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Discriminator {
+    bytes: [u8; Discriminator::MAX_LEN],
+    len: u8,
+}
+
+impl Discriminator {
+    /// The length of the longest discriminator held in full, that of Anchor
+    /// discriminators.
+    pub const MAX_LEN: usize = 8;
+
+    /// Copies up to `MAX_LEN` bytes of `discriminator`.
+    pub fn new(discriminator: &[u8]) -> Self {
+        let len = discriminator.len().min(Self::MAX_LEN);
+        let mut bytes = [0; Self::MAX_LEN];
+        bytes[..len].copy_from_slice(&discriminator[..len]);
+
+        Self {
+            bytes,
+            len: len as u8,
+        }
+    }
+
+    /// Returns the bytes of the discriminator.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl std::fmt::Debug for Discriminator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_slice().fmt(f)
+    }
+}
+
+/// The reason a `DecodeError` was raised.
+///
+/// # Variants
+///
+/// - `TooShort`: The data is shorter than the discriminator.
+/// - `DiscriminatorMismatch`: The data starts with another discriminator,
+///   usually because it belongs to another type. Both discriminators are held
+///   inline, so the error does not allocate.
+/// - `InvalidLayout`: The data following the discriminator does not match the
+///   Borsh layout of the type.
+/// - `TrailingBytes`: The type was decoded, but bytes were left over.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DecodeErrorReason {
+    #[error("data is shorter than the {expected}-byte discriminator")]
+    TooShort { expected: usize },
+    #[error("discriminator mismatch, expected {expected:?}, found {found:?}")]
+    DiscriminatorMismatch {
+        expected: Discriminator,
+        found: Discriminator,
+    },
+    #[error("invalid layout ({0})")]
+    InvalidLayout(String),
+    #[error("{remaining} trailing bytes")]
+    TrailingBytes { remaining: usize },
+}
+
+/// Sets whether every `CarbonDeserialize` type derived in the process with a
+/// discriminator tolerates trailing bytes, as if marked with
+/// `#[carbon(allow_trailing)]`.
+///
+/// This is useful when decoding accounts with reserved padding, or
+/// instructions extended by program upgrades the decoders do not know about
+/// yet. Trailing bytes are not allowed by default.
+///
+/// Types without a discriminator are left out: with nothing else telling them
+/// apart, allowing trailing bytes would let them decode any data that merely
+/// starts with a valid value. Mark them with `#[carbon(allow_trailing)]` to
+/// allow trailing bytes anyway.
+///
+/// # Example
+///
+/// ```rust
+/// carbon_core::deserialize::set_allow_trailing_bytes(true);
+/// ```
+pub fn set_allow_trailing_bytes(allow: bool) {
+    ALLOW_TRAILING_BYTES.store(allow, Ordering::Relaxed);
+}
+
+/// Returns whether trailing bytes have been allowed for every type with a
+/// discriminator with `set_allow_trailing_bytes`.
+pub fn allow_trailing_bytes() -> bool {
+    ALLOW_TRAILING_BYTES.load(Ordering::Relaxed)
+}
+
+/// Checks that `data` starts with `discriminator` and deserializes the rest
+/// of it as a `T`.
+///
+/// This is the decoding logic generated by the `CarbonDeserialize` derive.
+///
+/// # Parameters
+///
+/// - `discriminator`: The expected discriminator. It may be empty.
+/// - `data`: The data to decode, discriminator included.
+/// - `allow_trailing`: Whether bytes may be left over once `T` has been
+///   decoded. They are also allowed when `discriminator` is not empty and
+///   `set_allow_trailing_bytes(true)` has been called.
+///
+/// # Returns
+///
+/// The decoded `T` and the number of trailing bytes left unconsumed.
+///
+/// # Errors
+///
+/// Returns a `DecodeError` if the discriminator does not match, if the rest of
+/// the data does not match the layout of `T`, or if bytes are left over once
+/// `T` has been decoded and trailing bytes are not allowed.
+pub fn deserialize_with_discriminator<T: crate::borsh::BorshDeserialize>(
+    discriminator: &[u8],
+    data: &[u8],
+    allow_trailing: bool,
+) -> std::result::Result<(T, usize), DecodeError> {
+    if data.len() < discriminator.len() {
+        return Err(DecodeError {
+            offset: 0,
+            reason: DecodeErrorReason::TooShort {
+                expected: discriminator.len(),
+            },
+        });
+    }
+
+    let (found, mut rest) = data.split_at(discriminator.len());
+    if found != discriminator {
+        return Err(DecodeError {
+            offset: 0,
+            reason: DecodeErrorReason::DiscriminatorMismatch {
+                expected: Discriminator::new(discriminator),
+                found: Discriminator::new(found),
+            },
+        });
+    }
+
+    let value = T::deserialize(&mut rest).map_err(|e| DecodeError {
+        offset: data.len() - rest.len(),
+        reason: DecodeErrorReason::InvalidLayout(e.to_string()),
+    })?;
+
+    let allow_trailing = allow_trailing || (!discriminator.is_empty() && allow_trailing_bytes());
+    if !rest.is_empty() && !allow_trailing {
+        return Err(DecodeError {
+            offset: data.len() - rest.len(),
+            reason: DecodeErrorReason::TrailingBytes {
+                remaining: rest.len(),
+            },
+        });
+    }
+
+    Ok((value, rest.len()))
 }
 
 /// Extracts a discriminator from the beginning of a byte slice and returns the
@@ -156,3 +375,85 @@ impl crate::borsh::BorshDeserialize for PrefixString {
         })?))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_after_discriminator() {
+        assert_eq!(
+            deserialize_with_discriminator::<u16>(&[1, 2], &[1, 2, 5, 0], false),
+            Ok((5, 0))
+        );
+        assert_eq!(
+            deserialize_with_discriminator::<u16>(&[1, 2], &[1], false),
+            Err(DecodeError {
+                offset: 0,
+                reason: DecodeErrorReason::TooShort { expected: 2 },
+            })
+        );
+        assert_eq!(
+            deserialize_with_discriminator::<u16>(&[1, 2], &[1, 3, 5, 0], false),
+            Err(DecodeError {
+                offset: 0,
+                reason: DecodeErrorReason::DiscriminatorMismatch {
+                    expected: Discriminator::new(&[1, 2]),
+                    found: Discriminator::new(&[1, 3]),
+                },
+            })
+        );
+        assert!(matches!(
+            deserialize_with_discriminator::<u16>(&[1, 2], &[1, 2, 5], false),
+            Err(DecodeError {
+                offset: 3,
+                reason: DecodeErrorReason::InvalidLayout(_),
+            })
+        ));
+    }
+
+    #[test]
+    fn discriminators_are_held_inline() {
+        let discriminator = Discriminator::new(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        assert_eq!(discriminator.as_slice(), &[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(format!("{:?}", Discriminator::new(&[1, 2])), "[1, 2]");
+    }
+
+    #[test]
+    fn allows_trailing_bytes() {
+        assert_eq!(
+            deserialize_with_discriminator::<u16>(&[1], &[1, 5, 0, 9, 9], true),
+            Ok((5, 2))
+        );
+        assert_eq!(
+            deserialize_with_discriminator::<u16>(&[], &[5, 0, 9], true),
+            Ok((5, 1))
+        );
+    }
+
+    #[test]
+    fn allows_trailing_bytes_globally_only_with_a_discriminator() {
+        assert_eq!(
+            deserialize_with_discriminator::<u16>(&[1], &[1, 5, 0, 9], false),
+            Err(DecodeError {
+                offset: 3,
+                reason: DecodeErrorReason::TrailingBytes { remaining: 1 },
+            })
+        );
+
+        set_allow_trailing_bytes(true);
+        let with_discriminator = deserialize_with_discriminator::<u16>(&[1], &[1, 5, 0, 9], false);
+        let without_discriminator = deserialize_with_discriminator::<u16>(&[], &[5, 0, 9], false);
+        set_allow_trailing_bytes(false);
+
+        assert_eq!(with_discriminator, Ok((5, 1)));
+        assert_eq!(
+            without_discriminator,
+            Err(DecodeError {
+                offset: 2,
+                reason: DecodeErrorReason::TrailingBytes { remaining: 1 },
+            })
+        );
+    }
+}
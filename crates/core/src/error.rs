@@ -11,6 +11,8 @@
 //! - **`Error`**: An enum representing specific error cases, from missing data
 //!   in transactions to issues with data sources. Each variant provides a
 //!   descriptive error message.
+//! - **`ErrorContext`**: The update an error happened on, such as its slot,
+//!   signature or account, and the pipe and datasource involved.
 //! - **`CarbonResult`**: A type alias for `Result<T, Error>`, where `T` is the
 //!   successful return type.
 //!
@@ -19,14 +21,53 @@
 //! update types, missing transaction components, and custom errors for more
 //! flexible error management.
 //!
+//! # Key Concepts
+//!
+//! - **Sources**: Errors built with `Error::transient` or `Error::fatal` keep
+//!   the underlying error as their `source`, so the whole chain stays
+//!   available to callers and loggers.
+//! - **Context**: The `with_*` methods attach an `ErrorContext` to any error.
+//!   The pipeline adds the context of the update and pipe to every pipe
+//!   failure before applying its `ErrorPolicy`.
+//! - **Retryability**: `Error::is_retryable` tells transient failures, which
+//!   may succeed when tried again, from fatal ones. The retries of
+//!   `ErrorPolicy::Retry`, `RetryLayer` and `RestartPolicy` only apply to
+//!   retryable errors.
+//!
+//! # Example
+//!
+//! ```rust
+//! fn load(path: &Path) -> CarbonResult<Vec<u8>> {
+//!     std::fs::read(path).map_err(|e| Error::transient("failed to read snapshot", e))
+//! }
+//!
+//! let error = load(path).unwrap_err().with_slot(42);
+//! if error.is_retryable() {
+//!     // try again later
+//! }
+//! ```
+//!
 //! # Notes
 //!
 //! - Implementing `thiserror::Error` provides automatic derivation of error
 //!   display messages.
 //! - Each error variant corresponds to a unique error scenario within the
 //!   `carbon-core` framework.
+//! - `Error::Custom` carries no classification and is treated as retryable,
+//!   so that a configured retry policy applies to it as it did before errors
+//!   were classified. Use `Error::fatal` for errors that will fail again when
+//!   tried again.
+
+use {
+    crate::{datasource::DatasourceId, deserialize::DecodeError},
+    solana_sdk::{pubkey::Pubkey, signature::Signature},
+    std::fmt,
+    thiserror::Error,
+};
 
-use thiserror::Error;
+/// A boxed error that can be sent across threads, used as the `source` of
+/// an `Error`.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -42,8 +83,209 @@ pub enum Error {
     MissingInstructionData,
     #[error("Failed to consume datasource ({0})")]
     FailedToConsumeDatasource(String),
+    #[error("Datasource stalled ({0})")]
+    DatasourceStalled(String),
+    #[error("Update channel closed")]
+    UpdateChannelClosed,
+    #[error("Pipeline halted ({0})")]
+    PipelineHalted(String),
+    #[error("Timed out ({0})")]
+    Timeout(String),
     #[error("Custom error: {0}")]
     Custom(String),
+    #[error("Failed to decode ({0})")]
+    Decode(#[from] DecodeError),
+    #[error("{message}: {source}")]
+    Source {
+        message: String,
+        #[source]
+        source: BoxError,
+        retryable: bool,
+    },
+    #[error("{source} ({context})")]
+    WithContext {
+        context: Box<ErrorContext>,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Creates an error caused by `source` that may succeed when tried again,
+    /// such as a failed connection or a timed out request.
+    pub fn transient(message: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        Error::Source {
+            message: message.into(),
+            source: source.into(),
+            retryable: true,
+        }
+    }
+
+    /// Creates an error caused by `source` that will fail again if tried
+    /// again, such as malformed data.
+    pub fn fatal(message: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        Error::Source {
+            message: message.into(),
+            source: source.into(),
+            retryable: false,
+        }
+    }
+
+    /// Returns whether the operation that failed may succeed when tried
+    /// again.
+    ///
+    /// Failures to reach a datasource, stalls, timeouts and `Error::Custom`
+    /// are retryable, while missing transaction data, decoding errors, a
+    /// closed update channel and a halted pipeline are not. Errors built with
+    /// `Error::transient` or `Error::fatal` are classified accordingly.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::FailedToReceiveUpdates(_)
+            | Error::FailedToConsumeDatasource(_)
+            | Error::DatasourceStalled(_)
+            | Error::Timeout(_)
+            | Error::Custom(_) => true,
+            Error::MissingFeePayer
+            | Error::MissingInnerInstructions
+            | Error::MissingAccountInTransaction
+            | Error::MissingInstructionData
+            | Error::UpdateChannelClosed
+            | Error::PipelineHalted(_)
+            | Error::Decode(_) => false,
+            Error::Source { retryable, .. } => *retryable,
+            Error::WithContext { source, .. } => source.is_retryable(),
+        }
+    }
+
+    /// Returns the context attached to this error, if any.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Error::WithContext { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Attaches `context` to this error.
+    ///
+    /// Fields already set in the error's context are kept, as they were
+    /// attached closer to the failure.
+    pub fn with_context(self, context: ErrorContext) -> Self {
+        match self {
+            Error::WithContext {
+                context: existing,
+                source,
+            } => Error::WithContext {
+                context: Box::new(existing.merge(context)),
+                source,
+            },
+            error => Error::WithContext {
+                context: Box::new(context),
+                source: Box::new(error),
+            },
+        }
+    }
+
+    /// Attaches the signature of the transaction being processed.
+    pub fn with_signature(self, signature: Signature) -> Self {
+        self.with_context(ErrorContext {
+            signature: Some(signature),
+            ..ErrorContext::default()
+        })
+    }
+
+    /// Attaches the slot of the update being processed.
+    pub fn with_slot(self, slot: u64) -> Self {
+        self.with_context(ErrorContext {
+            slot: Some(slot),
+            ..ErrorContext::default()
+        })
+    }
+
+    /// Attaches the public key of the account being processed.
+    pub fn with_pubkey(self, pubkey: Pubkey) -> Self {
+        self.with_context(ErrorContext {
+            pubkey: Some(pubkey),
+            ..ErrorContext::default()
+        })
+    }
+
+    /// Attaches the name of the pipe that failed.
+    pub fn with_pipe(self, pipe: impl Into<String>) -> Self {
+        self.with_context(ErrorContext {
+            pipe: Some(pipe.into()),
+            ..ErrorContext::default()
+        })
+    }
+
+    /// Attaches the id of the datasource the update came from.
+    pub fn with_datasource(self, datasource: DatasourceId) -> Self {
+        self.with_context(ErrorContext {
+            datasource: Some(datasource),
+            ..ErrorContext::default()
+        })
+    }
+}
+
+/// Describes where an error happened.
+///
+/// Every field is optional, as errors are raised at different points of the
+/// pipeline and only some of them are known at each point.
+///
+/// # Fields
+///
+/// - `signature`: The signature of the transaction being processed.
+/// - `slot`: The slot of the update being processed.
+/// - `pubkey`: The public key of the account being processed.
+/// - `pipe`: The name of the pipe that failed.
+/// - `datasource`: The id of the datasource the update came from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    pub signature: Option<Signature>,
+    pub slot: Option<u64>,
+    pub pubkey: Option<Pubkey>,
+    pub pipe: Option<String>,
+    pub datasource: Option<DatasourceId>,
+}
+
+impl ErrorContext {
+    /// Fills the fields of `self` that are not set from `other`.
+    fn merge(self, other: ErrorContext) -> Self {
+        Self {
+            signature: self.signature.or(other.signature),
+            slot: self.slot.or(other.slot),
+            pubkey: self.pubkey.or(other.pubkey),
+            pipe: self.pipe.or(other.pipe),
+            datasource: self.datasource.or(other.datasource),
+        }
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+        let mut field = |f: &mut fmt::Formatter<'_>, name: &str, value: &dyn fmt::Display| {
+            let result = write!(f, "{separator}{name}: {value}");
+            separator = ", ";
+            result
+        };
+
+        if let Some(pipe) = &self.pipe {
+            field(f, "pipe", pipe)?;
+        }
+        if let Some(datasource) = &self.datasource {
+            field(f, "datasource", datasource)?;
+        }
+        if let Some(slot) = &self.slot {
+            field(f, "slot", slot)?;
+        }
+        if let Some(signature) = &self.signature {
+            field(f, "signature", signature)?;
+        }
+        if let Some(pubkey) = &self.pubkey {
+            field(f, "pubkey", pubkey)?;
+        }
+        Ok(())
+    }
 }
 
 /// A type alias for `Result` with the `Error` type as the error variant.
@@ -20,10 +20,38 @@
 //! and facilitating hierarchical processing.
 
 use {
+    crate::{
+        error::CarbonResult, metrics::MetricsCollection, processor::Processor,
+        transaction::TransactionMetadata,
+    },
+    async_trait::async_trait,
     serde::Deserialize,
     solana_sdk::{instruction::AccountMeta, pubkey::Pubkey},
+    std::{ops::Deref, sync::Arc},
 };
 
+/// Contains metadata about an instruction's context within a transaction.
+///
+/// `InstructionMetadata` includes the metadata of the transaction containing
+/// the instruction and the instruction's stack height, which indicates how
+/// deeply it is nested in cross-program invocations.
+///
+/// # Fields
+///
+/// - `transaction_metadata`: The metadata of the transaction containing the
+///   instruction.
+/// - `stack_height`: The stack height of the instruction, starting at 1 for
+///   the top-level instructions of the transaction.
+#[derive(Debug, Clone)]
+pub struct InstructionMetadata {
+    pub transaction_metadata: TransactionMetadata,
+    pub stack_height: u32,
+}
+
+/// The instructions of a transaction in execution order, each with its
+/// metadata.
+pub type InstructionsWithMetadata =
+    Vec<(InstructionMetadata, solana_sdk::instruction::Instruction)>;
 
 /// A decoded instruction containing program ID, data, and associated accounts.
 ///
@@ -49,95 +77,256 @@ pub struct DecodedInstruction<T> {
     pub accounts: Vec<AccountMeta>,
 }
 
+/// Defines a trait for decoding Solana instructions into structured data
+/// types.
+///
+/// # Associated Types
+///
+/// - `InstructionType`: The data type resulting from decoding the instruction.
+pub trait InstructionDecoder<'a> {
+    type InstructionType;
+
+    fn decode_instruction(
+        &self,
+        instruction: &'a solana_sdk::instruction::Instruction,
+    ) -> Option<DecodedInstruction<Self::InstructionType>>;
+}
+
+/// The input type for the instruction processor.
+///
+/// - `T`: The instruction type, as determined by the decoder.
+pub type InstructionProcessorInputType<T> = (
+    InstructionMetadata,
+    DecodedInstruction<T>,
+    Vec<NestedInstruction>,
+);
+
+/// A processing pipe that decodes and processes the instructions of
+/// transactions.
+///
+/// `InstructionPipe` runs every instruction of a transaction, including inner
+/// instructions, through its decoder and passes the instructions it decodes
+/// to its processor, together with their nested inner instructions.
+///
+/// # Type Parameters
+///
+/// - `T`: The data type of the decoded instructions, as determined by the
+///   decoder.
+///
+/// # Fields
+///
+/// - `decoder`: An `InstructionDecoder` that decodes raw instructions.
+/// - `processor`: A `Processor` that handles the decoded instructions.
+pub struct InstructionPipe<T: Send> {
+    pub decoder:
+        Box<dyn for<'a> InstructionDecoder<'a, InstructionType = T> + Send + Sync + 'static>,
+    pub processor: Box<dyn Processor<InputType = InstructionProcessorInputType<T>> + Send + Sync>,
+}
+
+/// A trait for processing instructions in the pipeline asynchronously.
+///
+/// `InstructionPipes` defines the `run` method for processing a top-level
+/// instruction together with its inner instructions. The `rollback` method is
+/// called when a slot is abandoned by a fork, and the `initialize`, `flush` and
+/// `shutdown` methods at the matching points of the pipeline's lifecycle. All
+/// of them do nothing by default.
+///
+/// # Parameters
+///
+/// - `nested_instruction`: The instruction to process, with its inner
+///   instructions.
+/// - `metrics`: A list of `Metrics` objects for recording and tracking metrics.
+#[async_trait]
+pub trait InstructionPipes<'a>: Send + Sync {
+    async fn run(
+        &mut self,
+        nested_instruction: &NestedInstruction,
+        metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()>;
+
+    async fn rollback(&mut self, slot: u64, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = (slot, metrics);
+        Ok(())
+    }
+
+    async fn initialize(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+
+    async fn flush(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+
+    async fn shutdown(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: Send + 'static> InstructionPipes<'_> for InstructionPipe<T> {
+    async fn run(
+        &mut self,
+        nested_instruction: &NestedInstruction,
+        metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()> {
+        log::trace!(
+            "InstructionPipe::run(nested_instruction: {:?}, metrics)",
+            nested_instruction,
+        );
+
+        let decoded_instruction = {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!("decode").entered();
+
+            self.decoder
+                .decode_instruction(&nested_instruction.instruction)
+        };
+
+        if let Some(decoded_instruction) = decoded_instruction {
+            let process = self.processor.process(
+                (
+                    nested_instruction.metadata.clone(),
+                    decoded_instruction,
+                    nested_instruction.inner_instructions.clone(),
+                ),
+                metrics.clone(),
+            );
+
+            #[cfg(feature = "tracing")]
+            let process = {
+                use tracing::Instrument;
 
+                process.instrument(tracing::info_span!("process"))
+            };
 
-// /// Represents a nested instruction with metadata, including potential inner
-// /// instructions.
-// ///
-// /// The `NestedInstruction` struct allows for recursive instruction handling,
-// /// where each instruction may have associated metadata and a list of nested
-// /// instructions.
-// ///
-// /// # Fields
-// ///
-// /// - `metadata`: The metadata associated with the instruction.
-// /// - `instruction`: The Solana instruction being processed.
-// /// - `inner_instructions`: A vector of `NestedInstruction`, representing any
-// ///   nested instructions.
-// #[derive(Debug, Clone)]
-// pub struct NestedInstruction {
-//     pub instruction: solana_sdk::instruction::Instruction,
-//     pub inner_instructions: Vec<NestedInstruction>,
-// }
-
-// #[derive(Debug)]
-// pub struct NestedInstructions(pub Vec<NestedInstruction>);
-
-// impl NestedInstructions {
-//     pub fn iter(&self) -> std::slice::Iter<NestedInstruction> {
-//         self.0.iter()
-//     }
-// }
-
-// impl Deref for NestedInstructions {
-//     type Target = [NestedInstruction];
-
-//     fn deref(&self) -> &[NestedInstruction] {
-//         &self.0[..]
-//     }
-// }
-
-// /// Nests instructions based on stack height, producing a hierarchy of
-// /// `NestedInstruction`.
-// ///
-// /// This function organizes instructions into a nested structure, enabling
-// /// hierarchical transaction analysis. Instructions are nested according to
-// /// their stack height, forming a tree-like structure.
-// ///
-// /// # Parameters
-// ///
-// /// - `instructions`: A list of tuples containing `InstructionMetadata` and
-// ///   instructions.
-// ///
-// /// # Returns
-// ///
-// /// A vector of `NestedInstruction`, representing the instructions organized by
-// /// stack depth.
-// impl From<Vec<Instruction>> for NestedInstructions {
-//     fn from(instructions: Vec<Instruction>) -> Self {
-//         log::trace!("from(instructions: {:?})", instructions);
-//         let mut result = Vec::<NestedInstruction>::new();
-//         let mut stack = Vec::<(Vec<usize>, usize)>::new();
-
-//         for (instruction) in instructions {
-//             let nested_instruction = NestedInstruction {
-//                 instruction,
-//                 inner_instructions: Vec::new(),
-//             };
-
-//             while let Some((_, parent_stack_height)) = stack.last() {
-//                 if metadata.stack_height as usize > *parent_stack_height {
-//                     break;
-//                 }
-//                 stack.pop();
-//             }
-
-//             if let Some((path_to_parent, _)) = stack.last() {
-//                 let mut current_instructions = &mut result;
-//                 for &index in path_to_parent {
-//                     current_instructions = &mut current_instructions[index].inner_instructions;
-//                 }
-//                 current_instructions.push(nested_instruction);
-//                 let mut new_path = path_to_parent.clone();
-//                 new_path.push(current_instructions.len() - 1);
-//                 stack.push((new_path, metadata.stack_height as usize));
-//             } else {
-//                 result.push(nested_instruction);
-//                 let new_path = vec![result.len() - 1];
-//                 stack.push((new_path, metadata.stack_height as usize));
-//             }
-//         }
-
-//         NestedInstructions(result)
-//     }
-// }
+            process.await?;
+        }
+
+        for inner_instruction in nested_instruction.inner_instructions.iter() {
+            self.run(inner_instruction, metrics.clone()).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn rollback(&mut self, slot: u64, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("InstructionPipe::rollback(slot: {:?}, metrics)", slot);
+
+        self.processor.rollback(slot, metrics).await
+    }
+
+    async fn initialize(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("InstructionPipe::initialize(metrics)");
+
+        self.processor.initialize(metrics).await
+    }
+
+    async fn flush(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("InstructionPipe::flush(metrics)");
+
+        self.processor.flush(metrics).await
+    }
+
+    async fn shutdown(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("InstructionPipe::shutdown(metrics)");
+
+        self.processor.shutdown(metrics).await
+    }
+}
+
+/// Represents a nested instruction with metadata, including potential inner
+/// instructions.
+///
+/// The `NestedInstruction` struct allows for recursive instruction handling,
+/// where each instruction may have associated metadata and a list of nested
+/// instructions.
+///
+/// # Fields
+///
+/// - `metadata`: The metadata associated with the instruction.
+/// - `instruction`: The Solana instruction being processed.
+/// - `inner_instructions`: A vector of `NestedInstruction`, representing any
+///   nested instructions.
+#[derive(Debug, Clone)]
+pub struct NestedInstruction {
+    pub metadata: InstructionMetadata,
+    pub instruction: solana_sdk::instruction::Instruction,
+    pub inner_instructions: Vec<NestedInstruction>,
+}
+
+#[derive(Debug)]
+pub struct NestedInstructions(pub Vec<NestedInstruction>);
+
+impl NestedInstructions {
+    pub fn iter(&self) -> std::slice::Iter<'_, NestedInstruction> {
+        self.0.iter()
+    }
+}
+
+impl Deref for NestedInstructions {
+    type Target = [NestedInstruction];
+
+    fn deref(&self) -> &[NestedInstruction] {
+        &self.0[..]
+    }
+}
+
+/// Nests instructions based on stack height, producing a hierarchy of
+/// `NestedInstruction`.
+///
+/// This function organizes instructions into a nested structure, enabling
+/// hierarchical transaction analysis. Instructions are nested according to
+/// their stack height, forming a tree-like structure.
+///
+/// # Parameters
+///
+/// - `instructions`: A list of tuples containing `InstructionMetadata` and
+///   instructions.
+///
+/// # Returns
+///
+/// A vector of `NestedInstruction`, representing the instructions organized by
+/// stack depth.
+impl From<InstructionsWithMetadata> for NestedInstructions {
+    fn from(instructions: InstructionsWithMetadata) -> Self {
+        log::trace!("from(instructions: {:?})", instructions);
+        let mut result = Vec::<NestedInstruction>::new();
+        let mut stack = Vec::<(Vec<usize>, usize)>::new();
+
+        for (metadata, instruction) in instructions {
+            let stack_height = metadata.stack_height as usize;
+            let nested_instruction = NestedInstruction {
+                metadata,
+                instruction,
+                inner_instructions: Vec::new(),
+            };
+
+            while let Some((_, parent_stack_height)) = stack.last() {
+                if stack_height > *parent_stack_height {
+                    break;
+                }
+                stack.pop();
+            }
+
+            if let Some((path_to_parent, _)) = stack.last() {
+                let mut current_instructions = &mut result;
+                for &index in path_to_parent {
+                    current_instructions = &mut current_instructions[index].inner_instructions;
+                }
+                current_instructions.push(nested_instruction);
+                let mut new_path = path_to_parent.clone();
+                new_path.push(current_instructions.len() - 1);
+                stack.push((new_path, stack_height));
+            } else {
+                result.push(nested_instruction);
+                let new_path = vec![result.len() - 1];
+                stack.push((new_path, stack_height));
+            }
+        }
+
+        NestedInstructions(result)
+    }
+}
@@ -0,0 +1,485 @@
+//! Provides middleware layers that wrap the processing of pipes.
+//!
+//! Cross-cutting concerns such as timeouts, retries or tracing apply
+//! to every processor alike. Instead of wrapping each processor by hand, they
+//! are written once as a `Layer` and registered with
+//! `PipelineBuilder::layer`, which applies them to the pipes registered after
+//! it.
+//!
+//! # Overview
+//!
+//! - **`Layer`**: A middleware that runs around a pipe. It can act before and
+//!   after the pipe runs, change its result, or run it several times.
+//! - **`Next`**: The rest of the chain, made of the remaining layers and the
+//!   pipe itself.
+//! - **`LayerContext`**: Describes the pipe being run.
+//!
+//! # Built-in Layers
+//!
+//! - **`LatencyLayer`**: Records how long each run of a pipe takes.
+//! - **`TimeoutLayer`**: Fails a pipe that takes longer than a given duration.
+//! - **`RetryLayer`**: Retries a failing pipe with exponential backoff.
+//! - **`TracingLayer`**: Runs the pipe inside a `tracing` span. Requires the
+//!   `tracing` feature.
+//!
+//! # Example
+//!
+//! ```rust
+//! struct LogErrors;
+//!
+//! #[async_trait]
+//! impl Layer for LogErrors {
+//!     async fn call(&self, context: &LayerContext<'_>, mut next: Next<'_>) -> CarbonResult<()> {
+//!         let result = next.run().await;
+//!         if let Err(error) = &result {
+//!             log::error!("pipe {} failed: {:?}", context.pipe, error);
+//!         }
+//!         result
+//!     }
+//! }
+//!
+//! let builder = PipelineBuilder::new()
+//!     .layer(LogErrors)
+//!     .layer(TimeoutLayer::new(Duration::from_secs(5)))
+//!     .account(MyAccountDecoder, MyAccountProcessor);
+//! ```
+//!
+//! # Notes
+//!
+//! - Layers run in the order they were registered, the first one being the
+//!   outermost.
+//! - A layer wraps the whole pipe, including decoding, so a pipe whose decoder
+//!   does not match the update still passes through its layers.
+//! - Retries of a `RetryLayer` happen inside the pipe's `ErrorPolicy`, which
+//!   only sees the final result.
+
+use {
+    crate::{
+        error::{CarbonResult, Error},
+        metrics::MetricsCollection,
+    },
+    async_trait::async_trait,
+    std::{future::Future, pin::Pin, sync::Arc, time::Duration},
+};
+
+/// The future returned by a single run of a pipe.
+pub type ProcessFuture<'a> = Pin<Box<dyn Future<Output = CarbonResult<()>> + Send + 'a>>;
+
+/// Describes the pipe a layer is running around.
+///
+/// # Fields
+///
+/// - `pipe`: The name of the pipe, as in `account_0` or `jupiter_swaps`.
+/// - `metrics`: The metrics of the pipeline.
+pub struct LayerContext<'a> {
+    pub pipe: &'a str,
+    pub metrics: &'a Arc<MetricsCollection>,
+}
+
+/// A middleware that runs around the processing of a pipe.
+///
+/// A layer receives the rest of the chain as `Next`. It usually does some
+/// work, runs `next`, and inspects or changes the result. It may also skip
+/// `next` altogether or run it more than once.
+#[async_trait]
+pub trait Layer: Send + Sync {
+    async fn call(&self, context: &LayerContext<'_>, next: Next<'_>) -> CarbonResult<()>;
+}
+
+/// A repeatable run of a pipe, wrapped by layers.
+#[async_trait]
+pub trait ProcessCall: Send {
+    async fn call(&mut self) -> CarbonResult<()>;
+}
+
+/// The rest of a layer chain.
+///
+/// Running `Next` runs the remaining layers and then the pipe. It can be run
+/// several times, each run passing the same data to the pipe again.
+pub struct Next<'a> {
+    layers: &'a [Arc<dyn Layer>],
+    context: &'a LayerContext<'a>,
+    call: &'a mut dyn ProcessCall,
+}
+
+impl<'a> Next<'a> {
+    /// Creates the chain of `layers` around `call`.
+    pub fn new(
+        layers: &'a [Arc<dyn Layer>],
+        context: &'a LayerContext<'a>,
+        call: &'a mut dyn ProcessCall,
+    ) -> Self {
+        Self {
+            layers,
+            context,
+            call,
+        }
+    }
+
+    /// Runs the remaining layers and the pipe.
+    pub async fn run(&mut self) -> CarbonResult<()> {
+        match self.layers.split_first() {
+            Some((layer, layers)) => {
+                let next = Next {
+                    layers,
+                    context: self.context,
+                    call: &mut *self.call,
+                };
+                layer.call(self.context, next).await
+            }
+            None => self.call.call().await,
+        }
+    }
+}
+
+/// Records how long each pipe takes to process an update.
+///
+/// The time is recorded in the `pipe_latency_nanoseconds` histogram, labeled
+/// with the name of the pipe as `pipe`, whether the pipe succeeds or not.
+///
+/// # Notes
+///
+/// - Unlike `pipe_process_time_nanoseconds`, which the pipeline records once
+///   per update, the layer records every run of the rest of the chain. Each
+///   retry of the pipe's `ErrorPolicy` or of an outer `RetryLayer` is
+///   recorded on its own, while layers registered after it are included in
+///   the time.
+#[derive(Debug, Default, Clone)]
+pub struct LatencyLayer;
+
+#[async_trait]
+impl Layer for LatencyLayer {
+    async fn call(&self, context: &LayerContext<'_>, mut next: Next<'_>) -> CarbonResult<()> {
+        let start = std::time::Instant::now();
+        let result = next.run().await;
+
+        context
+            .metrics
+            .histogram_with_labels("pipe_latency_nanoseconds", &[("pipe", context.pipe)])
+            .record(start.elapsed().as_nanos() as f64);
+
+        result
+    }
+}
+
+/// Fails a pipe that takes longer than `timeout` with `Error::Timeout`.
+///
+/// The pipe is cancelled when the timeout elapses, so processors should not
+/// rely on running to completion.
+#[derive(Debug, Clone)]
+pub struct TimeoutLayer {
+    timeout: Duration,
+}
+
+impl TimeoutLayer {
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+#[async_trait]
+impl Layer for TimeoutLayer {
+    async fn call(&self, context: &LayerContext<'_>, mut next: Next<'_>) -> CarbonResult<()> {
+        match tokio::time::timeout(self.timeout, next.run()).await {
+            Ok(result) => result,
+            Err(_) => Err(Error::Timeout(format!(
+                "pipe {} did not finish within {:?}",
+                context.pipe, self.timeout
+            ))),
+        }
+    }
+}
+
+/// Retries a failing pipe with exponential backoff.
+///
+/// Errors that are not retryable, as told by `Error::is_retryable`, are
+/// returned right away.
+///
+/// # Fields
+///
+/// - `max_retries`: How many times the pipe is retried after the first
+///   failure.
+/// - `initial_backoff`: The wait before the first retry. It doubles with
+///   every further retry, up to `max_backoff`.
+/// - `max_backoff`: The longest wait between retries.
+#[derive(Debug, Clone)]
+pub struct RetryLayer {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+#[async_trait]
+impl Layer for RetryLayer {
+    async fn call(&self, context: &LayerContext<'_>, mut next: Next<'_>) -> CarbonResult<()> {
+        let mut attempt = 0;
+
+        loop {
+            match next.run().await {
+                Ok(()) => return Ok(()),
+                Err(error) if attempt < self.max_retries && error.is_retryable() => {
+                    let delay = self
+                        .initial_backoff
+                        .saturating_mul(2u32.saturating_pow(attempt))
+                        .min(self.max_backoff);
+                    log::warn!(
+                        "pipe {} failed on attempt {}, retrying in {:?}: {:?}",
+                        context.pipe,
+                        attempt + 1,
+                        delay,
+                        error
+                    );
+
+                    attempt += 1;
+                    tokio::time::sleep(delay).await;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+/// Runs each pipe inside a `tracing` span named `pipe`, carrying the name of
+/// the pipe.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Default, Clone)]
+pub struct TracingLayer;
+
+#[cfg(feature = "tracing")]
+#[async_trait]
+impl Layer for TracingLayer {
+    async fn call(&self, context: &LayerContext<'_>, mut next: Next<'_>) -> CarbonResult<()> {
+        use tracing::Instrument;
+
+        let span = tracing::info_span!("pipe", pipe = context.pipe);
+        next.run().instrument(span).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::metrics::InMemoryMetrics, std::sync::Mutex};
+
+    /// Records the order in which layers and the pipe run.
+    type Events = Arc<Mutex<Vec<String>>>;
+
+    struct Record(&'static str, Events);
+
+    #[async_trait]
+    impl Layer for Record {
+        async fn call(&self, _context: &LayerContext<'_>, mut next: Next<'_>) -> CarbonResult<()> {
+            self.1.lock().unwrap().push(format!("{} before", self.0));
+            let result = next.run().await;
+            self.1.lock().unwrap().push(format!("{} after", self.0));
+            result
+        }
+    }
+
+    struct Skip;
+
+    #[async_trait]
+    impl Layer for Skip {
+        async fn call(&self, _context: &LayerContext<'_>, _next: Next<'_>) -> CarbonResult<()> {
+            Ok(())
+        }
+    }
+
+    /// A pipe failing with the errors of `failures`, in order, before
+    /// succeeding.
+    struct Pipe {
+        events: Events,
+        failures: Vec<Error>,
+        duration: Duration,
+    }
+
+    impl Pipe {
+        fn new(events: &Events) -> Self {
+            Self {
+                events: events.clone(),
+                failures: Vec::new(),
+                duration: Duration::ZERO,
+            }
+        }
+
+        fn runs(&self) -> usize {
+            self.events
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|event| *event == "pipe")
+                .count()
+        }
+    }
+
+    #[async_trait]
+    impl ProcessCall for Pipe {
+        async fn call(&mut self) -> CarbonResult<()> {
+            self.events.lock().unwrap().push("pipe".to_string());
+            tokio::time::sleep(self.duration).await;
+            if self.failures.is_empty() {
+                Ok(())
+            } else {
+                Err(self.failures.remove(0))
+            }
+        }
+    }
+
+    async fn run(layers: Vec<Arc<dyn Layer>>, pipe: &mut Pipe) -> CarbonResult<()> {
+        let metrics = Arc::new(MetricsCollection::new(vec![]));
+        let context = LayerContext {
+            pipe: "account_0",
+            metrics: &metrics,
+        };
+
+        Next::new(&layers, &context, pipe).run().await
+    }
+
+    fn retry_layer(max_retries: u32) -> Arc<dyn Layer> {
+        Arc::new(RetryLayer {
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+        })
+    }
+
+    fn transient() -> Error {
+        Error::transient("down", std::io::Error::other("down"))
+    }
+
+    #[tokio::test]
+    async fn layers_run_in_registration_order() {
+        let events = Events::default();
+        let mut pipe = Pipe::new(&events);
+
+        run(
+            vec![
+                Arc::new(Record("outer", events.clone())),
+                Arc::new(Record("inner", events.clone())),
+            ],
+            &mut pipe,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "outer before",
+                "inner before",
+                "pipe",
+                "inner after",
+                "outer after"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn an_empty_chain_runs_the_pipe() {
+        let events = Events::default();
+        let mut pipe = Pipe::new(&events);
+
+        run(vec![], &mut pipe).await.unwrap();
+        assert_eq!(pipe.runs(), 1);
+    }
+
+    #[tokio::test]
+    async fn layers_may_skip_the_pipe() {
+        let events = Events::default();
+        let mut pipe = Pipe::new(&events);
+
+        run(
+            vec![Arc::new(Skip), Arc::new(Record("inner", events.clone()))],
+            &mut pipe,
+        )
+        .await
+        .unwrap();
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn retry_layer_reruns_the_rest_of_the_chain() {
+        let events = Events::default();
+        let mut pipe = Pipe::new(&events);
+        pipe.failures = vec![transient(), transient()];
+
+        run(
+            vec![retry_layer(2), Arc::new(Record("inner", events.clone()))],
+            &mut pipe,
+        )
+        .await
+        .unwrap();
+        assert_eq!(pipe.runs(), 3);
+        assert_eq!(events.lock().unwrap().len(), 9);
+    }
+
+    #[tokio::test]
+    async fn retry_layer_gives_up_after_max_retries() {
+        let events = Events::default();
+        let mut pipe = Pipe::new(&events);
+        pipe.failures = vec![transient(), transient(), transient()];
+
+        assert!(run(vec![retry_layer(2)], &mut pipe).await.is_err());
+        assert_eq!(pipe.runs(), 3);
+    }
+
+    #[tokio::test]
+    async fn retry_layer_does_not_retry_fatal_errors() {
+        let events = Events::default();
+        let mut pipe = Pipe::new(&events);
+        pipe.failures = vec![Error::fatal("invalid", std::io::Error::other("invalid"))];
+
+        assert!(run(vec![retry_layer(2)], &mut pipe).await.is_err());
+        assert_eq!(pipe.runs(), 1);
+    }
+
+    #[tokio::test]
+    async fn retry_layer_retries_unclassified_errors() {
+        let events = Events::default();
+        let mut pipe = Pipe::new(&events);
+        pipe.failures = vec![Error::Custom("down".to_string())];
+
+        run(vec![retry_layer(2)], &mut pipe).await.unwrap();
+        assert_eq!(pipe.runs(), 2);
+    }
+
+    #[tokio::test]
+    async fn latency_layer_records_every_run() {
+        let events = Events::default();
+        let mut pipe = Pipe::new(&events);
+        pipe.failures = vec![transient()];
+        pipe.duration = Duration::from_millis(5);
+        let memory = Arc::new(InMemoryMetrics::new());
+        let metrics = Arc::new(MetricsCollection::new(vec![memory.clone()]));
+        let context = LayerContext {
+            pipe: "account_0",
+            metrics: &metrics,
+        };
+
+        let layers: Vec<Arc<dyn Layer>> = vec![retry_layer(1), Arc::new(LatencyLayer)];
+        Next::new(&layers, &context, &mut pipe).run().await.unwrap();
+        metrics.flush_metrics().await.unwrap();
+
+        let samples =
+            memory.histogram_with_labels("pipe_latency_nanoseconds", &[("pipe", "account_0")]);
+        assert_eq!(samples.len(), 2);
+        assert!(samples
+            .iter()
+            .all(|nanoseconds| *nanoseconds >= 5_000_000.0));
+    }
+
+    #[tokio::test]
+    async fn timeout_layer_fails_slow_pipes() {
+        let events = Events::default();
+        let mut pipe = Pipe::new(&events);
+        pipe.duration = Duration::from_millis(200);
+
+        let error = run(
+            vec![Arc::new(TimeoutLayer::new(Duration::from_millis(10)))],
+            &mut pipe,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(error, Error::Timeout(_)));
+        assert!(error.is_retryable());
+    }
+}
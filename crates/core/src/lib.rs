@@ -19,6 +19,19 @@
 //! - **[`account_deletion`]**: Handles the deletion of accounts and processes
 //!   these events in the pipeline.
 //!
+//! - **[`block`]**: Handles block-level data, such as blockhashes, block times
+//!   and rewards, and processes it through block pipes.
+//!
+//! - **[`channel`]**: Provides the update channel connecting datasources to
+//!   the pipeline, with an optional capacity and an overflow policy for
+//!   handling backpressure.
+//!
+//! - **[`checkpoint`]**: Persists the progress of each datasource so that a
+//!   restarted pipeline resumes where it stopped.
+//!
+//! - **[`codec`]**: Defines codecs for encoding pipeline values into bytes,
+//!   used when updates have to leave memory.
+//!
 //! - **[`collection`]**: Defines collections for instruction decoding, allowing
 //!   for customized instruction parsers that handle specific instruction sets.
 //!
@@ -26,6 +39,12 @@
 //!   integration of external data sources into the pipeline. Supports
 //!   Solana-specific data structures.
 //!
+//! - **[`dead_letter`]**: Captures updates that pipes failed to process, so
+//!   that they can be reprocessed later.
+//!
+//! - **[`dedup`]**: Drops duplicate updates delivered by more than one
+//!   datasource.
+//!
 //! - **[`deserialize`]**: Contains utilities for data deserialization,
 //!   including helper functions for parsing Solana transactions and other
 //!   binary data formats.
@@ -37,6 +56,9 @@
 //!   transactions. This module includes structures and traits for decoding and
 //!   handling transaction instructions.
 //!
+//! - **[`layer`]**: Defines middleware layers that wrap the processing of
+//!   pipes, such as timeouts, retries and tracing.
+//!
 //! - **[`metrics`]**: Facilitates performance monitoring and metric recording
 //!   within the pipeline. Metrics can be customized and are recorded at each
 //!   processing stage for monitoring and debugging purposes.
@@ -55,6 +77,9 @@
 //!   Supports complex nested instruction matching for comprehensive transaction
 //!   analysis.
 //!
+//! - **[`slot`]**: Defines slot status updates, used to roll back or hold back
+//!   updates of slots that are not yet final.
+//!
 //! - **[`transaction`]**: Manages transaction data, including metadata
 //!   extraction and parsing. This module supports transaction validation and
 //!   processing, enabling detailed transaction insights.
@@ -84,7 +109,7 @@
 //!     TestProgramDecoder,
 //!     TestProgramAccountProcessor
 //! )
-//! .transaction(TEST_SCHEMA.clone(), TestProgramTransactionProcessor)
+//! .transaction(TestProgramTransactionProcessor, Some(TEST_SCHEMA.clone()))
 //! .account_deletions(TestProgramAccountDeletionProcessor)
 //! .build()?
 //! .run()
@@ -112,12 +137,25 @@
 //! data processing requirements.
 
 pub mod account;
+pub mod account_deletion;
+pub mod block;
+pub mod channel;
+pub mod checkpoint;
+pub mod codec;
+pub mod collection;
+pub mod datasource;
+pub mod dead_letter;
+pub mod dedup;
 pub mod deserialize;
 pub mod error;
 pub mod instruction;
+pub mod layer;
 pub mod metrics;
-//pub mod pipeline;
+pub mod pipeline;
 pub mod processor;
+pub mod schema;
+pub mod slot;
+pub mod transaction;
 pub mod transformers;
 pub use borsh;
 #[cfg(feature = "macros")]
@@ -15,6 +15,16 @@
 //!   successful or failed update processing.
 //! - **Histograms**: Measure the distribution of values, such as processing
 //!   times, allowing insights into latency or response times.
+//! - **Labels**: Key-value pairs that add dimensions to a metric, such as the
+//!   datasource an update came from. Metrics with the same name but different
+//!   labels are tracked separately by backends that support labels.
+//!
+//! ## Recording Metrics
+//!
+//! Metrics are recorded through a `MetricsCollection`, which holds the
+//! backends of a pipeline. Recording goes through `Counter`, `Gauge` and
+//! `Histogram` handles and never waits for a backend. Recorded metrics are
+//! passed on to the backends in the background.
 //!
 //! ## Implementing the Trait
 //!
@@ -23,8 +33,35 @@
 //! visualization and alerting. The trait requires `async` functions, allowing
 //! implementations to perform non-blocking I/O operations, such as network
 //! requests or database writes.
+//!
+//! ## Built-in Backends
+//!
+//! - **`InMemoryMetrics`**: Keeps every metric in memory and exposes them
+//!   through getters, for asserting on metrics in tests.
+//! - **`PrometheusMetrics`**: Serves metrics in the Prometheus text format on
+//!   a local `/metrics` endpoint. Requires the `prometheus` feature.
+//! - **`StatsdMetrics`**: Sends metrics to a StatsD or DogStatsD agent over
+//!   UDP, batched into packets on each flush.
+//! - **`OtlpMetrics`**: Exports metrics, and the traces of the pipeline, to an
+//!   OpenTelemetry collector over OTLP/HTTP. Requires the `otlp` feature.
+//!
+//! The `*_with_labels` methods default to their label-less counterparts, which
+//! drop the labels. Backends that support labels override all three of them,
+//! and can then implement the label-less methods by passing no labels.
 
-use {crate::error::CarbonResult, async_trait::async_trait, std::sync::Arc};
+use {
+    crate::error::CarbonResult,
+    async_trait::async_trait,
+    std::{
+        collections::{HashMap, VecDeque},
+        sync::{
+            atomic::{AtomicBool, AtomicU64, Ordering},
+            Arc, Mutex, MutexGuard,
+        },
+        time::Duration,
+    },
+    tokio::sync::mpsc,
+};
 
 #[async_trait]
 pub trait Metrics: Send + Sync {
@@ -61,33 +98,443 @@ pub trait Metrics: Send + Sync {
     /// - `value`: The value to add to the histogram, typically representing
     ///   time or size.
     async fn record_histogram(&self, name: &str, value: f64) -> CarbonResult<()>;
+
+    /// Updates a gauge metric with the given labels.
+    ///
+    /// By default, the labels are dropped and the gauge is updated by name.
+    ///
+    /// # Parameters
+    ///
+    /// - `name`: The name of the gauge metric to update.
+    /// - `labels`: The label names and values of the gauge.
+    /// - `value`: The current value of the gauge metric.
+    async fn update_gauge_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) -> CarbonResult<()> {
+        let _ = labels;
+        self.update_gauge(name, value).await
+    }
+
+    /// Increments a counter metric with the given labels.
+    ///
+    /// By default, the labels are dropped and the counter is incremented by
+    /// name.
+    ///
+    /// # Parameters
+    ///
+    /// - `name`: The name of the counter metric to increment.
+    /// - `labels`: The label names and values of the counter.
+    /// - `value`: The amount by which to increment the counter.
+    async fn increment_counter_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: u64,
+    ) -> CarbonResult<()> {
+        let _ = labels;
+        self.increment_counter(name, value).await
+    }
+
+    /// Records a value in a histogram metric with the given labels.
+    ///
+    /// By default, the labels are dropped and the value is recorded by name.
+    ///
+    /// # Parameters
+    ///
+    /// - `name`: The name of the histogram metric to record.
+    /// - `labels`: The label names and values of the histogram.
+    /// - `value`: The value to add to the histogram.
+    async fn record_histogram_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) -> CarbonResult<()> {
+        let _ = labels;
+        self.record_histogram(name, value).await
+    }
+}
+
+/// How often `MetricsCollection` passes recorded metrics to its backends.
+pub const METRICS_REPORT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The number of samples a `Histogram` holds between two reports. Samples
+/// recorded while it is full are dropped.
+pub const HISTOGRAM_MAX_PENDING_SAMPLES: usize = 65_536;
+
+/// The name and labels that identify a metric.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct MetricKey {
+    name: String,
+    labels: Vec<(String, String)>,
+}
+
+impl MetricKey {
+    fn new(name: &str, labels: &[(&str, &str)]) -> Self {
+        Self {
+            name: name.to_string(),
+            labels: labels
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    fn labels(&self) -> Vec<(&str, &str)> {
+        self.labels
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect()
+    }
+}
+
+/// A handle to a counter registered in a `MetricsCollection`.
+///
+/// Incrementing a counter only adds to an atomic value, which is passed on
+/// to the backends by the next report. Handles are cheap to clone, and all
+/// clones refer to the same counter.
+#[derive(Debug, Clone)]
+pub struct Counter(Arc<CounterInner>);
+
+#[derive(Debug)]
+struct CounterInner {
+    key: MetricKey,
+    pending: AtomicU64,
+}
+
+impl Counter {
+    /// Increments the counter by `value`.
+    pub fn increment(&self, value: u64) {
+        self.0.pending.fetch_add(value, Ordering::Relaxed);
+    }
+}
+
+/// A handle to a gauge registered in a `MetricsCollection`.
+///
+/// Only the latest value set between two reports is passed on to the
+/// backends.
+#[derive(Debug, Clone)]
+pub struct Gauge(Arc<GaugeInner>);
+
+#[derive(Debug)]
+struct GaugeInner {
+    key: MetricKey,
+    value: AtomicU64,
+    updated: AtomicBool,
+}
+
+impl Gauge {
+    /// Sets the gauge to `value`.
+    pub fn set(&self, value: f64) {
+        self.0.value.store(value.to_bits(), Ordering::Relaxed);
+        self.0.updated.store(true, Ordering::Release);
+    }
+}
+
+/// A handle to a histogram registered in a `MetricsCollection`.
+///
+/// Recorded values are queued without blocking, and passed on to the backends
+/// by the next report. At most `HISTOGRAM_MAX_PENDING_SAMPLES` values are
+/// queued between two reports; values recorded past that are dropped, and
+/// their number is logged by the next report.
+#[derive(Debug, Clone)]
+pub struct Histogram(Arc<HistogramInner>);
+
+#[derive(Debug)]
+struct HistogramInner {
+    key: MetricKey,
+    sender: mpsc::Sender<f64>,
+    receiver: Mutex<mpsc::Receiver<f64>>,
+    dropped: AtomicU64,
+}
+
+impl Histogram {
+    /// Records `value` in the histogram.
+    pub fn record(&self, value: f64) {
+        // The receiver lives as long as the handle, so sending only fails
+        // when the queue is full.
+        if self.0.sender.try_send(value).is_err() {
+            self.0.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The metrics registered in a `MetricsCollection`.
+#[derive(Debug, Default)]
+struct MetricsRegistry {
+    counters: Mutex<HashMap<MetricKey, Counter>>,
+    gauges: Mutex<HashMap<MetricKey, Gauge>>,
+    histograms: Mutex<HashMap<MetricKey, Histogram>>,
+    report_lock: tokio::sync::Mutex<()>,
 }
 
+impl MetricsRegistry {
+    fn counter(&self, key: MetricKey) -> Counter {
+        lock(&self.counters)
+            .entry(key.clone())
+            .or_insert_with(|| {
+                Counter(Arc::new(CounterInner {
+                    key,
+                    pending: AtomicU64::new(0),
+                }))
+            })
+            .clone()
+    }
+
+    fn gauge(&self, key: MetricKey) -> Gauge {
+        lock(&self.gauges)
+            .entry(key.clone())
+            .or_insert_with(|| {
+                Gauge(Arc::new(GaugeInner {
+                    key,
+                    value: AtomicU64::new(0),
+                    updated: AtomicBool::new(false),
+                }))
+            })
+            .clone()
+    }
+
+    fn histogram(&self, key: MetricKey) -> Histogram {
+        lock(&self.histograms)
+            .entry(key.clone())
+            .or_insert_with(|| {
+                let (sender, receiver) = mpsc::channel(HISTOGRAM_MAX_PENDING_SAMPLES);
+                Histogram(Arc::new(HistogramInner {
+                    key,
+                    sender,
+                    receiver: Mutex::new(receiver),
+                    dropped: AtomicU64::new(0),
+                }))
+            })
+            .clone()
+    }
+
+    /// Passes everything recorded since the last report to `backends`.
+    ///
+    /// Metrics without labels are passed through the label-less methods of
+    /// the backends. A backend that fails to take a metric is logged, and
+    /// does not keep the metric from the other backends.
+    async fn report(&self, backends: &[Arc<dyn Metrics>]) {
+        let _report = self.report_lock.lock().await;
+
+        let counters: Vec<Counter> = lock(&self.counters).values().cloned().collect();
+        for counter in counters {
+            let value = counter.0.pending.swap(0, Ordering::Relaxed);
+            if value == 0 {
+                continue;
+            }
+
+            let key = &counter.0.key;
+            for backend in backends {
+                let result = if key.labels.is_empty() {
+                    backend.increment_counter(&key.name, value).await
+                } else {
+                    backend
+                        .increment_counter_with_labels(&key.name, &key.labels(), value)
+                        .await
+                };
+                log_report_error("counter", &key.name, result);
+            }
+        }
+
+        let gauges: Vec<Gauge> = lock(&self.gauges).values().cloned().collect();
+        for gauge in gauges {
+            if !gauge.0.updated.swap(false, Ordering::Acquire) {
+                continue;
+            }
+
+            let value = f64::from_bits(gauge.0.value.load(Ordering::Relaxed));
+            let key = &gauge.0.key;
+            for backend in backends {
+                let result = if key.labels.is_empty() {
+                    backend.update_gauge(&key.name, value).await
+                } else {
+                    backend
+                        .update_gauge_with_labels(&key.name, &key.labels(), value)
+                        .await
+                };
+                log_report_error("gauge", &key.name, result);
+            }
+        }
+
+        let histograms: Vec<Histogram> = lock(&self.histograms).values().cloned().collect();
+        for histogram in histograms {
+            let values: Vec<f64> = {
+                let mut receiver = lock(&histogram.0.receiver);
+                std::iter::from_fn(|| receiver.try_recv().ok()).collect()
+            };
+
+            let key = &histogram.0.key;
+            let dropped = histogram.0.dropped.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                log::warn!(
+                    "dropped {} samples of histogram {} that did not fit in its queue.",
+                    dropped,
+                    key.name
+                );
+            }
+
+            for value in values {
+                for backend in backends {
+                    let result = if key.labels.is_empty() {
+                        backend.record_histogram(&key.name, value).await
+                    } else {
+                        backend
+                            .record_histogram_with_labels(&key.name, &key.labels(), value)
+                            .await
+                    };
+                    log_report_error("histogram", &key.name, result);
+                }
+            }
+        }
+    }
+}
+
+/// Logs the failure of a backend to take a reported metric.
+fn log_report_error(kind: &str, name: &str, result: CarbonResult<()>) {
+    if let Err(error) = result {
+        log::error!("failed to report {} {}: {:?}", kind, name, error);
+    }
+}
+
+/// The metrics backends of a pipeline, and the metrics recorded for them.
+///
+/// Metrics are recorded through `Counter`, `Gauge` and `Histogram` handles,
+/// which are registered once and then updated synchronously without locks.
+/// Recording never waits for a backend: a background task, started by
+/// `initialize_metrics`, passes the recorded metrics to every backend every
+/// `METRICS_REPORT_INTERVAL`. The cost of recording a metric therefore does
+/// not depend on the number of backends.
+///
+/// # Example
+///
+/// ```rust
+/// struct CountingProcessor {
+///     swaps: Option<Counter>,
+/// }
+///
+/// #[async_trait]
+/// impl Processor for CountingProcessor {
+///     type InputType = InstructionProcessorInputType<SwapInstruction>;
+///
+///     async fn initialize(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+///         self.swaps = Some(metrics.counter("swaps_processed"));
+///         Ok(())
+///     }
+///
+///     async fn process(
+///         &mut self,
+///         data: Self::InputType,
+///         metrics: Arc<MetricsCollection>,
+///     ) -> CarbonResult<()> {
+///         if let Some(swaps) = &self.swaps {
+///             swaps.increment(1);
+///         }
+///         Ok(())
+///     }
+/// }
+/// ```
+///
+/// # Notes
+///
+/// - The async `update_gauge`, `increment_counter` and `record_histogram`
+///   methods look the metric up by name on every call. They remain for
+///   compatibility, and handles should be preferred on hot paths.
+/// - Backends see counters as one increment per report, carrying the sum of
+///   the increments recorded since the previous one.
+/// - `flush_metrics` and `shutdown_metrics` report pending metrics before
+///   flushing or shutting down the backends.
 #[derive(Default)]
 pub struct MetricsCollection {
     pub metrics: Vec<Arc<dyn Metrics>>,
+    registry: Arc<MetricsRegistry>,
+    reporter: Mutex<Option<tokio::task::JoinHandle<()>>>,
 }
 
 impl MetricsCollection {
     pub fn new(metrics: Vec<Arc<dyn Metrics>>) -> Self {
-        Self { metrics }
+        Self {
+            metrics,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the handle of the counter `name`, registering it if needed.
+    pub fn counter(&self, name: &str) -> Counter {
+        self.counter_with_labels(name, &[])
+    }
+
+    /// Returns the handle of the counter `name` with `labels`, registering it
+    /// if needed.
+    pub fn counter_with_labels(&self, name: &str, labels: &[(&str, &str)]) -> Counter {
+        self.registry.counter(MetricKey::new(name, labels))
     }
 
+    /// Returns the handle of the gauge `name`, registering it if needed.
+    pub fn gauge(&self, name: &str) -> Gauge {
+        self.gauge_with_labels(name, &[])
+    }
+
+    /// Returns the handle of the gauge `name` with `labels`, registering it if
+    /// needed.
+    pub fn gauge_with_labels(&self, name: &str, labels: &[(&str, &str)]) -> Gauge {
+        self.registry.gauge(MetricKey::new(name, labels))
+    }
+
+    /// Returns the handle of the histogram `name`, registering it if needed.
+    pub fn histogram(&self, name: &str) -> Histogram {
+        self.histogram_with_labels(name, &[])
+    }
+
+    /// Returns the handle of the histogram `name` with `labels`, registering
+    /// it if needed.
+    pub fn histogram_with_labels(&self, name: &str, labels: &[(&str, &str)]) -> Histogram {
+        self.registry.histogram(MetricKey::new(name, labels))
+    }
+
+    /// Initializes every backend and starts reporting recorded metrics to
+    /// them in the background.
     pub async fn initialize_metrics(&self) -> CarbonResult<()> {
         for metric in &self.metrics {
             metric.initialize().await?;
         }
+
+        let registry = self.registry.clone();
+        let backends = self.metrics.clone();
+        let reporter = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(METRICS_REPORT_INTERVAL);
+            loop {
+                interval.tick().await;
+                registry.report(&backends).await;
+            }
+        });
+
+        if let Some(previous) = lock(&self.reporter).replace(reporter) {
+            previous.abort();
+        }
         Ok(())
     }
 
+    /// Stops the background reporting, reports pending metrics and shuts down
+    /// every backend.
     pub async fn shutdown_metrics(&self) -> CarbonResult<()> {
+        if let Some(reporter) = lock(&self.reporter).take() {
+            reporter.abort();
+        }
+
+        self.registry.report(&self.metrics).await;
         for metric in &self.metrics {
             metric.shutdown().await?;
         }
         Ok(())
     }
 
+    /// Reports pending metrics and flushes every backend.
     pub async fn flush_metrics(&self) -> CarbonResult<()> {
+        self.registry.report(&self.metrics).await;
         for metric in &self.metrics {
             metric.flush().await?;
         }
@@ -95,23 +542,1518 @@ impl MetricsCollection {
     }
 
     pub async fn update_gauge(&self, name: &str, value: f64) -> CarbonResult<()> {
-        for metric in &self.metrics {
-            metric.update_gauge(name, value).await?;
-        }
+        self.gauge(name).set(value);
         Ok(())
     }
 
     pub async fn increment_counter(&self, name: &str, value: u64) -> CarbonResult<()> {
-        for metric in &self.metrics {
-            metric.increment_counter(name, value).await?;
-        }
+        self.counter(name).increment(value);
         Ok(())
     }
 
     pub async fn record_histogram(&self, name: &str, value: f64) -> CarbonResult<()> {
-        for metric in &self.metrics {
-            metric.record_histogram(name, value).await?;
+        self.histogram(name).record(value);
+        Ok(())
+    }
+
+    pub async fn update_gauge_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) -> CarbonResult<()> {
+        self.gauge_with_labels(name, labels).set(value);
+        Ok(())
+    }
+
+    pub async fn increment_counter_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: u64,
+    ) -> CarbonResult<()> {
+        self.counter_with_labels(name, labels).increment(value);
+        Ok(())
+    }
+
+    pub async fn record_histogram_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) -> CarbonResult<()> {
+        self.histogram_with_labels(name, labels).record(value);
+        Ok(())
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A summary of the samples recorded in a histogram.
+///
+/// # Fields
+///
+/// - `count`: The number of samples.
+/// - `sum`: The sum of the samples.
+/// - `min`: The smallest sample.
+/// - `max`: The largest sample.
+/// - `mean`: The average of the samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistogramSummary {
+    pub count: usize,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+/// A `Metrics` backend that keeps every metric in memory.
+///
+/// `InMemoryMetrics` is meant for tests: it records counters, gauges and
+/// histogram samples, and exposes them through getters so that tests can
+/// assert on the metrics a pipeline or processor emits.
+///
+/// # Example
+///
+/// ```rust
+/// let metrics = Arc::new(InMemoryMetrics::new());
+///
+/// Pipeline::builder()
+///     .datasource(MyDatasource::new())
+///     .metrics(metrics.clone())
+///     .account(MyAccountDecoder, MyAccountProcessor)
+///     .build()?
+///     .run()
+///     .await?;
+///
+/// assert_eq!(metrics.counter("updates_failed"), 0);
+/// assert_eq!(metrics.counter("updates_successful"), 10);
+/// ```
+///
+/// # Notes
+///
+/// - Metrics recorded through a `MetricsCollection` reach the backend when
+///   they are reported, for example on `flush_metrics` or when the pipeline
+///   stops.
+/// - Metrics are told apart by name and labels. The label-less getters only
+///   return metrics recorded without labels.
+#[derive(Debug, Default)]
+pub struct InMemoryMetrics {
+    counters: Mutex<HashMap<MetricKey, u64>>,
+    gauges: Mutex<HashMap<MetricKey, f64>>,
+    histograms: Mutex<HashMap<MetricKey, Vec<f64>>>,
+}
+
+impl InMemoryMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the value of the counter `name`, or 0 if it was never
+    /// incremented.
+    pub fn counter(&self, name: &str) -> u64 {
+        self.counter_with_labels(name, &[])
+    }
+
+    /// Returns the value of the counter `name` with `labels`, or 0 if it was
+    /// never incremented.
+    pub fn counter_with_labels(&self, name: &str, labels: &[(&str, &str)]) -> u64 {
+        lock(&self.counters)
+            .get(&MetricKey::new(name, labels))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the latest value of the gauge `name`, if it was ever set.
+    pub fn gauge(&self, name: &str) -> Option<f64> {
+        self.gauge_with_labels(name, &[])
+    }
+
+    /// Returns the latest value of the gauge `name` with `labels`, if it was
+    /// ever set.
+    pub fn gauge_with_labels(&self, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        lock(&self.gauges)
+            .get(&MetricKey::new(name, labels))
+            .copied()
+    }
+
+    /// Returns the samples recorded in the histogram `name`, in the order
+    /// they were recorded.
+    pub fn histogram(&self, name: &str) -> Vec<f64> {
+        self.histogram_with_labels(name, &[])
+    }
+
+    /// Returns the samples recorded in the histogram `name` with `labels`, in
+    /// the order they were recorded.
+    pub fn histogram_with_labels(&self, name: &str, labels: &[(&str, &str)]) -> Vec<f64> {
+        lock(&self.histograms)
+            .get(&MetricKey::new(name, labels))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Summarizes the samples of the histogram `name`, if any were recorded.
+    pub fn histogram_summary(&self, name: &str) -> Option<HistogramSummary> {
+        self.histogram_summary_with_labels(name, &[])
+    }
+
+    /// Summarizes the samples of the histogram `name` with `labels`, if any
+    /// were recorded.
+    pub fn histogram_summary_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+    ) -> Option<HistogramSummary> {
+        let samples = self.histogram_with_labels(name, labels);
+        if samples.is_empty() {
+            return None;
         }
+
+        let sum: f64 = samples.iter().sum();
+        Some(HistogramSummary {
+            count: samples.len(),
+            sum,
+            min: samples.iter().copied().fold(f64::INFINITY, f64::min),
+            max: samples.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            mean: sum / samples.len() as f64,
+        })
+    }
+
+    /// Forgets every recorded metric.
+    pub fn clear(&self) {
+        lock(&self.counters).clear();
+        lock(&self.gauges).clear();
+        lock(&self.histograms).clear();
+    }
+}
+
+#[async_trait]
+impl Metrics for InMemoryMetrics {
+    async fn initialize(&self) -> CarbonResult<()> {
+        Ok(())
+    }
+
+    async fn flush(&self) -> CarbonResult<()> {
+        Ok(())
+    }
+
+    async fn shutdown(&self) -> CarbonResult<()> {
         Ok(())
     }
+
+    async fn update_gauge(&self, name: &str, value: f64) -> CarbonResult<()> {
+        self.update_gauge_with_labels(name, &[], value).await
+    }
+
+    async fn increment_counter(&self, name: &str, value: u64) -> CarbonResult<()> {
+        self.increment_counter_with_labels(name, &[], value).await
+    }
+
+    async fn record_histogram(&self, name: &str, value: f64) -> CarbonResult<()> {
+        self.record_histogram_with_labels(name, &[], value).await
+    }
+
+    async fn update_gauge_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) -> CarbonResult<()> {
+        lock(&self.gauges).insert(MetricKey::new(name, labels), value);
+        Ok(())
+    }
+
+    async fn increment_counter_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: u64,
+    ) -> CarbonResult<()> {
+        *lock(&self.counters)
+            .entry(MetricKey::new(name, labels))
+            .or_default() += value;
+        Ok(())
+    }
+
+    async fn record_histogram_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) -> CarbonResult<()> {
+        lock(&self.histograms)
+            .entry(MetricKey::new(name, labels))
+            .or_default()
+            .push(value);
+        Ok(())
+    }
+}
+
+/// The default maximum size of a `StatsdMetrics` packet, in bytes.
+///
+/// This keeps packets within the payload of a single Ethernet frame.
+pub const DEFAULT_STATSD_MAX_PACKET_SIZE: usize = 1432;
+
+/// The default maximum number of metrics a `StatsdMetrics` buffers between
+/// flushes.
+pub const DEFAULT_STATSD_MAX_BUFFERED_METRICS: usize = 100_000;
+
+/// A `Metrics` backend that sends metrics to a StatsD agent over UDP.
+///
+/// Metrics are buffered as they are recorded and sent on `flush`, packed
+/// into as few packets as `max_packet_size` allows. Counters are sent as
+/// `c`, gauges as `g` and histograms as `h`.
+///
+/// Labels and the tags set with `with_tag` are sent as DogStatsD tags, for
+/// example `carbon.updates_processed:1|c|#env:prod,datasource:rpc`.
+///
+/// # Example
+///
+/// ```rust
+/// let builder = PipelineBuilder::new()
+///     .metrics(Arc::new(
+///         StatsdMetrics::new(([127, 0, 0, 1], 8125))
+///             .with_prefix("indexer")
+///             .with_tag("env", "prod"),
+///     ))
+///     .datasource(MyDatasource::new());
+/// ```
+///
+/// # Notes
+///
+/// - Metrics are buffered until `flush`. Flushing before `initialize` fails
+///   and keeps the buffered metrics, and metrics that could not be sent are
+///   kept for the next flush.
+/// - At most `max_buffered_metrics` metrics are buffered. Once the buffer is
+///   full, the oldest metrics are dropped and their number is logged on the
+///   next flush.
+/// - Negative gauge values are sent after a reset to zero, since StatsD reads
+///   a signed gauge value as a change to the current value.
+/// - Characters that StatsD reserves, such as `:`, `|` and `,`, are replaced
+///   with `_` in names and tags.
+pub struct StatsdMetrics {
+    address: std::net::SocketAddr,
+    prefix: Option<String>,
+    tags: Vec<(String, String)>,
+    max_packet_size: usize,
+    max_buffered_metrics: usize,
+    socket: std::sync::OnceLock<tokio::net::UdpSocket>,
+    buffer: Mutex<VecDeque<String>>,
+    dropped: AtomicU64,
+}
+
+impl StatsdMetrics {
+    /// Creates a backend that will send metrics to the agent at `address`.
+    pub fn new(address: impl Into<std::net::SocketAddr>) -> Self {
+        Self {
+            address: address.into(),
+            prefix: None,
+            tags: Vec::new(),
+            max_packet_size: DEFAULT_STATSD_MAX_PACKET_SIZE,
+            max_buffered_metrics: DEFAULT_STATSD_MAX_BUFFERED_METRICS,
+            socket: std::sync::OnceLock::new(),
+            buffer: Mutex::new(VecDeque::new()),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Sets a prefix for every metric name, separated from the name by a `.`.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Adds a tag that is sent with every metric.
+    pub fn with_tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.push((key.into(), value.into()));
+        self
+    }
+
+    /// Sets the maximum size of a packet, in bytes.
+    ///
+    /// A metric that does not fit in a packet on its own is sent in a packet
+    /// of its own.
+    pub fn with_max_packet_size(mut self, max_packet_size: usize) -> Self {
+        self.max_packet_size = max_packet_size;
+        self
+    }
+
+    /// Sets the maximum number of metrics buffered between flushes.
+    ///
+    /// Once the buffer is full, the oldest metrics are dropped to make room.
+    pub fn with_max_buffered_metrics(mut self, max_buffered_metrics: usize) -> Self {
+        self.max_buffered_metrics = max_buffered_metrics;
+        self
+    }
+
+    fn push(&self, name: &str, labels: &[(&str, &str)], value: &str, kind: &str) {
+        let mut line = String::new();
+        if let Some(prefix) = &self.prefix {
+            line.push_str(&Self::sanitize(prefix));
+            line.push('.');
+        }
+        line.push_str(&Self::sanitize(name));
+        line.push(':');
+        line.push_str(value);
+        line.push('|');
+        line.push_str(kind);
+
+        let tags = self
+            .tags
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .chain(labels.iter().copied())
+            .map(|(key, value)| format!("{}:{}", Self::sanitize(key), Self::sanitize(value)))
+            .collect::<Vec<_>>();
+        if !tags.is_empty() {
+            line.push_str("|#");
+            line.push_str(&tags.join(","));
+        }
+
+        let mut buffer = lock(&self.buffer);
+        buffer.push_back(line);
+        self.trim(&mut buffer);
+    }
+
+    /// Drops the oldest metrics of `buffer` until it holds no more than
+    /// `max_buffered_metrics`.
+    fn trim(&self, buffer: &mut VecDeque<String>) {
+        let excess = buffer.len().saturating_sub(self.max_buffered_metrics);
+        if excess > 0 {
+            buffer.drain(..excess);
+            self.dropped.fetch_add(excess as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn sanitize(value: &str) -> String {
+        value
+            .chars()
+            .map(|c| match c {
+                ':' | '|' | '@' | ',' | '#' | '\n' => '_',
+                c => c,
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Metrics for StatsdMetrics {
+    async fn initialize(&self) -> CarbonResult<()> {
+        use crate::error::Error;
+
+        if self.socket.get().is_some() {
+            return Err(Error::Custom(
+                "statsd metrics are already initialized".to_string(),
+            ));
+        }
+
+        let bind_address: std::net::SocketAddr = if self.address.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+
+        let socket = tokio::net::UdpSocket::bind(bind_address)
+            .await
+            .map_err(|e| Error::transient("failed to bind statsd socket", e))?;
+        socket
+            .connect(self.address)
+            .await
+            .map_err(|e| Error::transient("failed to connect statsd socket", e))?;
+
+        let _ = self.socket.set(socket);
+
+        log::info!("sending statsd metrics to {}", self.address);
+        Ok(())
+    }
+
+    async fn flush(&self) -> CarbonResult<()> {
+        use crate::error::Error;
+
+        let Some(socket) = self.socket.get() else {
+            return Err(Error::Custom(
+                "statsd metrics are not initialized".to_string(),
+            ));
+        };
+
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            log::warn!(
+                "dropped {} statsd metrics that did not fit in the buffer.",
+                dropped
+            );
+        }
+
+        let mut lines = std::mem::take(&mut *lock(&self.buffer));
+
+        let mut packets = Vec::new();
+        let mut packet = String::new();
+        let mut packet_lines = 0;
+        for line in &lines {
+            if !packet.is_empty() && packet.len() + 1 + line.len() > self.max_packet_size {
+                packets.push((std::mem::take(&mut packet), packet_lines));
+                packet_lines = 0;
+            }
+            if !packet.is_empty() {
+                packet.push('\n');
+            }
+            packet.push_str(line);
+            packet_lines += 1;
+        }
+        if !packet.is_empty() {
+            packets.push((packet, packet_lines));
+        }
+
+        let mut sent = 0;
+        for (packet, packet_lines) in packets {
+            if let Err(error) = socket.send(packet.as_bytes()).await {
+                // Keep the unsent metrics ahead of those recorded since.
+                let mut buffer = lock(&self.buffer);
+                lines.drain(..sent);
+                lines.append(&mut buffer);
+                *buffer = lines;
+                self.trim(&mut buffer);
+
+                return Err(Error::transient("failed to send statsd metrics", error));
+            }
+            sent += packet_lines;
+        }
+        Ok(())
+    }
+
+    async fn shutdown(&self) -> CarbonResult<()> {
+        self.flush().await
+    }
+
+    async fn update_gauge(&self, name: &str, value: f64) -> CarbonResult<()> {
+        self.update_gauge_with_labels(name, &[], value).await
+    }
+
+    async fn increment_counter(&self, name: &str, value: u64) -> CarbonResult<()> {
+        self.increment_counter_with_labels(name, &[], value).await
+    }
+
+    async fn record_histogram(&self, name: &str, value: f64) -> CarbonResult<()> {
+        self.record_histogram_with_labels(name, &[], value).await
+    }
+
+    async fn update_gauge_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) -> CarbonResult<()> {
+        if value.is_sign_negative() && value != 0.0 {
+            self.push(name, labels, "0", "g");
+        }
+        self.push(name, labels, &value.to_string(), "g");
+        Ok(())
+    }
+
+    async fn increment_counter_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: u64,
+    ) -> CarbonResult<()> {
+        self.push(name, labels, &value.to_string(), "c");
+        Ok(())
+    }
+
+    async fn record_histogram_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) -> CarbonResult<()> {
+        self.push(name, labels, &value.to_string(), "h");
+        Ok(())
+    }
+}
+
+/// The default histogram buckets of `PrometheusMetrics` and `OtlpMetrics` for
+/// processing times, in milliseconds.
+#[cfg(any(feature = "prometheus", feature = "otlp"))]
+pub const DEFAULT_PROCESS_TIME_BUCKETS: &[f64] = &[
+    1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+];
+
+#[cfg(feature = "prometheus")]
+static PROMETHEUS_METADATA: metrics::Metadata<'static> =
+    metrics::Metadata::new(module_path!(), metrics::Level::INFO, Some(module_path!()));
+
+/// A `Metrics` backend that serves metrics in the Prometheus text format.
+///
+/// When initialized, `PrometheusMetrics` listens on `listen_address` and
+/// answers scrapes of `/metrics` with the current value of every metric.
+/// Labels are exported as Prometheus labels.
+///
+/// Processing time histograms, whose names end in
+/// `_process_time_milliseconds` or `_process_time_nanoseconds`, are exported
+/// with buckets. Other histograms are exported as summaries.
+///
+/// # Example
+///
+/// ```rust
+/// let builder = PipelineBuilder::new()
+///     .metrics(Arc::new(
+///         PrometheusMetrics::new(([0, 0, 0, 0], 9000))
+///             .with_process_time_buckets(vec![5.0, 50.0, 500.0]),
+///     ))
+///     .datasource(MyDatasource::new());
+/// ```
+///
+/// # Notes
+///
+/// - Metrics recorded before `initialize` are dropped.
+/// - Scrapes of any other path also receive the metrics, and `/health`
+///   answers with `OK`.
+#[cfg(feature = "prometheus")]
+pub struct PrometheusMetrics {
+    listen_address: std::net::SocketAddr,
+    process_time_buckets: Vec<f64>,
+    recorder: std::sync::OnceLock<metrics_exporter_prometheus::PrometheusRecorder>,
+    exporter: Mutex<Option<tokio::task::JoinHandle<()>>>,
+}
+
+#[cfg(feature = "prometheus")]
+impl PrometheusMetrics {
+    /// Creates a backend that will serve metrics on `listen_address`.
+    pub fn new(listen_address: impl Into<std::net::SocketAddr>) -> Self {
+        Self {
+            listen_address: listen_address.into(),
+            process_time_buckets: DEFAULT_PROCESS_TIME_BUCKETS.to_vec(),
+            recorder: std::sync::OnceLock::new(),
+            exporter: Mutex::new(None),
+        }
+    }
+
+    /// Sets the histogram buckets for processing times, in milliseconds.
+    ///
+    /// The buckets of the `_process_time_nanoseconds` histograms are derived
+    /// from the same values.
+    pub fn with_process_time_buckets(mut self, buckets: Vec<f64>) -> Self {
+        self.process_time_buckets = buckets;
+        self
+    }
+
+    fn key(name: &str, labels: &[(&str, &str)]) -> metrics::Key {
+        let labels: Vec<metrics::Label> = labels
+            .iter()
+            .map(|(key, value)| metrics::Label::new(key.to_string(), value.to_string()))
+            .collect();
+
+        metrics::Key::from_parts(name.to_string(), labels)
+    }
+}
+
+#[cfg(feature = "prometheus")]
+#[async_trait]
+impl Metrics for PrometheusMetrics {
+    async fn initialize(&self) -> CarbonResult<()> {
+        use {
+            crate::error::Error,
+            metrics_exporter_prometheus::{Matcher, PrometheusBuilder},
+        };
+
+        if self.recorder.get().is_some() {
+            return Err(Error::Custom(
+                "prometheus metrics are already initialized".to_string(),
+            ));
+        }
+
+        let nanosecond_buckets: Vec<f64> = self
+            .process_time_buckets
+            .iter()
+            .map(|bucket| bucket * 1_000_000.0)
+            .collect();
+
+        let (recorder, exporter) = PrometheusBuilder::new()
+            .with_http_listener(self.listen_address)
+            .set_buckets_for_metric(
+                Matcher::Suffix("_process_time_milliseconds".to_string()),
+                &self.process_time_buckets,
+            )
+            .and_then(|builder| {
+                builder.set_buckets_for_metric(
+                    Matcher::Suffix("_process_time_nanoseconds".to_string()),
+                    &nanosecond_buckets,
+                )
+            })
+            .and_then(PrometheusBuilder::build)
+            .map_err(|e| Error::transient("failed to start prometheus exporter", e))?;
+
+        let listen_address = self.listen_address;
+        let exporter = tokio::spawn(async move {
+            if let Err(error) = exporter.await {
+                log::error!(
+                    "prometheus exporter on {} stopped: {:?}",
+                    listen_address,
+                    error
+                );
+            }
+        });
+
+        let _ = self.recorder.set(recorder);
+        *lock(&self.exporter) = Some(exporter);
+
+        log::info!("serving prometheus metrics on {}", self.listen_address);
+        Ok(())
+    }
+
+    async fn flush(&self) -> CarbonResult<()> {
+        Ok(())
+    }
+
+    async fn shutdown(&self) -> CarbonResult<()> {
+        if let Some(exporter) = lock(&self.exporter).take() {
+            exporter.abort();
+        }
+        Ok(())
+    }
+
+    async fn update_gauge(&self, name: &str, value: f64) -> CarbonResult<()> {
+        self.update_gauge_with_labels(name, &[], value).await
+    }
+
+    async fn increment_counter(&self, name: &str, value: u64) -> CarbonResult<()> {
+        self.increment_counter_with_labels(name, &[], value).await
+    }
+
+    async fn record_histogram(&self, name: &str, value: f64) -> CarbonResult<()> {
+        self.record_histogram_with_labels(name, &[], value).await
+    }
+
+    async fn update_gauge_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) -> CarbonResult<()> {
+        use metrics::Recorder;
+
+        if let Some(recorder) = self.recorder.get() {
+            recorder
+                .register_gauge(&Self::key(name, labels), &PROMETHEUS_METADATA)
+                .set(value);
+        }
+        Ok(())
+    }
+
+    async fn increment_counter_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: u64,
+    ) -> CarbonResult<()> {
+        use metrics::Recorder;
+
+        if let Some(recorder) = self.recorder.get() {
+            recorder
+                .register_counter(&Self::key(name, labels), &PROMETHEUS_METADATA)
+                .increment(value);
+        }
+        Ok(())
+    }
+
+    async fn record_histogram_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) -> CarbonResult<()> {
+        use metrics::Recorder;
+
+        if let Some(recorder) = self.recorder.get() {
+            recorder
+                .register_histogram(&Self::key(name, labels), &PROMETHEUS_METADATA)
+                .record(value);
+        }
+        Ok(())
+    }
+}
+
+/// The maximum number of finished spans `OtlpMetrics` buffers between two
+/// flushes. Spans finished while the buffer is full are dropped, and their
+/// number is logged on the next flush.
+#[cfg(feature = "otlp")]
+const OTLP_MAX_BUFFERED_SPANS: usize = 65_536;
+
+/// A `Metrics` backend that exports metrics and traces to an OpenTelemetry
+/// collector over OTLP/HTTP.
+///
+/// Metrics are aggregated in memory and exported on `flush` to the
+/// `/v1/metrics` path of `endpoint`. Counters are exported as cumulative
+/// sums, and labels as attributes.
+///
+/// With the `otlp` feature, which enables the `tracing` feature, the pipeline
+/// runs every update inside a `tracing` span named `update`, with child spans
+/// named `decode` and `process` for the stages of its pipes. The layer
+/// returned by `tracing_layer` turns those spans into OpenTelemetry spans,
+/// which are exported on `flush` to the `/v1/traces` path of `endpoint`.
+///
+/// # Example
+///
+/// ```rust
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// let otlp = Arc::new(OtlpMetrics::new("http://localhost:4318", "my-indexer"));
+/// tracing::subscriber::set_global_default(
+///     tracing_subscriber::registry().with(otlp.tracing_layer()),
+/// )?;
+///
+/// let builder = PipelineBuilder::new()
+///     .metrics(otlp)
+///     .datasource(MyDatasource::new());
+/// ```
+///
+/// # Notes
+///
+/// - Metrics and spans are buffered until `flush`. Flushing before
+///   `initialize` fails and keeps the buffered data.
+/// - Processing time histograms, whose names end in
+///   `_process_time_milliseconds` or `_process_time_nanoseconds`, use the
+///   buckets set with `with_process_time_buckets`.
+/// - Data is sent as protobuf over plain HTTP.
+#[cfg(feature = "otlp")]
+pub struct OtlpMetrics {
+    endpoint: String,
+    process_time_buckets: Vec<f64>,
+    meter: opentelemetry::metrics::Meter,
+    meter_provider: opentelemetry_sdk::metrics::SdkMeterProvider,
+    reader: Arc<opentelemetry_sdk::metrics::ManualReader>,
+    tracer_provider: opentelemetry_sdk::trace::SdkTracerProvider,
+    resource: opentelemetry_sdk::Resource,
+    spans: Arc<OtlpSpanBuffer>,
+    exporters: std::sync::OnceLock<OtlpExporters>,
+    instruments: Mutex<OtlpInstruments>,
+}
+
+#[cfg(feature = "otlp")]
+struct OtlpExporters {
+    metrics: opentelemetry_otlp::MetricExporter,
+    spans: opentelemetry_otlp::SpanExporter,
+}
+
+#[cfg(feature = "otlp")]
+#[derive(Default)]
+struct OtlpInstruments {
+    counters: HashMap<String, opentelemetry::metrics::Counter<u64>>,
+    gauges: HashMap<String, opentelemetry::metrics::Gauge<f64>>,
+    histograms: HashMap<String, opentelemetry::metrics::Histogram<f64>>,
+}
+
+#[cfg(feature = "otlp")]
+impl OtlpMetrics {
+    /// Creates a backend that will export to the collector at `endpoint`,
+    /// such as `http://localhost:4318`, as the service `service_name`.
+    pub fn new(endpoint: impl Into<String>, service_name: impl Into<String>) -> Self {
+        use opentelemetry::metrics::MeterProvider;
+
+        let resource = opentelemetry_sdk::Resource::builder()
+            .with_service_name(service_name.into())
+            .build();
+
+        let reader = Arc::new(opentelemetry_sdk::metrics::ManualReader::default());
+        let meter_provider = opentelemetry_sdk::metrics::SdkMeterProvider::builder()
+            .with_resource(resource.clone())
+            .with_reader(OtlpReader(reader.clone()))
+            .build();
+
+        let spans = Arc::new(OtlpSpanBuffer::default());
+        let tracer_provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+            .with_resource(resource.clone())
+            .with_span_processor(OtlpSpanProcessor(spans.clone()))
+            .build();
+
+        Self {
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            process_time_buckets: DEFAULT_PROCESS_TIME_BUCKETS.to_vec(),
+            meter: meter_provider.meter("carbon"),
+            meter_provider,
+            reader,
+            tracer_provider,
+            resource,
+            spans,
+            exporters: std::sync::OnceLock::new(),
+            instruments: Mutex::new(OtlpInstruments::default()),
+        }
+    }
+
+    /// Sets the histogram buckets for processing times, in milliseconds.
+    ///
+    /// The buckets of the `_process_time_nanoseconds` histograms are derived
+    /// from the same values.
+    pub fn with_process_time_buckets(mut self, buckets: Vec<f64>) -> Self {
+        self.process_time_buckets = buckets;
+        self
+    }
+
+    /// Returns a `tracing` layer that records spans for export by this
+    /// backend.
+    ///
+    /// Install the layer in the `tracing` subscriber of the application to
+    /// export the spans of the pipeline, along with any other spans.
+    pub fn tracing_layer<S>(
+        &self,
+    ) -> tracing_opentelemetry::OpenTelemetryLayer<S, opentelemetry_sdk::trace::SdkTracer>
+    where
+        S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+    {
+        use opentelemetry::trace::TracerProvider;
+
+        tracing_opentelemetry::layer().with_tracer(self.tracer_provider.tracer("carbon"))
+    }
+
+    fn attributes(labels: &[(&str, &str)]) -> Vec<opentelemetry::KeyValue> {
+        labels
+            .iter()
+            .map(|(key, value)| opentelemetry::KeyValue::new(key.to_string(), value.to_string()))
+            .collect()
+    }
+}
+
+#[cfg(feature = "otlp")]
+#[async_trait]
+impl Metrics for OtlpMetrics {
+    async fn initialize(&self) -> CarbonResult<()> {
+        use {
+            crate::error::Error, opentelemetry_otlp::WithExportConfig,
+            opentelemetry_sdk::trace::SpanExporter,
+        };
+
+        if self.exporters.get().is_some() {
+            return Err(Error::Custom(
+                "otlp metrics are already initialized".to_string(),
+            ));
+        }
+
+        let metrics = opentelemetry_otlp::MetricExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/metrics", self.endpoint))
+            .build()
+            .map_err(|e| Error::fatal("failed to build otlp metric exporter", e))?;
+
+        let mut spans = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/traces", self.endpoint))
+            .build()
+            .map_err(|e| Error::fatal("failed to build otlp span exporter", e))?;
+        spans.set_resource(&self.resource);
+
+        let _ = self.exporters.set(OtlpExporters { metrics, spans });
+
+        log::info!("exporting otlp metrics and traces to {}", self.endpoint);
+        Ok(())
+    }
+
+    async fn flush(&self) -> CarbonResult<()> {
+        use {
+            crate::error::Error,
+            opentelemetry_sdk::{
+                metrics::{
+                    data::ResourceMetrics, exporter::PushMetricExporter, reader::MetricReader,
+                },
+                trace::SpanExporter,
+            },
+        };
+
+        let Some(exporters) = self.exporters.get() else {
+            return Err(Error::Custom(
+                "otlp metrics are not initialized".to_string(),
+            ));
+        };
+
+        let mut resource_metrics = ResourceMetrics::default();
+        self.reader
+            .collect(&mut resource_metrics)
+            .map_err(|e| Error::transient("failed to collect otlp metrics", e))?;
+        exporters
+            .metrics
+            .export(&resource_metrics)
+            .await
+            .map_err(|e| Error::transient("failed to export otlp metrics", e))?;
+
+        let dropped = self.spans.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            log::warn!(
+                "dropped {} otlp spans that did not fit in the buffer.",
+                dropped
+            );
+        }
+
+        let spans = std::mem::take(&mut *lock(&self.spans.spans));
+        if !spans.is_empty() {
+            exporters
+                .spans
+                .export(spans)
+                .await
+                .map_err(|e| Error::transient("failed to export otlp spans", e))?;
+        }
+        Ok(())
+    }
+
+    async fn shutdown(&self) -> CarbonResult<()> {
+        use crate::error::Error;
+
+        self.flush().await?;
+
+        self.meter_provider
+            .shutdown()
+            .map_err(|e| Error::transient("failed to shut down otlp metrics", e))?;
+        self.tracer_provider
+            .shutdown()
+            .map_err(|e| Error::transient("failed to shut down otlp traces", e))
+    }
+
+    async fn update_gauge(&self, name: &str, value: f64) -> CarbonResult<()> {
+        self.update_gauge_with_labels(name, &[], value).await
+    }
+
+    async fn increment_counter(&self, name: &str, value: u64) -> CarbonResult<()> {
+        self.increment_counter_with_labels(name, &[], value).await
+    }
+
+    async fn record_histogram(&self, name: &str, value: f64) -> CarbonResult<()> {
+        self.record_histogram_with_labels(name, &[], value).await
+    }
+
+    async fn update_gauge_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) -> CarbonResult<()> {
+        lock(&self.instruments)
+            .gauges
+            .entry(name.to_string())
+            .or_insert_with(|| self.meter.f64_gauge(name.to_string()).build())
+            .record(value, &Self::attributes(labels));
+        Ok(())
+    }
+
+    async fn increment_counter_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: u64,
+    ) -> CarbonResult<()> {
+        lock(&self.instruments)
+            .counters
+            .entry(name.to_string())
+            .or_insert_with(|| self.meter.u64_counter(name.to_string()).build())
+            .add(value, &Self::attributes(labels));
+        Ok(())
+    }
+
+    async fn record_histogram_with_labels(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) -> CarbonResult<()> {
+        lock(&self.instruments)
+            .histograms
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                let histogram = self.meter.f64_histogram(name.to_string());
+                if name.ends_with("_process_time_milliseconds") {
+                    histogram.with_boundaries(self.process_time_buckets.clone())
+                } else if name.ends_with("_process_time_nanoseconds") {
+                    histogram.with_boundaries(
+                        self.process_time_buckets
+                            .iter()
+                            .map(|bucket| bucket * 1_000_000.0)
+                            .collect(),
+                    )
+                } else {
+                    histogram
+                }
+                .build()
+            })
+            .record(value, &Self::attributes(labels));
+        Ok(())
+    }
+}
+
+/// Lets `OtlpMetrics` collect from the `ManualReader` it hands to its meter
+/// provider.
+#[cfg(feature = "otlp")]
+#[derive(Debug)]
+struct OtlpReader(Arc<opentelemetry_sdk::metrics::ManualReader>);
+
+#[cfg(feature = "otlp")]
+impl opentelemetry_sdk::metrics::reader::MetricReader for OtlpReader {
+    fn register_pipeline(&self, pipeline: std::sync::Weak<opentelemetry_sdk::metrics::Pipeline>) {
+        self.0.register_pipeline(pipeline)
+    }
+
+    fn collect(
+        &self,
+        resource_metrics: &mut opentelemetry_sdk::metrics::data::ResourceMetrics,
+    ) -> opentelemetry_sdk::error::OTelSdkResult {
+        self.0.collect(resource_metrics)
+    }
+
+    fn force_flush(&self) -> opentelemetry_sdk::error::OTelSdkResult {
+        self.0.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> opentelemetry_sdk::error::OTelSdkResult {
+        self.0.shutdown_with_timeout(timeout)
+    }
+
+    fn temporality(
+        &self,
+        kind: opentelemetry_sdk::metrics::InstrumentKind,
+    ) -> opentelemetry_sdk::metrics::Temporality {
+        self.0.temporality(kind)
+    }
+}
+
+/// The finished spans buffered until `OtlpMetrics` is flushed, and the number
+/// of spans dropped because the buffer was full.
+#[cfg(feature = "otlp")]
+#[derive(Debug, Default)]
+struct OtlpSpanBuffer {
+    spans: Mutex<Vec<opentelemetry_sdk::trace::SpanData>>,
+    dropped: AtomicU64,
+}
+
+/// Buffers finished spans until `OtlpMetrics` is flushed.
+#[cfg(feature = "otlp")]
+#[derive(Debug)]
+struct OtlpSpanProcessor(Arc<OtlpSpanBuffer>);
+
+#[cfg(feature = "otlp")]
+impl opentelemetry_sdk::trace::SpanProcessor for OtlpSpanProcessor {
+    fn on_start(&self, _span: &mut opentelemetry_sdk::trace::Span, _cx: &opentelemetry::Context) {}
+
+    fn on_end(&self, span: opentelemetry_sdk::trace::SpanData) {
+        if !span.span_context.is_sampled() {
+            return;
+        }
+
+        let mut spans = lock(&self.0.spans);
+        if spans.len() < OTLP_MAX_BUFFERED_SPANS {
+            spans.push(span);
+        } else {
+            self.0.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn force_flush(&self) -> opentelemetry_sdk::error::OTelSdkResult {
+        Ok(())
+    }
+
+    fn shutdown_with_timeout(&self, _timeout: Duration) -> opentelemetry_sdk::error::OTelSdkResult {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn in_memory_metrics_record_every_kind_of_metric() {
+        let metrics = InMemoryMetrics::new();
+
+        metrics.increment_counter("updates", 2).await.unwrap();
+        metrics.increment_counter("updates", 3).await.unwrap();
+        metrics.update_gauge("queued", 4.0).await.unwrap();
+        metrics.update_gauge("queued", 1.0).await.unwrap();
+        for sample in [3.0, 1.0, 2.0] {
+            metrics.record_histogram("latency", sample).await.unwrap();
+        }
+
+        assert_eq!(metrics.counter("updates"), 5);
+        assert_eq!(metrics.counter("missing"), 0);
+        assert_eq!(metrics.gauge("queued"), Some(1.0));
+        assert_eq!(metrics.gauge("missing"), None);
+        assert_eq!(metrics.histogram("latency"), vec![3.0, 1.0, 2.0]);
+        assert_eq!(
+            metrics.histogram_summary("latency"),
+            Some(HistogramSummary {
+                count: 3,
+                sum: 6.0,
+                min: 1.0,
+                max: 3.0,
+                mean: 2.0,
+            })
+        );
+        assert_eq!(metrics.histogram_summary("missing"), None);
+
+        metrics.clear();
+        assert_eq!(metrics.counter("updates"), 0);
+        assert_eq!(metrics.gauge("queued"), None);
+        assert!(metrics.histogram("latency").is_empty());
+    }
+
+    #[tokio::test]
+    async fn in_memory_metrics_tell_labels_apart() {
+        let metrics = InMemoryMetrics::new();
+        let rpc = [("datasource", "rpc")];
+        let ws = [("datasource", "ws")];
+
+        metrics.increment_counter("restarts", 1).await.unwrap();
+        metrics
+            .increment_counter_with_labels("restarts", &rpc, 2)
+            .await
+            .unwrap();
+        metrics
+            .update_gauge_with_labels("slot_lag", &ws, 5.0)
+            .await
+            .unwrap();
+
+        assert_eq!(metrics.counter("restarts"), 1);
+        assert_eq!(metrics.counter_with_labels("restarts", &rpc), 2);
+        assert_eq!(metrics.counter_with_labels("restarts", &ws), 0);
+        assert_eq!(metrics.gauge("slot_lag"), None);
+        assert_eq!(metrics.gauge_with_labels("slot_lag", &ws), Some(5.0));
+    }
+
+    #[tokio::test]
+    async fn in_memory_metrics_receive_reported_handles() {
+        let metrics = Arc::new(InMemoryMetrics::new());
+        let collection = MetricsCollection::new(vec![metrics.clone()]);
+
+        let counter = collection.counter("updates_processed");
+        counter.increment(2);
+        counter.increment(1);
+        collection.gauge("updates_queued").set(7.0);
+        collection
+            .histogram_with_labels("latency", &[("datasource", "rpc")])
+            .record(4.0);
+        assert_eq!(metrics.counter("updates_processed"), 0);
+
+        collection.flush_metrics().await.unwrap();
+        assert_eq!(metrics.counter("updates_processed"), 3);
+        assert_eq!(metrics.gauge("updates_queued"), Some(7.0));
+        assert_eq!(
+            metrics.histogram_with_labels("latency", &[("datasource", "rpc")]),
+            vec![4.0]
+        );
+
+        collection.flush_metrics().await.unwrap();
+        assert_eq!(metrics.counter("updates_processed"), 3);
+    }
+
+    #[tokio::test]
+    async fn histogram_samples_past_the_queue_are_dropped() {
+        let metrics = Arc::new(InMemoryMetrics::new());
+        let collection = MetricsCollection::new(vec![metrics.clone()]);
+
+        let histogram = collection.histogram("latency");
+        for value in 0..HISTOGRAM_MAX_PENDING_SAMPLES + 3 {
+            histogram.record(value as f64);
+        }
+        assert_eq!(histogram.0.dropped.load(Ordering::Relaxed), 3);
+
+        collection.flush_metrics().await.unwrap();
+        let samples = metrics.histogram("latency");
+        assert_eq!(samples.len(), HISTOGRAM_MAX_PENDING_SAMPLES);
+        assert_eq!(
+            samples.last(),
+            Some(&((HISTOGRAM_MAX_PENDING_SAMPLES - 1) as f64))
+        );
+        assert_eq!(histogram.0.dropped.load(Ordering::Relaxed), 0);
+
+        histogram.record(1.0);
+        collection.flush_metrics().await.unwrap();
+        assert_eq!(
+            metrics.histogram("latency").len(),
+            HISTOGRAM_MAX_PENDING_SAMPLES + 1
+        );
+    }
+
+    async fn statsd_agent() -> (tokio::net::UdpSocket, std::net::SocketAddr) {
+        let agent = tokio::net::UdpSocket::bind(("127.0.0.1", 0)).await.unwrap();
+        let address = agent.local_addr().unwrap();
+        (agent, address)
+    }
+
+    async fn receive_packet(agent: &tokio::net::UdpSocket) -> String {
+        let mut packet = vec![0; 65_536];
+        let length = tokio::time::timeout(Duration::from_secs(5), agent.recv(&mut packet))
+            .await
+            .unwrap()
+            .unwrap();
+        String::from_utf8(packet[..length].to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn statsd_sends_tagged_metrics_to_the_agent() {
+        let (agent, address) = statsd_agent().await;
+        let statsd = StatsdMetrics::new(address)
+            .with_prefix("indexer")
+            .with_tag("env", "test");
+
+        statsd
+            .increment_counter("updates_processed", 3)
+            .await
+            .unwrap();
+        assert!(statsd.flush().await.is_err());
+        statsd.initialize().await.unwrap();
+        statsd
+            .update_gauge_with_labels("slot_lag", &[("datasource", "rpc:1")], -2.0)
+            .await
+            .unwrap();
+        statsd.flush().await.unwrap();
+
+        assert_eq!(
+            receive_packet(&agent).await,
+            "indexer.updates_processed:3|c|#env:test\n\
+             indexer.slot_lag:0|g|#env:test,datasource:rpc_1\n\
+             indexer.slot_lag:-2|g|#env:test,datasource:rpc_1"
+        );
+    }
+
+    #[tokio::test]
+    async fn statsd_splits_packets_at_the_maximum_size() {
+        let (agent, address) = statsd_agent().await;
+        let statsd = StatsdMetrics::new(address).with_max_packet_size(24);
+        statsd.initialize().await.unwrap();
+
+        for _ in 0..3 {
+            statsd.increment_counter("updates", 1).await.unwrap();
+        }
+        statsd.flush().await.unwrap();
+
+        assert_eq!(receive_packet(&agent).await, "updates:1|c\nupdates:1|c");
+        assert_eq!(receive_packet(&agent).await, "updates:1|c");
+    }
+
+    #[tokio::test]
+    async fn statsd_keeps_metrics_that_failed_to_send() {
+        let (agent, address) = statsd_agent().await;
+        let statsd = StatsdMetrics::new(address).with_max_packet_size(100);
+        statsd.initialize().await.unwrap();
+
+        // A packet larger than a UDP datagram can carry fails to send.
+        let oversized = "x".repeat(70_000);
+        statsd.increment_counter("sent", 1).await.unwrap();
+        statsd.increment_counter(&oversized, 1).await.unwrap();
+        statsd.increment_counter("unsent", 1).await.unwrap();
+        assert!(statsd.flush().await.is_err());
+
+        assert_eq!(receive_packet(&agent).await, "sent:1|c");
+        let buffer = lock(&statsd.buffer);
+        assert_eq!(buffer.len(), 2);
+        assert!(buffer[0].starts_with("xxx"));
+        assert_eq!(buffer[1], "unsent:1|c");
+    }
+
+    #[tokio::test]
+    async fn statsd_drops_the_oldest_metrics_once_the_buffer_is_full() {
+        let (agent, address) = statsd_agent().await;
+        let statsd = StatsdMetrics::new(address).with_max_buffered_metrics(2);
+        statsd.initialize().await.unwrap();
+
+        for value in 1..=3 {
+            statsd
+                .update_gauge("updates_queued", value as f64)
+                .await
+                .unwrap();
+        }
+        assert_eq!(statsd.dropped.load(Ordering::Relaxed), 1);
+        statsd.flush().await.unwrap();
+
+        assert_eq!(
+            receive_packet(&agent).await,
+            "updates_queued:2|g\nupdates_queued:3|g"
+        );
+        assert_eq!(statsd.dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[cfg(feature = "prometheus")]
+    async fn scrape(address: std::net::SocketAddr) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[tokio::test]
+    async fn prometheus_serves_recorded_metrics() {
+        let port = std::net::TcpListener::bind(("127.0.0.1", 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let address: std::net::SocketAddr = ([127, 0, 0, 1], port).into();
+        let prometheus = PrometheusMetrics::new(address).with_process_time_buckets(vec![1.0, 10.0]);
+
+        prometheus
+            .increment_counter("recorded_before_initialize", 1)
+            .await
+            .unwrap();
+        prometheus.initialize().await.unwrap();
+        assert!(prometheus.initialize().await.is_err());
+
+        prometheus
+            .increment_counter("updates_processed", 3)
+            .await
+            .unwrap();
+        prometheus
+            .increment_counter_with_labels("datasource_restarts", &[("datasource", "rpc")], 2)
+            .await
+            .unwrap();
+        prometheus
+            .update_gauge("updates_queued", 7.0)
+            .await
+            .unwrap();
+        prometheus
+            .record_histogram("updates_process_time_milliseconds", 5.0)
+            .await
+            .unwrap();
+
+        let response = scrape(address).await;
+        assert!(response.contains("updates_processed 3"), "{response}");
+        assert!(response.contains(r#"datasource_restarts{datasource="rpc"} 2"#));
+        assert!(response.contains("updates_queued 7"));
+        assert!(response.contains(r#"updates_process_time_milliseconds_bucket{le="10"} 1"#));
+        assert!(!response.contains("recorded_before_initialize"));
+
+        prometheus.shutdown().await.unwrap();
+    }
+
+    /// The path and body of every request received by `otlp_receiver`.
+    #[cfg(feature = "otlp")]
+    type OtlpRequests = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
+
+    /// Stands in for an OTLP/HTTP collector, recording every request.
+    #[cfg(feature = "otlp")]
+    async fn otlp_receiver() -> (String, OtlpRequests) {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+            .await
+            .unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let requests = OtlpRequests::default();
+        let recorded = requests.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let recorded = recorded.clone();
+                tokio::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    loop {
+                        let mut request_line = String::new();
+                        if stream.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+                            return;
+                        }
+                        let path = request_line.split(' ').nth(1).unwrap().to_string();
+
+                        let mut content_length = 0;
+                        loop {
+                            let mut header = String::new();
+                            stream.read_line(&mut header).await.unwrap();
+                            if header == "\r\n" {
+                                break;
+                            }
+                            if let Some((name, value)) = header.split_once(':') {
+                                if name.eq_ignore_ascii_case("content-length") {
+                                    content_length = value.trim().parse().unwrap();
+                                }
+                            }
+                        }
+
+                        let mut body = vec![0; content_length];
+                        stream.read_exact(&mut body).await.unwrap();
+                        lock(&recorded).push((path, body));
+                        stream
+                            .get_mut()
+                            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                            .await
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        (endpoint, requests)
+    }
+
+    #[cfg(feature = "otlp")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn otlp_exports_metrics_to_the_collector() {
+        let (endpoint, requests) = otlp_receiver().await;
+        let otlp = OtlpMetrics::new(endpoint, "test-indexer");
+
+        assert!(otlp.flush().await.is_err());
+        otlp.initialize().await.unwrap();
+        assert!(otlp.initialize().await.is_err());
+
+        otlp.increment_counter("updates_processed", 3)
+            .await
+            .unwrap();
+        otlp.increment_counter_with_labels("datasource_restarts", &[("datasource", "rpc")], 1)
+            .await
+            .unwrap();
+        otlp.update_gauge("updates_queued", 7.0).await.unwrap();
+        otlp.record_histogram("updates_process_time_milliseconds", 5.0)
+            .await
+            .unwrap();
+        otlp.flush().await.unwrap();
+
+        let requests = lock(&requests).clone();
+        let (_, body) = requests
+            .iter()
+            .find(|(path, _)| path == "/v1/metrics")
+            .expect("no metrics were exported");
+        for expected in [
+            "updates_processed",
+            "datasource_restarts",
+            "rpc",
+            "updates_queued",
+            "updates_process_time_milliseconds",
+            "test-indexer",
+        ] {
+            assert!(
+                body.windows(expected.len())
+                    .any(|window| window == expected.as_bytes()),
+                "{expected} was not exported"
+            );
+        }
+
+        otlp.shutdown().await.unwrap();
+    }
+
+    #[cfg(feature = "otlp")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn otlp_counts_spans_past_the_buffer() {
+        use opentelemetry::trace::{Span, Tracer, TracerProvider};
+
+        let (endpoint, requests) = otlp_receiver().await;
+        let otlp = OtlpMetrics::new(endpoint, "test-indexer");
+        otlp.initialize().await.unwrap();
+
+        let tracer = otlp.tracer_provider.tracer("test");
+        for _ in 0..OTLP_MAX_BUFFERED_SPANS + 2 {
+            tracer.start("update").end();
+        }
+        assert_eq!(lock(&otlp.spans.spans).len(), OTLP_MAX_BUFFERED_SPANS);
+        assert_eq!(otlp.spans.dropped.load(Ordering::Relaxed), 2);
+
+        otlp.flush().await.unwrap();
+        assert!(lock(&otlp.spans.spans).is_empty());
+        assert_eq!(otlp.spans.dropped.load(Ordering::Relaxed), 0);
+        assert!(lock(&requests).iter().any(|(path, _)| path == "/v1/traces"));
+
+        otlp.shutdown().await.unwrap();
+    }
 }
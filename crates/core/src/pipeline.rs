@@ -30,8 +30,13 @@
 //! # Fields and Configuration
 //!
 //! - **datasources**: A list of `Datasource` objects that act as the sources
-//!   for account and transaction data.
+//!   for account and transaction data, each identified by a `DatasourceId`.
 //! - **account_pipes**: A collection of pipes for processing account updates.
+//!   Like every other pipe, each one carries the `ErrorPolicy` that decides
+//!   what happens when it fails.
+//! - **block_pipes**: Pipes responsible for handling block-level data.
+//! - **slot_status_pipes**: Pipes responsible for handling slot status
+//!   updates.
 //! - **account_deletion_pipes**: Pipes responsible for handling account
 //!   deletion events.
 //! - **instruction_pipes**: Used to process instructions within transactions.
@@ -40,6 +45,29 @@
 //!   on performance data.
 //! - **metrics_flush_interval**: Specifies how frequently metrics are flushed.
 //!   Defaults to 5 seconds if unset.
+//! - **channel_capacity**: The maximum number of updates queued between the
+//!   datasources and the pipeline. Unbounded if unset.
+//! - **channel_overflow_policy**: What happens to new updates once the queue
+//!   is full. Defaults to blocking the datasources.
+//! - **workers**: Optional worker pipelines that process updates concurrently,
+//!   each with its own set of pipes. Updates are sharded across workers by
+//!   key so that updates for the same key keep their order.
+//! - **datasource_restart_policy**: An optional `RestartPolicy` under which
+//!   datasources that fail are restarted with exponential backoff.
+//! - **stall_policies**: Per-datasource `StallPolicy`s that detect datasources
+//!   which silently stop delivering updates.
+//! - **checkpointer**: An optional `Checkpointer` that persists the highest
+//!   fully processed slot of each datasource and provides the resume point
+//!   when the pipeline restarts.
+//! - **deduplicator**: An optional `Deduplicator` that drops copies of updates
+//!   delivered by more than one datasource.
+//! - **finalized_only**: When set, updates are held back until their slot is
+//!   rooted, and discarded if their slot dies. Otherwise, pipes are asked to
+//!   roll back the slots that datasources report as dead.
+//! - **dead_letter_sink**: An optional `DeadLetterSink` receiving the updates
+//!   that pipes with `ErrorPolicy::DeadLetter` failed to process. Dead letters
+//!   kept in a `DeadLetterStore` can be fed back through
+//!   `Pipeline::replay_dead_letters`.
 //!
 //! ## Notes
 //!
@@ -49,6 +77,23 @@
 //!   wrappers ensuring safe, shared access.
 //! - Proper metric collection and flushing are essential for monitoring
 //!   pipeline performance, especially in production environments.
+//! - Every pipe has a name, given at registration or derived from its
+//!   position, such as `account_0`. Besides the pipeline-wide metrics, each
+//!   pipe records `pipe_updates_successful`, `pipe_updates_failed` and its
+//!   processing time in `pipe_process_time_nanoseconds`, labeled with the
+//!   name of the pipe as `pipe`.
+//! - Updates are stamped with the time their datasource sent them into the
+//!   update channel. For every update, the pipeline records how far behind
+//!   the chain its datasource is, labeled with the datasource id:
+//!   - `slot_lag`: The distance between the slot of the update and the tip of
+//!     the chain, the highest slot reported by a slot status update from any
+//!     datasource. Until a slot status update arrives, the tip is the highest
+//!     slot received from any datasource.
+//!   - `block_time_lag_seconds`: The time since the block time of the update,
+//!     for transactions and blocks that carry one.
+//!   - `updates_queue_wait_time_nanoseconds` and
+//!     `updates_queue_wait_time_milliseconds`: The time between sending the
+//!     update into the channel and starting to process it.
 
 use {
     crate::{
@@ -56,25 +101,43 @@ use {
             AccountDecoder, AccountMetadata, AccountPipe, AccountPipes, AccountProcessorInputType,
         },
         account_deletion::{AccountDeletionPipe, AccountDeletionPipes},
+        block::{BlockDetails, BlockPipe, BlockPipes},
+        channel::{self, OverflowPolicy},
+        checkpoint::{Checkpoint, Checkpointer},
         collection::InstructionDecoderCollection,
-        datasource::{AccountDeletion, Datasource, Update},
-        error::CarbonResult,
+        datasource::{AccountDeletion, Datasource, TransactionUpdate, Update},
+        dead_letter::{DeadLetter, DeadLetterSink, DeadLetterStore},
+        dedup::{DedupWindow, Deduplicator, DEFAULT_DEDUP_MAX_ENTRIES},
+        error::{CarbonResult, Error, ErrorContext},
         instruction::{
             InstructionDecoder, InstructionPipe, InstructionPipes, InstructionProcessorInputType,
             InstructionsWithMetadata, NestedInstructions,
         },
-        metrics::{Metrics, MetricsCollection},
+        layer::{Layer, LayerContext, Next, ProcessCall, ProcessFuture},
+        metrics::{Counter, Gauge, Histogram, Metrics, MetricsCollection},
         processor::Processor,
         schema::TransactionSchema,
-        transaction::{TransactionPipe, TransactionPipes, TransactionProcessorInputType},
+        slot::{SlotStatus, SlotStatusPipe, SlotStatusPipes, SlotStatusUpdate},
+        transaction::{
+            TransactionMetadata, TransactionPipe, TransactionPipes, TransactionProcessorInputType,
+        },
         transformers,
     },
     core::time,
     serde::de::DeserializeOwned,
-    std::{convert::TryInto, sync::Arc, time::Instant},
+    solana_sdk::{pubkey::Pubkey, signature::Signature},
+    std::{
+        collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, HashMap, HashSet},
+        convert::TryInto,
+        hash::{Hash, Hasher},
+        sync::{Arc, Mutex},
+        time::{Instant, SystemTime},
+    },
     tokio_util::sync::CancellationToken,
 };
 
+pub use crate::datasource::DatasourceId;
+
 /// Defines the shutdown behavior for the pipeline.
 ///
 /// `ShutdownStrategy` determines how the pipeline will behave when it receives
@@ -97,7 +160,7 @@ use {
 ///
 /// - `ProcessPending` is the default variant, enabling the pipeline to ensure
 ///   that no updates are lost during shutdown.
-#[derive(Default, PartialEq, Debug)]
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum ShutdownStrategy {
     /// Stop the whole pipeline immediately.
     Immediate,
@@ -106,686 +169,3378 @@ pub enum ShutdownStrategy {
     ProcessPending,
 }
 
-/// Represents the primary data processing pipeline in the `carbon-core`
-/// framework.
+/// Defines what happens when a pipe fails to process an update.
 ///
-/// The `Pipeline` struct is responsible for orchestrating the flow of data from
-/// various sources, processing it through multiple pipes (for accounts,
-/// transactions, instructions, and account deletions), and recording metrics at
-/// each stage. This flexible design allows for customized data processing,
-/// handling a variety of update types with minimal boilerplate code.
+/// Every pipe carries its own `ErrorPolicy`, set through
+/// `PipelineBuilder::error_policy` when the pipe is registered. A failing pipe
+/// never prevents the remaining pipes from processing the update, unless its
+/// policy halts the pipeline.
 ///
-/// ## Overview
+/// # Variants
 ///
-/// A `Pipeline` instance includes collections of data sources and processing
-/// pipes, enabling users to configure the pipeline to handle diverse types of
-/// blockchain-related data. Each pipe is responsible for decoding, processing,
-/// and routing specific data types, while the metrics system records relevant
-/// statistics.
+/// - `Skip`: Logs the error and moves on. This is the default.
+/// - `Retry`: Runs the pipe again up to `max_retries` times, waiting
+///   `initial_backoff` before the first retry and doubling the wait after
+///   every further attempt, up to `max_backoff`. If every attempt fails, or
+///   the pipe fails with an error that is not retryable, the update is
+///   skipped.
+/// - `DeadLetter`: Sends the update to the pipeline's `DeadLetterSink`.
+/// - `Halt`: Stops the pipeline immediately. `Pipeline::run` then returns an
+///   `Error::PipelineHalted`.
 ///
-/// ### Key Concepts
+/// # Example
 ///
-/// - **Datasources**: These provide the raw data, such as account updates,
-///   transaction details, and account deletions.
-/// - **Pipes**: Modular units that handle specific data types:
-///   - `AccountPipes` for account updates.
-///   - `AccountDeletionPipes` for account deletions.
-///   - `InstructionPipes` for instruction data within transactions.
-///   - `TransactionPipes` for entire transaction payloads.
-/// - **Metrics**: Collect performance data, enabling real-time insights and
-///   efficient monitoring.
+/// ```rust
+/// let builder = PipelineBuilder::new()
+///     .error_policy(ErrorPolicy::Retry {
+///         max_retries: 5,
+///         initial_backoff: Duration::from_millis(100),
+///         max_backoff: Duration::from_secs(10),
+///     })
+///     .account(TokenProgramDecoder, TokenAccountProcessor)
+///     .error_policy(ErrorPolicy::Halt)
+///     .transaction(LedgerProcessor, None);
+/// ```
 ///
-/// ## Fields
+/// # Notes
 ///
-/// - `datasources`: A vector of data sources (`Datasource` implementations)
-///   that provide the data for processing. Each data source must be wrapped in
-///   an `Arc` for safe, concurrent access.
-/// - `account_pipes`: A vector of `AccountPipes`, each responsible for handling
-///   account updates.
-/// - `account_deletion_pipes`: A vector of `AccountDeletionPipes` to handle
-///   deletion events.
-/// - `instruction_pipes`: A vector of `InstructionPipes` for processing
-///   instructions within transactions. These pipes work with nested
-///   instructions and are generically defined to support varied instruction
-///   types.
-/// - `transaction_pipes`: A vector of `TransactionPipes` responsible for
-///   processing complete transaction payloads.
-/// - `metrics`: A vector of `Metrics` implementations to record and track
-///   performance data. Each metrics instance is managed within an `Arc` to
-///   ensure thread safety.
-/// - `metrics_flush_interval`: An optional interval, in seconds, defining how
-///   frequently metrics should be flushed. If `None`, the default interval is
-///   used.
+/// - Updates that failed in a pipe with `Skip`, `Retry` or `DeadLetter` still
+///   count as processed, so checkpoints move past them.
+/// - Every failure is counted in the `pipe_errors` counter.
+/// - Before the policy is applied, the error is given the context of the
+///   failure: the pipe, the datasource, and the slot, signature or account
+///   of the update.
+#[derive(Default, Clone, PartialEq, Eq, Debug)]
+pub enum ErrorPolicy {
+    #[default]
+    Skip,
+    Retry {
+        max_retries: u32,
+        initial_backoff: time::Duration,
+        max_backoff: time::Duration,
+    },
+    DeadLetter,
+    Halt,
+}
+
+impl ErrorPolicy {
+    /// Returns how long to wait before retrying after `attempt` failed
+    /// retries, or `None` if the pipe should not be run again.
+    fn retry_delay(&self, attempt: u32) -> Option<time::Duration> {
+        match self {
+            ErrorPolicy::Retry {
+                max_retries,
+                initial_backoff,
+                max_backoff,
+            } if attempt < *max_retries => {
+                Some(backoff_delay(*initial_backoff, *max_backoff, attempt))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Defines how failed datasources are restarted.
 ///
-/// ## Example
+/// Without a restart policy, a datasource whose `consume` returns an error
+/// stays stopped for the rest of the run. With one, the pipeline restarts it,
+/// resuming from its latest committed checkpoint when a `Checkpointer` is
+/// configured. A datasource that fails with an error that is not retryable
+/// is treated as having used up its restarts.
 ///
-/// ```rust
+/// # Fields
 ///
-/// carbon_core::pipeline::Pipeline::builder()
-/// .datasource(transaction_crawler)
-/// .metrics(Arc::new(LogMetrics::new()))
-/// .metrics(Arc::new(PrometheusMetrics::new()))
-/// .instruction(
-///    TestProgramDecoder,
-///    TestProgramProcessor
-/// )
-/// .account(
-///     TestProgramDecoder,
-///     TestProgramAccountProcessor
-/// )
-/// .transaction(TEST_SCHEMA.clone(), TestProgramTransactionProcessor)
-/// .account_deletions(TestProgramAccountDeletionProcessor)
-/// .build()?
-/// .run()
-/// .await?;
-/// ```
+/// - `max_restarts`: How many times each datasource may be restarted.
+/// - `initial_backoff`: The wait before the first restart. It doubles with
+///   every further restart, up to `max_backoff`.
+/// - `max_backoff`: The longest wait between restarts.
+/// - `fail_pipeline`: Whether the pipeline stops with an error once a
+///   datasource has used up its restarts. Otherwise the datasource stays
+///   stopped and the pipeline keeps running.
+/// - `reset_after`: How long a datasource has to run before failing for its
+///   restarts and backoff to start over, so that failures far apart do not
+///   use up `max_restarts` over the lifetime of the pipeline. A run counts
+///   until the datasource last sent an update, so a datasource that stalls
+///   or keeps failing without delivering anything never resets.
 ///
-/// ## Notes
+/// # Example
 ///
-/// - Ensure that each data source and pipe implements the required traits, such
-///   as `Datasource`, `AccountPipes`, and `Metrics`, as appropriate.
-/// - The pipeline is designed for concurrent operation, utilizing `Arc` and
-///   `Box` types to handle shared ownership and trait object storage.
-/// - The `metrics_flush_interval` controls how frequently the pipeline's
-///   metrics are flushed. If `None`, a default interval (usually 5 seconds) is
-///   used.
-pub struct Pipeline {
-    pub datasources: Vec<Arc<dyn Datasource + Send + Sync>>,
-    pub account_pipes: Vec<Box<dyn AccountPipes>>,
-    pub account_deletion_pipes: Vec<Box<dyn AccountDeletionPipes>>,
-    pub instruction_pipes: Vec<Box<dyn for<'a> InstructionPipes<'a>>>,
-    pub transaction_pipes: Vec<Box<dyn for<'a> TransactionPipes<'a>>>,
-    pub metrics: Arc<MetricsCollection>,
-    pub metrics_flush_interval: Option<u64>,
-    pub shutdown_strategy: ShutdownStrategy,
+/// ```rust
+/// let builder = PipelineBuilder::new()
+///     .datasource(MyDatasource::new())
+///     .datasource_restart_policy(RestartPolicy {
+///         max_restarts: 10,
+///         fail_pipeline: true,
+///         ..RestartPolicy::default()
+///     });
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RestartPolicy {
+    pub max_restarts: u32,
+    pub initial_backoff: time::Duration,
+    pub max_backoff: time::Duration,
+    pub fail_pipeline: bool,
+    pub reset_after: time::Duration,
 }
 
-impl Pipeline {
-    /// Creates a new `PipelineBuilder` instance for constructing a `Pipeline`.
-    ///
-    /// The `builder` method returns a `PipelineBuilder` that allows you to
-    /// configure and customize the pipeline components before building the
-    /// final `Pipeline` object. This approach provides a flexible and
-    /// type-safe way to assemble a pipeline by specifying data sources,
-    /// processing pipes, and metrics.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// carbon_core::pipeline::Pipeline::builder()
-    /// .datasource(transaction_crawler)
-    /// .metrics(Arc::new(LogMetrics::new()))
-    /// .metrics(Arc::new(PrometheusMetrics::new()))
-    /// .instruction(
-    ///    TestProgramDecoder,
-    ///    TestProgramProcessor
-    /// )
-    /// // ...
-    /// ```
-    ///
-    /// # Returns
-    ///
-    /// Returns a `PipelineBuilder` instance with empty collections for data
-    /// sources, pipes, and metrics. You can then configure each component
-    /// using the builder pattern.
-    pub fn builder() -> PipelineBuilder {
-        log::trace!("Pipeline::builder()");
-        PipelineBuilder {
-            datasources: Vec::new(),
-            account_pipes: Vec::new(),
-            account_deletion_pipes: Vec::new(),
-            instruction_pipes: Vec::new(),
-            transaction_pipes: Vec::new(),
-            metrics: MetricsCollection::default(),
-            metrics_flush_interval: None,
-            shutdown_strategy: ShutdownStrategy::default(),
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            initial_backoff: time::Duration::from_secs(1),
+            max_backoff: time::Duration::from_secs(60),
+            fail_pipeline: false,
+            reset_after: time::Duration::from_secs(600),
         }
     }
+}
 
-    /// Runs the `Pipeline`, processing updates from data sources and handling
-    /// metrics.
-    ///
-    /// The `run` method initializes the pipeline’s metrics system and starts
-    /// listening for updates from the configured data sources. It checks
-    /// the types of updates provided by each data source to ensure that the
-    /// required data types are available for processing. The method then
-    /// enters a loop where it processes each update received from the data
-    /// sources in turn, logging and updating metrics based on the success
-    /// or failure of each operation.
-    ///
-    /// # How it Works
-    ///
-    /// - Initializes metrics and sets up an interval for periodic metric
-    ///   flushing.
-    /// - Spawns tasks for each data source to continuously consume updates.
-    /// - Processes updates according to their type (e.g., Account, Transaction,
-    ///   or AccountDeletion).
-    /// - Records performance metrics such as update processing times, and
-    ///   tracks success and failure counts.
-    ///
-    /// # Errors
-    ///
-    /// The method returns an `Err` variant if:
-    /// - Required update types (e.g., `AccountUpdate`, `AccountDeletion`,
-    ///   `Transaction`) are not provided by any data source, causing a mismatch
-    ///   in expected data processing capabilities.
-    /// - A data source encounters an error while consuming updates.
-    /// - An error occurs during metrics flushing or processing of updates.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use carbon_core::Pipeline;
-    ///
-    /// let mut pipeline = Pipeline::builder()
-    ///     .datasource(MyDatasource::new())
-    ///     .metrics(MyMetrics::new())
-    ///     .build()
-    ///     .expect("Failed to build pipeline");
-    ///
-    /// // Running the pipeline asynchronously
-    /// tokio::spawn(async move {
-    ///     if let Err(e) = pipeline.run().await {
-    ///         eprintln!("Pipeline run error: {:?}", e);
-    ///     }
-    /// });
-    /// ```
-    ///
-    /// # Notes
-    ///
-    /// - This method is asynchronous and should be awaited within a Tokio
-    ///   runtime environment.
-    /// - The pipeline monitors metrics and flushes them based on the configured
-    ///   `metrics_flush_interval`.
-    /// - The `run` method operates in an infinite loop, handling updates until
-    ///   a termination condition occurs.
-    pub async fn run(&mut self) -> CarbonResult<()> {
-        log::info!("starting pipeline. num_datasources: {}, num_metrics: {}, num_account_pipes: {}, num_account_deletion_pipes: {}, num_instruction_pipes: {}, num_transaction_pipes: {}",
-            self.datasources.len(),
-            self.metrics.metrics.len(),
-            self.account_pipes.len(),
-            self.account_deletion_pipes.len(),
-            self.instruction_pipes.len(),
-            self.transaction_pipes.len(),
-        );
+/// Defines how a datasource that stops delivering updates is detected and
+/// handled.
+///
+/// A datasource is considered stalled when the pipeline has not received any
+/// update from it for `timeout`. Every stall is counted in the
+/// `datasource_stalls` counter and logged as a warning before `action` is
+/// taken.
+///
+/// # Fields
+///
+/// - `timeout`: How long a datasource may go without delivering an update.
+/// - `action`: What happens once the datasource is stalled.
+///
+/// # Example
+///
+/// ```rust
+/// let builder = PipelineBuilder::new()
+///     .stall_policy(StallPolicy {
+///         timeout: Duration::from_secs(30),
+///         action: StallAction::Restart,
+///     })
+///     .datasource(MyDatasource::new());
+/// ```
+///
+/// # Notes
+///
+/// - Any update counts as activity, including slot status updates, which
+///   makes them a natural heartbeat for datasources whose other updates are
+///   infrequent.
+/// - Activity is recorded when the datasource sends an update into the
+///   pipeline's channel. A datasource waiting for room in a full channel
+///   counts as active, so a slow pipeline does not make it look stalled.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StallPolicy {
+    pub timeout: time::Duration,
+    pub action: StallAction,
+}
 
-        log::trace!("run(self)");
+/// What happens when a datasource has stalled.
+///
+/// # Variants
+///
+/// - `Warn`: Only the warning and the metric are emitted, after which the
+///   datasource is given another `timeout`.
+/// - `Restart`: The datasource is cancelled and restarted under the
+///   pipeline's `RestartPolicy`, or under `RestartPolicy::default()` if none
+///   is set.
+/// - `Fail`: The pipeline stops with `Error::DatasourceStalled`.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StallAction {
+    #[default]
+    Warn,
+    Restart,
+    Fail,
+}
 
-        self.metrics.initialize_metrics().await?;
-        let (update_sender, mut update_receiver) = tokio::sync::mpsc::unbounded_channel::<Update>();
+/// Runs a datasource, restarting it as allowed by the `RestartPolicy`.
+struct DatasourceSupervisor {
+    datasource_id: DatasourceId,
+    datasource: Arc<dyn Datasource + Send + Sync>,
+    sender: channel::UpdateSender<(Update, DatasourceId)>,
+    cancellation_token: CancellationToken,
+    metrics: Arc<MetricsCollection>,
+    checkpointer: Option<Arc<dyn Checkpointer>>,
+    restart_policy: Option<RestartPolicy>,
+    stall_policy: Option<StallPolicy>,
+    activity: Arc<channel::SenderActivity>,
+    shutdown_sender: Arc<tokio::sync::watch::Sender<Option<ShutdownStrategy>>>,
+    fatal_error: Arc<Mutex<Option<Error>>>,
+}
 
-        let datasource_cancellation_token = CancellationToken::new();
+impl DatasourceSupervisor {
+    async fn run(self, mut resume_from: Option<Checkpoint>) {
+        let mut restarts = 0;
 
-        for datasource in &self.datasources {
-            let datasource_cancellation_token_clone = datasource_cancellation_token.clone();
-            let sender_clone = update_sender.clone();
-            let datasource_clone = Arc::clone(datasource);
-            let metrics_collection = self.metrics.clone();
-
-            tokio::spawn(async move {
-                if let Err(e) = datasource_clone
-                    .consume(
-                        &sender_clone,
-                        datasource_cancellation_token_clone,
-                        metrics_collection,
-                    )
-                    .await
-                {
-                    log::error!("error consuming datasource: {:?}", e);
-                }
-            });
-        }
+        loop {
+            let started_at = tokio::time::Instant::now();
+            let result = self.consume(resume_from.take()).await;
 
-        let mut interval = tokio::time::interval(time::Duration::from_secs(
-            self.metrics_flush_interval.unwrap_or(5),
-        ));
+            let Err(error) = result else {
+                return;
+            };
+            log::error!(
+                "error consuming datasource {}: {:?}",
+                self.datasource_id,
+                error
+            );
 
-        loop {
-            tokio::select! {
-                _ = tokio::signal::ctrl_c() => {
-                    log::trace!("received SIGINT, shutting down.");
-                    datasource_cancellation_token.cancel();
-
-                    if self.shutdown_strategy == ShutdownStrategy::Immediate {
-                        log::info!("shutting down the pipeline immediately.");
-                        self.metrics.flush_metrics().await?;
-                        self.metrics.shutdown_metrics().await?;
-                        break;
-                    } else {
-                        log::info!("shutting down the pipeline after processing pending updates.");
-                    }
+            if self.cancellation_token.is_cancelled() || lock(&self.fatal_error).is_some() {
+                return;
+            }
+            let Some(restart_policy) = &self.restart_policy else {
+                return;
+            };
+            let ran_for = started_at.elapsed().saturating_sub(self.activity.idle());
+            if restarts > 0 && ran_for >= restart_policy.reset_after {
+                log::info!(
+                    "datasource {} ran for {:?} before failing, resetting its restarts.",
+                    self.datasource_id,
+                    ran_for
+                );
+                restarts = 0;
+            }
+
+            if restarts >= restart_policy.max_restarts || !error.is_retryable() {
+                if error.is_retryable() {
+                    log::error!(
+                        "datasource {} failed after {} restarts, giving up.",
+                        self.datasource_id,
+                        restarts
+                    );
+                } else {
+                    log::error!(
+                        "datasource {} failed with an error that is not retryable, giving up.",
+                        self.datasource_id
+                    );
                 }
-                _ = interval.tick() => {
-                    self.metrics.flush_metrics().await?;
+
+                if restart_policy.fail_pipeline {
+                    *lock(&self.fatal_error) = Some(Error::FailedToConsumeDatasource(format!(
+                        "datasource {} exhausted its restarts: {}",
+                        self.datasource_id, error
+                    )));
+                    self.shutdown_sender
+                        .send_replace(Some(ShutdownStrategy::Immediate));
                 }
-                update = update_receiver.recv() => {
-                    match update {
-                        Some(update) => {
-                            self
-                                .metrics.increment_counter("updates_received", 1)
-                                .await?;
-
-                            let start = Instant::now();
-                            let process_result = self.process(update.clone()).await;
-                            let time_taken_nanoseconds = start.elapsed().as_nanos();
-                            let time_taken_milliseconds = time_taken_nanoseconds / 1_000_000;
-
-                            self
-                                .metrics
-                                .record_histogram("updates_process_time_nanoseconds", time_taken_nanoseconds as f64)
-                                .await?;
-
-                            self
-                                .metrics
-                                .record_histogram("updates_process_time_milliseconds", time_taken_milliseconds as f64)
-                                .await?;
-
-                            match process_result {
-                                Ok(_) => {
-                                    self
-                                        .metrics.increment_counter("updates_successful", 1)
-                                        .await?;
-
-                                    log::trace!("processed update")
-                                }
-                                Err(error) => {
-                                    log::error!("error processing update ({:?}): {:?}", update, error);
-                                    self.metrics.increment_counter("updates_failed", 1).await?;
-                                }
-                            };
+                return;
+            }
 
-                            self
-                                .metrics.increment_counter("updates_processed", 1)
-                                .await?;
+            let delay = backoff_delay(
+                restart_policy.initial_backoff,
+                restart_policy.max_backoff,
+                restarts,
+            );
+            log::warn!(
+                "restarting datasource {} in {:?}.",
+                self.datasource_id,
+                delay
+            );
+            tokio::select! {
+                _ = self.cancellation_token.cancelled() => return,
+                _ = tokio::time::sleep(delay) => {}
+            }
+            restarts += 1;
 
-                            self
-                                .metrics.update_gauge("updates_queued", update_receiver.len() as f64)
-                                .await?;
-                        }
-                        None => {
-                            log::info!("update_receiver closed, shutting down.");
-                            self.metrics.flush_metrics().await?;
-                            self.metrics.shutdown_metrics().await?;
-                            break;
-                        }
-                    }
+            self.metrics
+                .counter_with_labels(
+                    "datasource_restarts",
+                    &[("datasource", self.datasource_id.as_str())],
+                )
+                .increment(1);
+
+            if let Some(checkpointer) = &self.checkpointer {
+                match checkpointer.load(self.datasource_id.as_str()).await {
+                    Ok(checkpoint) => resume_from = checkpoint,
+                    Err(error) => log::error!(
+                        "failed to load checkpoint for datasource {}: {:?}",
+                        self.datasource_id,
+                        error
+                    ),
                 }
             }
         }
+    }
 
-        log::info!("pipeline shutdown complete.");
+    /// Runs `consume` once, watching the datasource for stalls if a
+    /// `StallPolicy` is set.
+    async fn consume(&self, resume_from: Option<Checkpoint>) -> CarbonResult<()> {
+        let consume_cancellation_token = self.cancellation_token.child_token();
+        let consume = self.datasource.consume(
+            self.datasource_id.clone(),
+            &self.sender,
+            resume_from,
+            consume_cancellation_token.clone(),
+            self.metrics.clone(),
+        );
 
-        Ok(())
-    }
+        let Some(stall_policy) = &self.stall_policy else {
+            return consume.await;
+        };
 
-    /// Processes a single update and routes it through the appropriate pipeline
-    /// stages.
-    ///
-    /// The `process` method takes an `Update` and determines its type, then
-    /// routes it through the corresponding pipes for handling account
-    /// updates, transactions, or account deletions. It also records metrics
-    /// for processed updates, providing insights into the processing
-    /// workload and performance.
-    ///
-    /// ## Functionality
-    ///
-    /// - **Account Updates**: Passes account updates through the
-    ///   `account_pipes`. Each pipe processes the account metadata and the
-    ///   updated account state.
-    /// - **Transaction Updates**: Extracts transaction metadata and
-    ///   instructions, nests them if needed, and routes them through
-    ///   `instruction_pipes` and `transaction_pipes`.
-    /// - **Account Deletions**: Sends account deletion events through the
-    ///   `account_deletion_pipes`.
-    ///
-    /// The method also updates metrics counters for each type of update,
-    /// tracking how many updates have been processed in each category.
-    ///
-    /// # Parameters
-    ///
-    /// - `update`: An `Update` variant representing the type of data received.
-    ///   This can be an `Account`, `Transaction`, or `AccountDeletion`, each
-    ///   triggering different processing logic within the pipeline.
-    ///
-    /// # Returns
-    ///
-    /// Returns a `CarbonResult<()>`, indicating `Ok(())` on successful
-    /// processing or an error if processing fails at any stage.
-    ///
-    /// # Notes
-    ///
-    /// - This method is asynchronous and should be awaited within a Tokio
-    ///   runtime.
-    /// - Each type of update (account, transaction, account deletion) requires
-    ///   its own set of pipes, so ensure that appropriate pipes are configured
-    ///   based on the data types expected from the data sources.
-    /// - Metrics are recorded after each successful processing stage to track
-    ///   processing volumes and identify potential bottlenecks in real-time.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if any of the pipes fail during processing, or if an
-    /// issue arises while incrementing counters or updating metrics. Handle
-    /// errors gracefully to ensure continuous pipeline operation.
-    async fn process(&mut self, update: Update) -> CarbonResult<()> {
-        log::trace!("process(self, update: {:?})", update);
-        match update {
-            Update::Account(account_update) => {
-                let account_metadata = AccountMetadata {
-                    slot: account_update.slot,
-                    pubkey: account_update.pubkey,
-                };
+        self.activity.touch();
+        tokio::pin!(consume);
 
-                for pipe in self.account_pipes.iter_mut() {
-                    pipe.run(
-                        (account_metadata.clone(), account_update.account.clone()),
-                        self.metrics.clone(),
-                    )
-                    .await?;
-                }
+        loop {
+            let remaining = stall_policy.timeout.saturating_sub(self.activity.idle());
 
-                self.metrics
-                    .increment_counter("account_updates_processed", 1)
-                    .await?;
+            tokio::select! {
+                result = &mut consume => return result,
+                _ = tokio::time::sleep(remaining) => {}
             }
-            Update::Transaction(transaction_update) => {
-                let transaction_metadata = &(*transaction_update).clone().try_into()?;
 
-                let instructions_with_metadata: InstructionsWithMetadata =
-                    transformers::extract_instructions_with_metadata(
-                        transaction_metadata,
-                        &transaction_update,
-                    )?;
+            let idle = self.activity.idle();
+            if idle < stall_policy.timeout {
+                continue;
+            }
 
-                let nested_instructions: NestedInstructions = instructions_with_metadata.into();
+            log::warn!(
+                "datasource {} delivered no updates for {:?}.",
+                self.datasource_id,
+                idle
+            );
+            self.metrics
+                .counter_with_labels(
+                    "datasource_stalls",
+                    &[("datasource", self.datasource_id.as_str())],
+                )
+                .increment(1);
 
-                for pipe in self.instruction_pipes.iter_mut() {
-                    for nested_instruction in nested_instructions.iter() {
-                        pipe.run(nested_instruction, self.metrics.clone()).await?;
-                    }
+            let error = Error::DatasourceStalled(format!(
+                "datasource {} delivered no updates for {:?}",
+                self.datasource_id, stall_policy.timeout
+            ));
+            match stall_policy.action {
+                StallAction::Warn => {
+                    self.activity.touch();
                 }
-
-                for pipe in self.transaction_pipes.iter_mut() {
-                    pipe.run(
-                        transaction_metadata.clone(),
-                        &nested_instructions,
-                        self.metrics.clone(),
-                    )
-                    .await?;
+                StallAction::Restart => {
+                    consume_cancellation_token.cancel();
+                    return Err(error);
                 }
-
-                self.metrics
-                    .increment_counter("transaction_updates_processed", 1)
-                    .await?;
-            }
-            Update::AccountDeletion(account_deletion) => {
-                for pipe in self.account_deletion_pipes.iter_mut() {
-                    pipe.run(account_deletion.clone(), self.metrics.clone())
-                        .await?;
+                StallAction::Fail => {
+                    consume_cancellation_token.cancel();
+                    *lock(&self.fatal_error) = Some(error);
+                    self.shutdown_sender
+                        .send_replace(Some(ShutdownStrategy::Immediate));
+                    return Err(Error::DatasourceStalled(format!(
+                        "datasource {} stopped the pipeline",
+                        self.datasource_id
+                    )));
                 }
-
-                self.metrics
-                    .increment_counter("account_deletions_processed", 1)
-                    .await?;
             }
-        };
-
-        Ok(())
+        }
     }
 }
 
-/// A builder for constructing a `Pipeline` instance with customized data
-/// sources, processing pipes, and metrics.
+/// A pipe registered with the pipeline, together with its name, its
+/// `ErrorPolicy` and its layers.
 ///
-/// The `PipelineBuilder` struct offers a flexible way to assemble a `Pipeline`
-/// by allowing configuration of its components, such as data sources, account
-/// and transaction pipes, deletion handling, and metrics. Using the builder
-/// pattern, you can add the desired elements incrementally and then finalize
-/// with a call to `build`.
+/// # Fields
 ///
-/// ## Overview
+/// - `name`: The name of the pipe, used in logs, metrics and dead letters.
+/// - `pipe`: The pipe itself.
+/// - `error_policy`: What happens when the pipe fails.
+/// - `layers`: The `Layer`s the pipe runs in, outermost first.
+pub struct PipeEntry<P: ?Sized> {
+    pub name: String,
+    pub pipe: Box<P>,
+    pub error_policy: ErrorPolicy,
+    pub layers: Vec<Arc<dyn Layer>>,
+    metrics: Option<PipeMetrics>,
+}
+
+impl<P: ?Sized> PipeEntry<P> {
+    pub fn new(name: impl Into<String>, pipe: Box<P>, error_policy: ErrorPolicy) -> Self {
+        Self {
+            name: name.into(),
+            pipe,
+            error_policy,
+            layers: Vec::new(),
+            metrics: None,
+        }
+    }
+
+    /// Runs the pipe in `layers`, outermost first.
+    pub fn with_layers(mut self, layers: Vec<Arc<dyn Layer>>) -> Self {
+        self.layers = layers;
+        self
+    }
+}
+
+/// Handles to the metrics recorded for every run of a pipe.
 ///
-/// The `PipelineBuilder` supports the following components:
-/// - **Datasources**: Sources of data updates, such as account information and
-///   transactions.
-/// - **Account Pipes**: For processing account updates from data sources.
-/// - **Account Deletion Pipes**: For handling account deletion updates.
-/// - **Instruction Pipes**: For handling instructions associated with
-///   transactions.
-/// - **Transaction Pipes**: For handling full transaction data.
-/// - **Metrics**: Collects and reports performance data, such as update
-///   processing times.
-/// - **Metrics Flush Interval**: Optional interval defining how often to flush
-///   metrics data.
+/// The metrics are `pipe_updates_successful`, `pipe_updates_failed`,
+/// `pipe_process_time_nanoseconds` and `pipe_process_time_milliseconds`, each
+/// labeled with the name of the pipe as `pipe`.
+struct PipeMetrics {
+    updates_successful: Counter,
+    updates_failed: Counter,
+    process_time_nanoseconds: Histogram,
+    process_time_milliseconds: Histogram,
+}
+
+impl PipeMetrics {
+    fn new(metrics: &MetricsCollection, pipe: &str) -> Self {
+        let labels = [("pipe", pipe)];
+
+        Self {
+            updates_successful: metrics.counter_with_labels("pipe_updates_successful", &labels),
+            updates_failed: metrics.counter_with_labels("pipe_updates_failed", &labels),
+            process_time_nanoseconds: metrics
+                .histogram_with_labels("pipe_process_time_nanoseconds", &labels),
+            process_time_milliseconds: metrics
+                .histogram_with_labels("pipe_process_time_milliseconds", &labels),
+        }
+    }
+
+    /// Records the outcome and processing time of one run of the pipe.
+    fn record(&self, time_taken: time::Duration, successful: bool) {
+        if successful {
+            self.updates_successful.increment(1);
+        } else {
+            self.updates_failed.increment(1);
+        }
+        self.process_time_nanoseconds
+            .record(time_taken.as_nanos() as f64);
+        self.process_time_milliseconds
+            .record(time_taken.as_millis() as f64);
+    }
+}
+
+/// Handles to the metrics the pipeline records for every update.
+struct PipelineMetrics {
+    updates_received: Counter,
+    updates_queued: Gauge,
+    updates_spilled: Gauge,
+    update_channel_capacity: Gauge,
+    updates_dropped: Counter,
+    updates_awaiting_finality: Gauge,
+    updates_rolled_back: Counter,
+    updates_deduplicated: Counter,
+    updates_processed: Counter,
+    updates_successful: Counter,
+    updates_failed: Counter,
+    updates_process_time_nanoseconds: Histogram,
+    updates_process_time_milliseconds: Histogram,
+    account_updates_processed: Counter,
+    transaction_updates_processed: Counter,
+    account_deletions_processed: Counter,
+    block_updates_processed: Counter,
+    slot_status_updates_processed: Counter,
+    slots_rolled_back: Counter,
+    pipe_errors: Counter,
+    dead_letters: Counter,
+    dead_letters_replayed: Counter,
+    datasources: HashMap<DatasourceId, DatasourceMetrics>,
+}
+
+impl PipelineMetrics {
+    fn new(metrics: &MetricsCollection) -> Self {
+        Self {
+            updates_received: metrics.counter("updates_received"),
+            updates_queued: metrics.gauge("updates_queued"),
+            updates_spilled: metrics.gauge("updates_spilled"),
+            update_channel_capacity: metrics.gauge("update_channel_capacity"),
+            updates_dropped: metrics.counter("updates_dropped"),
+            updates_awaiting_finality: metrics.gauge("updates_awaiting_finality"),
+            updates_rolled_back: metrics.counter("updates_rolled_back"),
+            updates_deduplicated: metrics.counter("updates_deduplicated"),
+            updates_processed: metrics.counter("updates_processed"),
+            updates_successful: metrics.counter("updates_successful"),
+            updates_failed: metrics.counter("updates_failed"),
+            updates_process_time_nanoseconds: metrics.histogram("updates_process_time_nanoseconds"),
+            updates_process_time_milliseconds: metrics
+                .histogram("updates_process_time_milliseconds"),
+            account_updates_processed: metrics.counter("account_updates_processed"),
+            transaction_updates_processed: metrics.counter("transaction_updates_processed"),
+            account_deletions_processed: metrics.counter("account_deletions_processed"),
+            block_updates_processed: metrics.counter("block_updates_processed"),
+            slot_status_updates_processed: metrics.counter("slot_status_updates_processed"),
+            slots_rolled_back: metrics.counter("slots_rolled_back"),
+            pipe_errors: metrics.counter("pipe_errors"),
+            dead_letters: metrics.counter("dead_letters"),
+            dead_letters_replayed: metrics.counter("dead_letters_replayed"),
+            datasources: HashMap::new(),
+        }
+    }
+
+    /// Returns the lag metrics of `datasource_id`, registering them on first
+    /// use.
+    fn datasource(
+        &mut self,
+        metrics: &MetricsCollection,
+        datasource_id: &DatasourceId,
+    ) -> &DatasourceMetrics {
+        self.datasources
+            .entry(datasource_id.clone())
+            .or_insert_with(|| DatasourceMetrics::new(metrics, datasource_id))
+    }
+}
+
+/// Handles to the lag metrics the pipeline records for the updates of a
+/// datasource, labeled with the datasource id.
+struct DatasourceMetrics {
+    slot_lag: Gauge,
+    block_time_lag_seconds: Gauge,
+    queue_wait_time_nanoseconds: Histogram,
+    queue_wait_time_milliseconds: Histogram,
+}
+
+impl DatasourceMetrics {
+    fn new(metrics: &MetricsCollection, datasource_id: &DatasourceId) -> Self {
+        let labels = [("datasource", datasource_id.as_str())];
+
+        Self {
+            slot_lag: metrics.gauge_with_labels("slot_lag", &labels),
+            block_time_lag_seconds: metrics.gauge_with_labels("block_time_lag_seconds", &labels),
+            queue_wait_time_nanoseconds: metrics
+                .histogram_with_labels("updates_queue_wait_time_nanoseconds", &labels),
+            queue_wait_time_milliseconds: metrics
+                .histogram_with_labels("updates_queue_wait_time_milliseconds", &labels),
+        }
+    }
+
+    /// Records how far `update` lags behind `tip_slot`, the tip of the chain,
+    /// and the block time, and how long it waited since it was sent.
+    fn record(&self, update: &Update, sent_at: SystemTime, tip_slot: u64) {
+        let (slot, _) = update_position(update);
+        self.slot_lag.set(tip_slot.saturating_sub(slot) as f64);
+
+        let block_time = match update {
+            Update::Transaction(transaction_update) => transaction_update.block_time,
+            Update::Block(block_details) => block_details.block_time,
+            _ => None,
+        };
+        if let Some(block_time) = block_time {
+            let now = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64();
+            self.block_time_lag_seconds.set(now - block_time as f64);
+        }
+
+        let queue_wait_time = SystemTime::now()
+            .duration_since(sent_at)
+            .unwrap_or_default();
+        self.queue_wait_time_nanoseconds
+            .record(queue_wait_time.as_nanos() as f64);
+        self.queue_wait_time_milliseconds
+            .record(queue_wait_time.as_millis() as f64);
+    }
+}
+
+/// A run of a pipe on the arguments of an update, repeatable by layers.
+struct PipeCall<'a, P: ?Sized, A> {
+    pipe: &'a mut P,
+    args: A,
+    run: for<'b> fn(&'b mut P, &'b A) -> ProcessFuture<'b>,
+}
+
+#[async_trait::async_trait]
+impl<P: ?Sized + Send, A: Send + Sync> ProcessCall for PipeCall<'_, P, A> {
+    async fn call(&mut self) -> CarbonResult<()> {
+        (self.run)(self.pipe, &self.args).await
+    }
+}
+
+/// A pipe failure that outlived the retries allowed by its `ErrorPolicy`.
 ///
-/// Each component can be added through method chaining, enhancing code
-/// readability and maintainability.
+/// `instruction` is the position of the failed instruction within the
+/// transaction, for failures of instruction pipes.
+struct PipeFailure {
+    pipe: String,
+    instruction: Option<usize>,
+    error_policy: ErrorPolicy,
+    error: Error,
+}
+
+/// Returns the slot, signature and account of `update` as an `ErrorContext`.
+fn update_context(update: &Update) -> ErrorContext {
+    match update {
+        Update::Account(account_update) => ErrorContext {
+            slot: Some(account_update.slot),
+            pubkey: Some(account_update.pubkey),
+            ..ErrorContext::default()
+        },
+        Update::Transaction(transaction_update) => ErrorContext {
+            slot: Some(transaction_update.slot),
+            signature: Some(transaction_update.signature),
+            ..ErrorContext::default()
+        },
+        Update::AccountDeletion(account_deletion) => ErrorContext {
+            slot: Some(account_deletion.slot),
+            pubkey: Some(account_deletion.pubkey),
+            ..ErrorContext::default()
+        },
+        Update::Block(block_details) => ErrorContext {
+            slot: Some(block_details.slot),
+            ..ErrorContext::default()
+        },
+        Update::SlotStatus(slot_status) => ErrorContext {
+            slot: Some(slot_status.slot),
+            ..ErrorContext::default()
+        },
+    }
+}
+
+/// Runs a pipe entry on `args` through its layers, retrying it as allowed by
+/// its `ErrorPolicy`, and records the pipe's metrics.
+macro_rules! run_pipe {
+    ($entry:expr, $pipe:expr, $metrics:expr, $args:expr, $run:expr) => {{
+        let context = LayerContext {
+            pipe: $pipe,
+            metrics: $metrics,
+        };
+        let mut call = PipeCall {
+            pipe: &mut *$entry.pipe,
+            args: $args,
+            run: $run,
+        };
+        let start = Instant::now();
+        let result = run_with_retries!(
+            $entry.error_policy,
+            Next::new(&$entry.layers, &context, &mut call).run()
+        );
+        if let Some(pipe_metrics) = &$entry.metrics {
+            pipe_metrics.record(start.elapsed(), result.is_ok());
+        }
+        result
+    }};
+}
+
+/// Awaits a pipe call, retrying it as allowed by the `ErrorPolicy`.
+macro_rules! run_with_retries {
+    ($error_policy:expr, $call:expr) => {{
+        let mut attempt = 0;
+        loop {
+            match $call.await {
+                Ok(()) => break Ok(()),
+                Err(error) => match $error_policy
+                    .retry_delay(attempt)
+                    .filter(|_| error.is_retryable())
+                {
+                    Some(delay) => {
+                        log::warn!(
+                            "pipe failed on attempt {}, retrying in {:?}: {:?}",
+                            attempt + 1,
+                            delay,
+                            error
+                        );
+                        attempt += 1;
+                        tokio::time::sleep(delay).await;
+                    }
+                    None => break Err(error),
+                },
+            }
+        }
+    }};
+}
+
+/// A handle for shutting down a running `Pipeline` from code.
+///
+/// `PipelineHandle` is obtained from [`Pipeline::handle`] before the pipeline
+/// is moved into its task. It is cheap to clone and can be used from any task,
+/// for example from a test or from an orchestrator's health endpoint.
 ///
 /// # Example
 ///
 /// ```rust
-/// carbon_core::pipeline::Pipeline::builder()
-/// .datasource(transaction_crawler)
-/// .metrics(Arc::new(LogMetrics::new()))
-/// .metrics(Arc::new(PrometheusMetrics::new()))
-/// .instruction(
-///    TestProgramDecoder,
-///    TestProgramProcessor
-/// )
-/// // ...
-/// ```
+/// let mut pipeline = Pipeline::builder()
+///     .datasource(MyDatasource::new())
+///     .shutdown_drain_timeout(Duration::from_secs(30))
+///     .build()?;
+/// let handle = pipeline.handle();
 ///
-/// # Fields
+/// let pipeline_task = tokio::spawn(async move { pipeline.run().await });
 ///
-/// - `datasources`: A collection of `Datasource` objects wrapped in `Arc` for
-///   shared ownership across threads. Each `Datasource` provides updates to the
-///   pipeline.
-/// - `account_pipes`: A collection of `AccountPipes` to handle account updates.
-/// - `account_deletion_pipes`: A collection of `AccountDeletionPipes` for
-///   processing account deletions.
-/// - `instruction_pipes`: A collection of `InstructionPipes` to process
-///   instructions in transactions.
-/// - `transaction_pipes`: A collection of `TransactionPipes` to process full
-///   transaction data.
-/// - `metrics`: A vector of `Metrics` implementations for tracking pipeline
-///   performance.
-/// - `metrics_flush_interval`: An optional interval (in seconds) for flushing
-///   metrics data. If not set, a default flush interval will be used.
+/// handle.shutdown(ShutdownStrategy::ProcessPending);
+/// pipeline_task.await??;
+/// ```
+#[derive(Clone)]
+pub struct PipelineHandle {
+    shutdown_sender: Arc<tokio::sync::watch::Sender<Option<ShutdownStrategy>>>,
+}
+
+impl PipelineHandle {
+    /// Requests the pipeline to shut down using the given strategy.
+    ///
+    /// With `ShutdownStrategy::ProcessPending`, the datasources are stopped
+    /// and the pipeline returns from `run` once the pending updates have been
+    /// processed or the drain timeout has elapsed. A later request with
+    /// `ShutdownStrategy::Immediate` stops the pipeline without waiting for
+    /// the remaining updates.
+    ///
+    /// If the pipeline is not running yet, it shuts down as soon as it starts.
+    pub fn shutdown(&self, shutdown_strategy: ShutdownStrategy) {
+        log::trace!("shutdown(self, shutdown_strategy: {:?})", shutdown_strategy);
+        self.shutdown_sender.send_replace(Some(shutdown_strategy));
+    }
+}
+
+/// Listens for the process signals that shut down the pipeline: SIGINT on all
+/// platforms and SIGTERM on Unix.
+struct ShutdownSignal {
+    #[cfg(unix)]
+    terminate: Option<tokio::signal::unix::Signal>,
+}
+
+impl ShutdownSignal {
+    fn new() -> Self {
+        Self {
+            #[cfg(unix)]
+            terminate: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .map_err(|error| log::warn!("failed to listen for SIGTERM: {:?}", error))
+                .ok(),
+        }
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(terminate) = self.terminate.as_mut() {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Tracks which slots of each datasource have been fully processed.
 ///
-/// # Returns
+/// A slot is fully processed once the datasource has moved on to a later
+/// slot and every update of that slot or an earlier one has been processed
+/// successfully. Updates are registered with `begin` when the pipeline
+/// receives them and with `complete` once all pipes have run, which may happen
+/// out of order when workers are used.
+#[derive(Default)]
+struct CheckpointTracker {
+    datasources: HashMap<DatasourceId, DatasourceProgress>,
+}
+
+#[derive(Default)]
+struct DatasourceProgress {
+    highest_slot_received: u64,
+    in_flight: BTreeMap<u64, usize>,
+    lowest_failed_slot: Option<u64>,
+    last_signature: Option<(u64, Signature)>,
+    committed_slot: Option<u64>,
+}
+
+impl CheckpointTracker {
+    fn begin(&mut self, datasource_id: &DatasourceId, slot: u64) {
+        let progress = self.datasources.entry(datasource_id.clone()).or_default();
+        progress.highest_slot_received = progress.highest_slot_received.max(slot);
+        *progress.in_flight.entry(slot).or_default() += 1;
+    }
+
+    fn complete(
+        &mut self,
+        datasource_id: &DatasourceId,
+        slot: u64,
+        signature: Option<Signature>,
+        success: bool,
+    ) {
+        let Some(progress) = self.datasources.get_mut(datasource_id) else {
+            return;
+        };
+
+        if let Some(count) = progress.in_flight.get_mut(&slot) {
+            *count -= 1;
+            if *count == 0 {
+                progress.in_flight.remove(&slot);
+            }
+        }
+
+        if !success {
+            if progress.lowest_failed_slot.is_none() {
+                log::warn!(
+                    "update in slot {} from datasource {} failed, holding back its checkpoint.",
+                    slot,
+                    datasource_id
+                );
+            }
+            progress.lowest_failed_slot = Some(
+                progress
+                    .lowest_failed_slot
+                    .map_or(slot, |failed| failed.min(slot)),
+            );
+        } else if let Some(signature) = signature {
+            if progress
+                .last_signature
+                .is_none_or(|(last_slot, _)| slot >= last_slot)
+            {
+                progress.last_signature = Some((slot, signature));
+            }
+        }
+    }
+
+    /// Returns the checkpoints that advanced since they were last committed.
+    fn uncommitted(&self) -> Vec<(DatasourceId, Checkpoint)> {
+        self.datasources
+            .iter()
+            .filter_map(|(datasource_id, progress)| {
+                let mut slot = progress.highest_slot_received.checked_sub(1)?;
+                if let Some((&lowest_in_flight, _)) = progress.in_flight.first_key_value() {
+                    slot = slot.min(lowest_in_flight.checked_sub(1)?);
+                }
+                if let Some(lowest_failed_slot) = progress.lowest_failed_slot {
+                    slot = slot.min(lowest_failed_slot.checked_sub(1)?);
+                }
+
+                if progress
+                    .committed_slot
+                    .is_some_and(|committed| committed >= slot)
+                {
+                    return None;
+                }
+
+                let signature = progress
+                    .last_signature
+                    .filter(|(signature_slot, _)| *signature_slot == slot)
+                    .map(|(_, signature)| signature);
+
+                Some((datasource_id.clone(), Checkpoint { slot, signature }))
+            })
+            .collect()
+    }
+
+    fn committed(&mut self, datasource_id: &DatasourceId, slot: u64) {
+        if let Some(progress) = self.datasources.get_mut(datasource_id) {
+            progress.committed_slot = Some(slot);
+        }
+    }
+}
+
+/// An update on its way through the pipeline.
 ///
-/// After configuring the builder, call `build` to create a `Pipeline` instance.
-/// The builder will return a `CarbonResult<Pipeline>`, which will either
-/// contain the configured pipeline or an error if configuration failed.
+/// `sent_at` is the time the datasource sent the update into the update
+/// channel, used to measure how long the update waited to be processed.
+#[derive(Clone)]
+struct ReceivedUpdate {
+    update: Update,
+    datasource_id: DatasourceId,
+    sent_at: SystemTime,
+}
+
+/// The tip of the chain as seen by the pipeline, which `slot_lag` is measured
+/// against.
 ///
-/// # Notes
+/// The tip is the highest slot reported by a slot status update. Until one
+/// arrives, it falls back to the highest slot of any update received.
+#[derive(Default)]
+struct ChainTip {
+    slot_status: Option<u64>,
+    received: u64,
+}
+
+impl ChainTip {
+    fn observe(&mut self, update: &Update) {
+        let (slot, _) = update_position(update);
+        if let Update::SlotStatus(_) = update {
+            self.slot_status = Some(self.slot_status.map_or(slot, |tip| tip.max(slot)));
+        }
+        self.received = self.received.max(slot);
+    }
+
+    fn slot(&self) -> u64 {
+        self.slot_status.unwrap_or(self.received)
+    }
+}
+
+/// The number of rooted slots a `FinalityBuffer` remembers, so that updates
+/// arriving after their slot was rooted are released right away.
+const ROOTED_SLOTS_REMEMBERED: usize = 1_000;
+
+/// Holds back updates until their slot is rooted.
 ///
-/// - The builder pattern allows for method chaining, making it easy to
-///   incrementally add components to the `Pipeline`.
-/// - Ensure that each component matches the data and update types expected by
-///   your application.
+/// Updates are grouped by slot. A rooted slot releases its updates, and a slot
+/// reported as dead discards them. Slots below a root are kept until they are
+/// reported either way, since roots from several datasources can arrive out
+/// of order or skip slots. Updates for a recently rooted slot are released as
+/// soon as they arrive.
 #[derive(Default)]
-pub struct PipelineBuilder {
-    pub datasources: Vec<Arc<dyn Datasource + Send + Sync>>,
-    pub account_pipes: Vec<Box<dyn AccountPipes>>,
-    pub account_deletion_pipes: Vec<Box<dyn AccountDeletionPipes>>,
-    pub instruction_pipes: Vec<Box<dyn for<'a> InstructionPipes<'a>>>,
-    pub transaction_pipes: Vec<Box<dyn for<'a> TransactionPipes<'a>>>,
-    pub metrics: MetricsCollection,
-    pub metrics_flush_interval: Option<u64>,
-    pub shutdown_strategy: ShutdownStrategy,
+struct FinalityBuffer {
+    slots: BTreeMap<u64, Vec<ReceivedUpdate>>,
+    rooted: BTreeSet<u64>,
 }
 
-impl PipelineBuilder {
-    /// Creates a new `PipelineBuilder` with empty collections for datasources,
-    /// pipes, and metrics.
+impl FinalityBuffer {
+    /// Buffers `received` or returns the updates that became final and the
+    /// number of updates discarded.
+    fn receive(&mut self, received: ReceivedUpdate) -> (Vec<ReceivedUpdate>, usize) {
+        let Update::SlotStatus(slot_status) = &received.update else {
+            let (slot, _) = update_position(&received.update);
+            if self.rooted.contains(&slot) {
+                return (vec![received], 0);
+            }
+
+            self.slots.entry(slot).or_default().push(received);
+            return (Vec::new(), 0);
+        };
+
+        match slot_status.status {
+            SlotStatus::Rooted => {
+                self.rooted.insert(slot_status.slot);
+                while self.rooted.len() > ROOTED_SLOTS_REMEMBERED {
+                    self.rooted.pop_first();
+                }
+
+                (self.slots.remove(&slot_status.slot).unwrap_or_default(), 0)
+            }
+            SlotStatus::Dead => (
+                Vec::new(),
+                self.slots
+                    .remove(&slot_status.slot)
+                    .map_or(0, |updates| updates.len()),
+            ),
+            SlotStatus::Processed | SlotStatus::Confirmed => (Vec::new(), 0),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.slots.values().map(Vec::len).sum()
+    }
+}
+
+/// The default interval at which checkpoints are committed.
+pub const DEFAULT_CHECKPOINT_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
+/// The default interval at which pipes are flushed.
+pub const DEFAULT_FLUSH_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
+/// The number of updates that can wait for a single worker before the
+/// pipeline waits for that worker to catch up.
+pub const WORKER_QUEUE_CAPACITY: usize = 1_000;
+
+/// A request for a worker to flush its pipes, answered with the result of the
+/// flush.
+type FlushRequest = tokio::sync::oneshot::Sender<CarbonResult<()>>;
+
+/// Asks every worker to flush its pipes and waits until all of them are done.
+///
+/// # Errors
+///
+/// Returns the first flush error, or an error if a worker stopped before
+/// answering.
+async fn flush_workers(
+    flush_senders: &[tokio::sync::mpsc::Sender<FlushRequest>],
+) -> CarbonResult<()> {
+    let mut replies = Vec::with_capacity(flush_senders.len());
+    for flush_sender in flush_senders {
+        let (reply_sender, reply_receiver) = tokio::sync::oneshot::channel();
+        flush_sender
+            .send(reply_sender)
+            .await
+            .map_err(|_| Error::Custom("worker stopped before flushing its pipes".to_string()))?;
+        replies.push(reply_receiver);
+    }
+
+    let mut result = Ok(());
+    for reply in replies {
+        let flushed = reply.await.unwrap_or_else(|_| {
+            Err(Error::Custom(
+                "worker stopped before flushing its pipes".to_string(),
+            ))
+        });
+        result = result.and(flushed);
+    }
+
+    result
+}
+
+/// Selects the key used to assign transaction updates to workers.
+///
+/// When the pipeline runs with workers, every update is assigned to a worker
+/// by its shard key, and updates sharing a key are processed in order by the
+/// same worker. Account updates and account deletions always use the account
+/// pubkey; `TransactionShardKey` decides the key for transactions.
+///
+/// # Variants
+///
+/// - `FeePayer`: Uses the fee payer of the transaction. This is the default
+///   behavior.
+/// - `Custom`: Uses the pubkey returned by the given function, for example a
+///   pool or market address.
+#[derive(Default, Clone)]
+pub enum TransactionShardKey {
+    #[default]
+    FeePayer,
+    Custom(Arc<dyn Fn(&TransactionUpdate) -> Pubkey + Send + Sync>),
+}
+
+impl std::fmt::Debug for TransactionShardKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FeePayer => f.write_str("FeePayer"),
+            Self::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+/// Registers the pipes of a single worker on the given builder.
+///
+/// See [`PipelineBuilder::workers`].
+pub type WorkerPipes = Box<dyn Fn(PipelineBuilder) -> PipelineBuilder + Send + Sync>;
+
+/// Represents the primary data processing pipeline in the `carbon-core`
+/// framework.
+///
+/// The `Pipeline` struct is responsible for orchestrating the flow of data from
+/// various sources, processing it through multiple pipes (for accounts,
+/// transactions, instructions, and account deletions), and recording metrics at
+/// each stage. This flexible design allows for customized data processing,
+/// handling a variety of update types with minimal boilerplate code.
+///
+/// ## Overview
+///
+/// A `Pipeline` instance includes collections of data sources and processing
+/// pipes, enabling users to configure the pipeline to handle diverse types of
+/// blockchain-related data. Each pipe is responsible for decoding, processing,
+/// and routing specific data types, while the metrics system records relevant
+/// statistics.
+///
+/// ### Key Concepts
+///
+/// - **Datasources**: These provide the raw data, such as account updates,
+///   transaction details, and account deletions.
+/// - **Pipes**: Modular units that handle specific data types:
+///   - `AccountPipes` for account updates.
+///   - `AccountDeletionPipes` for account deletions.
+///   - `InstructionPipes` for instruction data within transactions.
+///   - `TransactionPipes` for entire transaction payloads.
+///   - `BlockPipes` for block-level data.
+///   - `SlotStatusPipes` for slot status updates.
+/// - **Metrics**: Collect performance data, enabling real-time insights and
+///   efficient monitoring.
+///
+/// ## Fields
+///
+/// - `datasources`: A vector of data sources (`Datasource` implementations)
+///   that provide the data for processing, each paired with its
+///   `DatasourceId`. Each data source must be wrapped in an `Arc` for safe,
+///   concurrent access.
+/// - `account_pipes`: A vector of `AccountPipes`, each responsible for handling
+///   account updates.
+/// - `account_deletion_pipes`: A vector of `AccountDeletionPipes` to handle
+///   deletion events.
+/// - `instruction_pipes`: A vector of `InstructionPipes` for processing
+///   instructions within transactions. These pipes work with nested
+///   instructions and are generically defined to support varied instruction
+///   types.
+/// - `transaction_pipes`: A vector of `TransactionPipes` responsible for
+///   processing complete transaction payloads.
+/// - `block_pipes`: A vector of `BlockPipes` responsible for processing
+///   block-level data.
+/// - `slot_status_pipes`: A vector of `SlotStatusPipes` responsible for
+///   processing slot status updates.
+/// - `metrics`: A vector of `Metrics` implementations to record and track
+///   performance data. Each metrics instance is managed within an `Arc` to
+///   ensure thread safety.
+/// - `metrics_flush_interval`: An optional interval, in seconds, defining how
+///   frequently metrics should be flushed. If `None`, the default interval is
+///   used.
+/// - `channel_capacity`: An optional limit on the number of updates queued
+///   between the datasources and the pipeline. If `None`, the queue is
+///   unbounded.
+/// - `channel_overflow_policy`: The `OverflowPolicy` applied when the queue
+///   reaches `channel_capacity`.
+/// - `workers`: Worker pipelines holding their own pipes. If empty, updates
+///   are processed one at a time by the pipes of this pipeline.
+/// - `transaction_shard_key`: The `TransactionShardKey` used to assign
+///   transactions to workers.
+/// - `shutdown_drain_timeout`: An optional limit on how long a
+///   `ShutdownStrategy::ProcessPending` shutdown waits for pending updates.
+/// - `checkpointer`: An optional `Checkpointer` used to resume datasources
+///   and to persist their progress.
+/// - `checkpoint_interval`: How often checkpoints are committed.
+/// - `flush_interval`: How often pipes are asked to write out buffered data.
+/// - `deduplicator`: An optional `Deduplicator` that drops duplicate updates
+///   before they are processed.
+/// - `finalized_only`: Whether updates are held back until their slot is
+///   rooted.
+/// - `dead_letter_sink`: An optional `DeadLetterSink` for updates that pipes
+///   with `ErrorPolicy::DeadLetter` failed to process.
+/// - `datasource_restart_policy`: An optional `RestartPolicy` for restarting
+///   failed datasources.
+/// - `stall_policies`: The `StallPolicy` of each datasource that is watched
+///   for stalls.
+///
+/// ## Example
+///
+/// ```rust
+///
+/// carbon_core::pipeline::Pipeline::builder()
+/// .datasource(transaction_crawler)
+/// .metrics(Arc::new(LogMetrics::new()))
+/// .metrics(Arc::new(PrometheusMetrics::new()))
+/// .instruction(
+///    TestProgramDecoder,
+///    TestProgramProcessor
+/// )
+/// .account(
+///     TestProgramDecoder,
+///     TestProgramAccountProcessor
+/// )
+/// .transaction(TestProgramTransactionProcessor, Some(TEST_SCHEMA.clone()))
+/// .account_deletions(TestProgramAccountDeletionProcessor)
+/// .build()?
+/// .run()
+/// .await?;
+/// ```
+///
+/// ## Notes
+///
+/// - Ensure that each data source and pipe implements the required traits, such
+///   as `Datasource`, `AccountPipes`, and `Metrics`, as appropriate.
+/// - The pipeline is designed for concurrent operation, utilizing `Arc` and
+///   `Box` types to handle shared ownership and trait object storage.
+/// - The `metrics_flush_interval` controls how frequently the pipeline's
+///   metrics are flushed. If `None`, a default interval (usually 5 seconds) is
+///   used.
+pub struct Pipeline {
+    pub datasources: Vec<(DatasourceId, Arc<dyn Datasource + Send + Sync>)>,
+    pub account_pipes: Vec<PipeEntry<dyn AccountPipes>>,
+    pub account_deletion_pipes: Vec<PipeEntry<dyn AccountDeletionPipes>>,
+    pub instruction_pipes: Vec<PipeEntry<dyn for<'a> InstructionPipes<'a>>>,
+    pub transaction_pipes: Vec<PipeEntry<dyn for<'a> TransactionPipes<'a>>>,
+    pub block_pipes: Vec<PipeEntry<dyn BlockPipes>>,
+    pub slot_status_pipes: Vec<PipeEntry<dyn SlotStatusPipes>>,
+    pub metrics: Arc<MetricsCollection>,
+    pub metrics_flush_interval: Option<u64>,
+    pub shutdown_strategy: ShutdownStrategy,
+    pub channel_capacity: Option<usize>,
+    pub channel_overflow_policy: OverflowPolicy<(Update, DatasourceId)>,
+    pub workers: Vec<Pipeline>,
+    pub transaction_shard_key: TransactionShardKey,
+    pub shutdown_drain_timeout: Option<time::Duration>,
+    pub checkpointer: Option<Arc<dyn Checkpointer>>,
+    pub checkpoint_interval: time::Duration,
+    pub flush_interval: time::Duration,
+    pub deduplicator: Option<Deduplicator>,
+    pub finalized_only: bool,
+    pub dead_letter_sink: Option<Arc<dyn DeadLetterSink>>,
+    pub datasource_restart_policy: Option<RestartPolicy>,
+    pub stall_policies: HashMap<DatasourceId, StallPolicy>,
+    shutdown_sender: Arc<tokio::sync::watch::Sender<Option<ShutdownStrategy>>>,
+    checkpoint_tracker: Option<Arc<Mutex<CheckpointTracker>>>,
+    finality_buffer: FinalityBuffer,
+    fatal_error: Arc<Mutex<Option<Error>>>,
+    update_metrics: PipelineMetrics,
+    chain_tip: Arc<Mutex<ChainTip>>,
+}
+
+impl Pipeline {
+    /// Creates a new `PipelineBuilder` instance for constructing a `Pipeline`.
+    ///
+    /// The `builder` method returns a `PipelineBuilder` that allows you to
+    /// configure and customize the pipeline components before building the
+    /// final `Pipeline` object. This approach provides a flexible and
+    /// type-safe way to assemble a pipeline by specifying data sources,
+    /// processing pipes, and metrics.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// carbon_core::pipeline::Pipeline::builder()
+    /// .datasource(transaction_crawler)
+    /// .metrics(Arc::new(LogMetrics::new()))
+    /// .metrics(Arc::new(PrometheusMetrics::new()))
+    /// .instruction(
+    ///    TestProgramDecoder,
+    ///    TestProgramProcessor
+    /// )
+    /// // ...
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// Returns a `PipelineBuilder` instance with empty collections for data
+    /// sources, pipes, and metrics. You can then configure each component
+    /// using the builder pattern.
+    pub fn builder() -> PipelineBuilder {
+        log::trace!("Pipeline::builder()");
+        PipelineBuilder {
+            datasources: Vec::new(),
+            account_pipes: Vec::new(),
+            account_deletion_pipes: Vec::new(),
+            instruction_pipes: Vec::new(),
+            transaction_pipes: Vec::new(),
+            block_pipes: Vec::new(),
+            slot_status_pipes: Vec::new(),
+            metrics: MetricsCollection::default(),
+            metrics_flush_interval: None,
+            shutdown_strategy: ShutdownStrategy::default(),
+            channel_capacity: None,
+            channel_overflow_policy: OverflowPolicy::default(),
+            workers: None,
+            transaction_shard_key: TransactionShardKey::default(),
+            shutdown_drain_timeout: None,
+            checkpointer: None,
+            checkpoint_interval: None,
+            flush_interval: None,
+            dedup_window: None,
+            dedup_max_entries: None,
+            finalized_only: false,
+            error_policy: ErrorPolicy::default(),
+            dead_letter_sink: None,
+            datasource_restart_policy: None,
+            stall_policy: None,
+            stall_policies: HashMap::new(),
+            layers: Vec::new(),
+        }
+    }
+
+    /// Returns a `PipelineHandle` that can shut down this pipeline while it is
+    /// running.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let handle = pipeline.handle();
+    /// tokio::spawn(async move { pipeline.run().await });
+    ///
+    /// handle.shutdown(ShutdownStrategy::Immediate);
+    /// ```
+    pub fn handle(&self) -> PipelineHandle {
+        PipelineHandle {
+            shutdown_sender: self.shutdown_sender.clone(),
+        }
+    }
+
+    /// Runs the `Pipeline`, processing updates from data sources and handling
+    /// metrics.
+    ///
+    /// The `run` method initializes the pipeline’s metrics system and starts
+    /// listening for updates from the configured data sources. It checks
+    /// the types of updates provided by each data source to ensure that the
+    /// required data types are available for processing. The method then
+    /// enters a loop where it processes each update received from the data
+    /// sources in turn, logging and updating metrics based on the success
+    /// or failure of each operation.
+    ///
+    /// # How it Works
+    ///
+    /// - Initializes metrics and the processors of all pipes, and sets up an
+    ///   interval for periodic metric flushing.
+    /// - Spawns tasks for each data source to continuously consume updates,
+    ///   restarting failed data sources according to the `RestartPolicy`.
+    /// - Processes updates according to their type (e.g., Account, Transaction,
+    ///   or AccountDeletion).
+    /// - If workers are configured, dispatches each update to the worker
+    ///   selected by its shard key instead of processing it inline. Slot
+    ///   status updates are sent to every worker.
+    /// - In finalized only mode, holds back updates until their slot is
+    ///   rooted.
+    /// - Records performance metrics such as update processing times, and
+    ///   tracks success and failure counts.
+    /// - Flushes the pipes periodically and once more when shutting down, so
+    ///   that processors buffering data write it out, then shuts the
+    ///   processors down.
+    ///
+    /// # Errors
+    ///
+    /// The method returns an `Err` variant if:
+    /// - Required update types (e.g., `AccountUpdate`, `AccountDeletion`,
+    ///   `Transaction`) are not provided by any data source, causing a mismatch
+    ///   in expected data processing capabilities.
+    /// - A data source keeps failing after using up the restarts of a
+    ///   `RestartPolicy` with `fail_pipeline` set.
+    /// - A data source stalls under a `StallPolicy` with `StallAction::Fail`.
+    /// - An error occurs during metrics flushing or processing of updates.
+    /// - A pipe with `ErrorPolicy::Halt` failed, in which case the error is an
+    ///   `Error::PipelineHalted`.
+    /// - A processor fails to initialize or to shut down.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use carbon_core::Pipeline;
+    ///
+    /// let mut pipeline = Pipeline::builder()
+    ///     .datasource(MyDatasource::new())
+    ///     .metrics(MyMetrics::new())
+    ///     .build()
+    ///     .expect("Failed to build pipeline");
+    ///
+    /// // Running the pipeline asynchronously
+    /// tokio::spawn(async move {
+    ///     if let Err(e) = pipeline.run().await {
+    ///         eprintln!("Pipeline run error: {:?}", e);
+    ///     }
+    /// });
+    /// ```
+    ///
+    /// # Notes
+    ///
+    /// - This method is asynchronous and should be awaited within a Tokio
+    ///   runtime environment.
+    /// - The pipeline monitors metrics and flushes them based on the configured
+    ///   `metrics_flush_interval`.
+    /// - The `run` method operates in an infinite loop, handling updates until
+    ///   a termination condition occurs.
+    /// - The pipeline shuts down on SIGINT, on SIGTERM (Unix only), when a
+    ///   shutdown is requested through a `PipelineHandle`, when a pipe with
+    ///   `ErrorPolicy::Halt` fails, or once every datasource has stopped.
+    pub async fn run(&mut self) -> CarbonResult<()> {
+        log::info!("starting pipeline. num_datasources: {}, num_workers: {}, num_metrics: {}, num_account_pipes: {}, num_account_deletion_pipes: {}, num_instruction_pipes: {}, num_transaction_pipes: {}, num_block_pipes: {}, num_slot_status_pipes: {}",
+            self.datasources.len(),
+            self.workers.len(),
+            self.metrics.metrics.len(),
+            self.account_pipes.len(),
+            self.account_deletion_pipes.len(),
+            self.instruction_pipes.len(),
+            self.transaction_pipes.len(),
+            self.block_pipes.len(),
+            self.slot_status_pipes.len(),
+        );
+
+        log::trace!("run(self)");
+
+        self.metrics.initialize_metrics().await?;
+        self.initialize_pipes().await?;
+        for worker in self.workers.iter_mut() {
+            worker.initialize_pipes().await?;
+        }
+        let (update_sender, mut update_receiver) = channel::channel::<(Update, DatasourceId)>(
+            self.channel_capacity,
+            self.channel_overflow_policy.clone(),
+        )?;
+        if let Some(capacity) = update_receiver.capacity() {
+            self.update_metrics
+                .update_channel_capacity
+                .set(capacity as f64);
+        }
+
+        let datasource_cancellation_token = CancellationToken::new();
+
+        for (datasource_id, datasource) in &self.datasources {
+            let resume_from = match &self.checkpointer {
+                Some(checkpointer) => checkpointer.load(datasource_id.as_str()).await?,
+                None => None,
+            };
+            log::info!(
+                "starting datasource {} from checkpoint {:?}",
+                datasource_id,
+                resume_from
+            );
+
+            let stall_policy = self.stall_policies.get(datasource_id).cloned();
+            let restart_policy = self.datasource_restart_policy.clone().or_else(|| {
+                stall_policy
+                    .as_ref()
+                    .filter(|stall_policy| stall_policy.action == StallAction::Restart)
+                    .map(|_| RestartPolicy::default())
+            });
+            let activity = Arc::new(channel::SenderActivity::default());
+
+            let supervisor = DatasourceSupervisor {
+                datasource_id: datasource_id.clone(),
+                datasource: Arc::clone(datasource),
+                sender: update_sender.with_activity(activity.clone()),
+                cancellation_token: datasource_cancellation_token.clone(),
+                metrics: self.metrics.clone(),
+                checkpointer: self.checkpointer.clone(),
+                restart_policy,
+                stall_policy,
+                activity,
+                shutdown_sender: self.shutdown_sender.clone(),
+                fatal_error: self.fatal_error.clone(),
+            };
+
+            tokio::spawn(supervisor.run(resume_from));
+        }
+
+        // Only the datasources hold senders from here on, so the receiver
+        // closes once they have all stopped.
+        drop(update_sender);
+
+        let processing_cancellation_token = CancellationToken::new();
+        let mut worker_senders = Vec::with_capacity(self.workers.len());
+        let mut worker_flush_senders = Vec::with_capacity(self.workers.len());
+        let mut worker_handles = Vec::with_capacity(self.workers.len());
+
+        for mut worker in std::mem::take(&mut self.workers) {
+            let (worker_sender, mut worker_receiver) =
+                tokio::sync::mpsc::channel::<ReceivedUpdate>(WORKER_QUEUE_CAPACITY);
+            let (worker_flush_sender, mut worker_flush_receiver) =
+                tokio::sync::mpsc::channel::<FlushRequest>(1);
+            let processing_cancellation_token_clone = processing_cancellation_token.clone();
+            let mut flush_interval = tokio::time::interval(worker.flush_interval);
+
+            worker_senders.push(worker_sender);
+            worker_flush_senders.push(worker_flush_sender);
+            worker_handles.push(tokio::spawn(async move {
+                loop {
+                    tokio::select! {
+                        biased;
+                        _ = processing_cancellation_token_clone.cancelled() => break,
+                        Some(flush_request) = worker_flush_receiver.recv() => {
+                            let _ = flush_request.send(worker.flush_pipes().await);
+                        }
+                        _ = flush_interval.tick() => {
+                            let _ = worker.flush_pipes().await;
+                        }
+                        received = worker_receiver.recv() => {
+                            let Some(received) = received else {
+                                break;
+                            };
+
+                            worker.process_and_record(received).await;
+                        }
+                    }
+                }
+
+                let flushed = worker.flush_pipes().await;
+                (worker, flushed)
+            }));
+        }
+
+        // Backends are flushed in the background, so that a slow backend
+        // does not hold up processing.
+        let metrics = self.metrics.clone();
+        let metrics_flush_period =
+            time::Duration::from_secs(self.metrics_flush_interval.unwrap_or(5));
+        let metrics_flusher = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(metrics_flush_period);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(error) = metrics.flush_metrics().await {
+                    log::error!("failed to flush metrics: {:?}", error);
+                }
+            }
+        });
+        let mut checkpoint_interval = tokio::time::interval(self.checkpoint_interval);
+        let mut flush_interval = tokio::time::interval(self.flush_interval);
+        let inline = worker_senders.is_empty();
+
+        let mut shutdown_signal = ShutdownSignal::new();
+        let mut shutdown_receiver = self.shutdown_sender.subscribe();
+        if shutdown_receiver.borrow().is_some() {
+            shutdown_receiver.mark_changed();
+        }
+        let mut draining = false;
+        let mut drain_timer = None;
+
+        loop {
+            let shutdown_strategy = tokio::select! {
+                _ = shutdown_signal.recv() => {
+                    log::trace!("received shutdown signal.");
+                    Some(self.shutdown_strategy)
+                }
+                Ok(()) = shutdown_receiver.changed() => {
+                    log::trace!("received shutdown request.");
+                    *shutdown_receiver.borrow_and_update()
+                }
+                _ = processing_cancellation_token.cancelled() => {
+                    log::warn!(
+                        "drain timeout elapsed, abandoning {} pending updates.",
+                        update_receiver.len() + update_receiver.spilled()
+                    );
+                    break;
+                }
+                _ = checkpoint_interval.tick(), if self.checkpointer.is_some() => {
+                    // Checkpoints are taken before the flush, so that they
+                    // only cover updates whose data the flush writes out.
+                    let uncommitted = self.uncommitted_checkpoints();
+                    let flushed = if inline {
+                        self.flush_pipes().await
+                    } else {
+                        flush_workers(&worker_flush_senders).await
+                    };
+
+                    match flushed {
+                        Ok(()) => self.commit_checkpoints(uncommitted).await,
+                        Err(error) => log::warn!(
+                            "holding back checkpoints, pipes failed to flush: {:?}",
+                            error
+                        ),
+                    }
+                    None
+                }
+                _ = flush_interval.tick(), if inline => {
+                    let _ = self.flush_pipes().await;
+                    None
+                }
+                update = update_receiver.recv_with_timestamp() => {
+                    match update {
+                        Some(((update, datasource_id), sent_at)) => {
+                            self.update_metrics.updates_received.increment(1);
+                            lock(&self.chain_tip).observe(&update);
+
+                            let received = ReceivedUpdate {
+                                update,
+                                datasource_id,
+                                sent_at,
+                            };
+
+                            if self.finalized_only {
+                                let slot_status = matches!(received.update, Update::SlotStatus(_))
+                                    .then(|| received.clone());
+                                let (released, discarded) = self.finality_buffer.receive(received);
+
+                                if discarded > 0 {
+                                    self.update_metrics.updates_rolled_back.increment(discarded as u64);
+                                }
+
+                                for received in released.into_iter().chain(slot_status) {
+                                    self.dispatch(received, &worker_senders, &processing_cancellation_token).await;
+                                }
+
+                                self.update_metrics.updates_awaiting_finality.set(self.finality_buffer.len() as f64);
+                            } else {
+                                self.dispatch(received, &worker_senders, &processing_cancellation_token).await;
+                            }
+
+                            let updates_dropped = update_receiver.take_dropped();
+                            if updates_dropped > 0 {
+                                self.update_metrics.updates_dropped.increment(updates_dropped);
+                            }
+
+                            self.update_metrics.updates_queued.set(update_receiver.len() as f64);
+                            self.update_metrics.updates_spilled.set(update_receiver.spilled() as f64);
+
+                            None
+                        }
+                        None => {
+                            log::info!("update_receiver closed, shutting down.");
+                            break;
+                        }
+                    }
+                }
+            };
+
+            if let Some(shutdown_strategy) = shutdown_strategy {
+                datasource_cancellation_token.cancel();
+
+                if shutdown_strategy == ShutdownStrategy::Immediate {
+                    log::info!("shutting down the pipeline immediately.");
+                    processing_cancellation_token.cancel();
+                    break;
+                }
+
+                if !draining {
+                    log::info!("shutting down the pipeline after processing pending updates.");
+                    draining = true;
+
+                    if let Some(drain_timeout) = self.shutdown_drain_timeout {
+                        let processing_cancellation_token = processing_cancellation_token.clone();
+                        drain_timer = Some(tokio::spawn(async move {
+                            tokio::time::sleep(drain_timeout).await;
+                            processing_cancellation_token.cancel();
+                        }));
+                    }
+                }
+            }
+        }
+
+        let updates_awaiting_finality = self.finality_buffer.len();
+        if updates_awaiting_finality > 0 {
+            log::warn!(
+                "abandoning {} updates whose slots were not rooted.",
+                updates_awaiting_finality
+            );
+            self.finality_buffer = FinalityBuffer::default();
+        }
+
+        drop(worker_senders);
+        drop(worker_flush_senders);
+        let mut flushed = true;
+        for handle in worker_handles {
+            match handle.await {
+                Ok((worker, worker_flushed)) => {
+                    flushed &= worker_flushed.is_ok();
+                    self.workers.push(worker);
+                }
+                Err(error) => {
+                    log::error!("worker task failed: {:?}", error);
+                    flushed = false;
+                }
+            }
+        }
+
+        if let Some(drain_timer) = drain_timer {
+            drain_timer.abort();
+        }
+        self.shutdown_sender.send_replace(None);
+
+        let uncommitted = self.uncommitted_checkpoints();
+        if inline {
+            flushed &= self.flush_pipes().await.is_ok();
+        }
+        if flushed {
+            self.commit_checkpoints(uncommitted).await;
+        } else {
+            log::warn!("holding back checkpoints, pipes failed to flush.");
+        }
+
+        metrics_flusher.abort();
+        self.metrics.flush_metrics().await?;
+
+        let mut shutdown_result = self.shutdown_pipes().await;
+        for worker in self.workers.iter_mut() {
+            let worker_shutdown_result = worker.shutdown_pipes().await;
+            shutdown_result = shutdown_result.and(worker_shutdown_result);
+        }
+        if let Err(error) = &shutdown_result {
+            log::error!("failed to shut down pipes: {:?}", error);
+        }
+
+        self.metrics.shutdown_metrics().await?;
+
+        log::info!("pipeline shutdown complete.");
+
+        if let Some(error) = lock(&self.fatal_error).take() {
+            return Err(error);
+        }
+
+        shutdown_result
+    }
+
+    /// Hands a received update to the pipes, either inline or through the
+    /// worker selected by its shard key.
+    ///
+    /// Duplicate updates are dropped here, and every update is registered
+    /// with the checkpoint tracker. Slot status updates are sent to every
+    /// worker, so that each one rolls back its own pipes.
+    async fn dispatch(
+        &mut self,
+        received: ReceivedUpdate,
+        worker_senders: &[tokio::sync::mpsc::Sender<ReceivedUpdate>],
+        processing_cancellation_token: &CancellationToken,
+    ) {
+        let (slot, _) = update_position(&received.update);
+
+        let is_duplicate = self
+            .deduplicator
+            .as_mut()
+            .is_some_and(|deduplicator| deduplicator.is_duplicate(&received.update));
+
+        if is_duplicate {
+            if let Some(checkpoint_tracker) = &self.checkpoint_tracker {
+                let mut checkpoint_tracker = lock(checkpoint_tracker);
+                checkpoint_tracker.begin(&received.datasource_id, slot);
+                checkpoint_tracker.complete(&received.datasource_id, slot, None, true);
+            }
+
+            self.update_metrics.updates_deduplicated.increment(1);
+            return;
+        }
+
+        if worker_senders.is_empty() {
+            if let Some(checkpoint_tracker) = &self.checkpoint_tracker {
+                lock(checkpoint_tracker).begin(&received.datasource_id, slot);
+            }
+
+            return self.process_and_record(received).await;
+        }
+
+        let workers = match received.update {
+            Update::SlotStatus(_) => (0..worker_senders.len()).collect(),
+            _ => vec![self.worker_index(&received.update, worker_senders.len())],
+        };
+
+        for worker in workers {
+            if let Some(checkpoint_tracker) = &self.checkpoint_tracker {
+                lock(checkpoint_tracker).begin(&received.datasource_id, slot);
+            }
+
+            if let Err(error) = worker_senders[worker].send(received.clone()).await {
+                if let Some(checkpoint_tracker) = &self.checkpoint_tracker {
+                    lock(checkpoint_tracker).complete(&error.0.datasource_id, slot, None, false);
+                }
+
+                if !processing_cancellation_token.is_cancelled() {
+                    log::error!("worker {} has stopped, dropping update.", worker);
+                    self.update_metrics.updates_failed.increment(1);
+                }
+            }
+        }
+    }
+
+    /// Processes a single update and records its outcome in the metrics.
+    ///
+    /// This wraps `process` with the per-update bookkeeping shared by the
+    /// sequential loop in `run` and by worker tasks: processing time
+    /// histograms, the `updates_successful`, `updates_failed` and
+    /// `updates_processed` counters, the lag metrics of the datasource that
+    /// produced the update, and checkpoint progress for that datasource. A
+    /// failed update is logged rather than returned. If it failed in a pipe
+    /// with `ErrorPolicy::Halt`, the pipeline is shut down immediately, and no
+    /// further updates are processed.
+    async fn process_and_record(&mut self, received: ReceivedUpdate) {
+        if lock(&self.fatal_error).is_some() {
+            log::trace!("pipeline stopped on a fatal error, not processing update.");
+            return;
+        }
+
+        let ReceivedUpdate {
+            update,
+            datasource_id,
+            sent_at,
+        } = received;
+
+        let tip_slot = lock(&self.chain_tip).slot();
+        self.update_metrics
+            .datasource(&self.metrics, &datasource_id)
+            .record(&update, sent_at, tip_slot);
+
+        let (slot, signature) = update_position(&update);
+        let start = Instant::now();
+        let process_result = self.process(&update, &datasource_id).await;
+        let time_taken_nanoseconds = start.elapsed().as_nanos();
+        let time_taken_milliseconds = time_taken_nanoseconds / 1_000_000;
+
+        self.update_metrics
+            .updates_process_time_nanoseconds
+            .record(time_taken_nanoseconds as f64);
+        self.update_metrics
+            .updates_process_time_milliseconds
+            .record(time_taken_milliseconds as f64);
+
+        if let Some(checkpoint_tracker) = &self.checkpoint_tracker {
+            lock(checkpoint_tracker).complete(
+                &datasource_id,
+                slot,
+                signature,
+                process_result.is_ok(),
+            );
+        }
+
+        match process_result {
+            Ok(_) => {
+                self.update_metrics.updates_successful.increment(1);
+
+                log::trace!("processed update")
+            }
+            Err(Error::PipelineHalted(reason)) => {
+                log::error!("halting the pipeline: {}", reason);
+                *lock(&self.fatal_error) = Some(Error::PipelineHalted(reason));
+                self.shutdown_sender
+                    .send_replace(Some(ShutdownStrategy::Immediate));
+                self.update_metrics.updates_failed.increment(1);
+            }
+            Err(error) => {
+                log::error!("error processing update ({:?}): {:?}", update, error);
+                self.update_metrics.updates_failed.increment(1);
+            }
+        };
+
+        self.update_metrics.updates_processed.increment(1);
+    }
+
+    /// Returns the checkpoints that advanced since the last commit.
+    fn uncommitted_checkpoints(&self) -> Vec<(DatasourceId, Checkpoint)> {
+        self.checkpoint_tracker
+            .as_ref()
+            .map(|checkpoint_tracker| lock(checkpoint_tracker).uncommitted())
+            .unwrap_or_default()
+    }
+
+    /// Commits checkpoints taken with `uncommitted_checkpoints`.
+    ///
+    /// Failures are logged and retried on the next commit, so that a
+    /// temporarily unavailable store does not stop the pipeline.
+    async fn commit_checkpoints(&self, uncommitted: Vec<(DatasourceId, Checkpoint)>) {
+        let (Some(checkpointer), Some(checkpoint_tracker)) =
+            (&self.checkpointer, &self.checkpoint_tracker)
+        else {
+            return;
+        };
+
+        for (datasource_id, checkpoint) in uncommitted {
+            match checkpointer
+                .commit(datasource_id.as_str(), &checkpoint)
+                .await
+            {
+                Ok(()) => lock(checkpoint_tracker).committed(&datasource_id, checkpoint.slot),
+                Err(error) => log::error!(
+                    "failed to commit checkpoint for datasource {}: {:?}",
+                    datasource_id,
+                    error
+                ),
+            }
+        }
+    }
+
+    /// Selects the worker that processes `update`.
+    ///
+    /// Updates with the same shard key always map to the same worker, which
+    /// processes them in the order they were received. Account updates and
+    /// account deletions are keyed by the account pubkey, and transactions by
+    /// the configured `TransactionShardKey`. Block updates always go to the
+    /// first worker, so that they are processed in order. Slot status updates
+    /// are sent to every worker and are not sharded.
+    fn worker_index(&self, update: &Update, workers: usize) -> usize {
+        let shard_key = match update {
+            Update::SlotStatus(_) | Update::Block(_) => return 0,
+            Update::Account(account_update) => account_update.pubkey,
+            Update::AccountDeletion(account_deletion) => account_deletion.pubkey,
+            Update::Transaction(transaction_update) => match &self.transaction_shard_key {
+                TransactionShardKey::FeePayer => transaction_update
+                    .transaction
+                    .message
+                    .static_account_keys()
+                    .first()
+                    .copied()
+                    .unwrap_or_default(),
+                TransactionShardKey::Custom(shard_key) => shard_key(transaction_update),
+            },
+        };
+
+        let mut hasher = DefaultHasher::new();
+        shard_key.hash(&mut hasher);
+        (hasher.finish() % workers as u64) as usize
+    }
+
+    /// Processes a single update and routes it through the appropriate pipeline
+    /// stages.
+    ///
+    /// The `process` method takes an `Update` and determines its type, then
+    /// routes it through the corresponding pipes for handling account
+    /// updates, transactions, or account deletions. It also records metrics
+    /// for processed updates, providing insights into the processing
+    /// workload and performance.
+    ///
+    /// ## Functionality
+    ///
+    /// - **Account Updates**: Passes account updates through the
+    ///   `account_pipes`. Each pipe processes the account metadata and the
+    ///   updated account state.
+    /// - **Transaction Updates**: Extracts transaction metadata and
+    ///   instructions, nests them if needed, and routes them through
+    ///   `instruction_pipes` and `transaction_pipes`.
+    /// - **Account Deletions**: Sends account deletion events through the
+    ///   `account_deletion_pipes`.
+    /// - **Block Updates**: Sends block details through the `block_pipes`.
+    /// - **Slot Status Updates**: Sends slot status updates through the
+    ///   `slot_status_pipes`, and rolls back every pipe when a slot is
+    ///   reported as dead.
+    ///
+    /// The method also updates metrics counters for each type of update,
+    /// tracking how many updates have been processed in each category.
+    ///
+    /// # Parameters
+    ///
+    /// - `update`: An `Update` variant representing the type of data received.
+    ///   This can be an `Account`, `Transaction`, `AccountDeletion`, `Block` or
+    ///   `SlotStatus`, each triggering different processing logic within the
+    ///   pipeline.
+    /// - `datasource_id`: The id of the datasource that produced the update.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `CarbonResult<()>`, indicating `Ok(())` on successful
+    /// processing or an error if processing fails at any stage.
+    ///
+    /// # Notes
+    ///
+    /// - This method is asynchronous and should be awaited within a Tokio
+    ///   runtime.
+    /// - Each type of update (account, transaction, account deletion) requires
+    ///   its own set of pipes, so ensure that appropriate pipes are configured
+    ///   based on the data types expected from the data sources.
+    /// - Metrics are recorded after each successful processing stage to track
+    ///   processing volumes and identify potential bottlenecks in real-time.
+    /// - A failing pipe is handled according to its `ErrorPolicy` and does not
+    ///   stop the other pipes. A pipe that fails on one instruction of a
+    ///   transaction still runs the remaining instructions, and each failed
+    ///   instruction is handled on its own.
+    /// - With the `tracing` feature, the update is processed inside a `tracing`
+    ///   span named `update`, carrying its kind, slot, signature and
+    ///   datasource. Decoding a transaction runs in a child span named
+    ///   `decode`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::PipelineHalted` if a pipe with `ErrorPolicy::Halt`
+    /// fails, and an error if the update cannot be prepared for the pipes, if
+    /// a dead letter cannot be delivered, or if an issue arises while
+    /// incrementing counters or updating metrics.
+    async fn process(&mut self, update: &Update, datasource_id: &DatasourceId) -> CarbonResult<()> {
+        log::trace!("process(self, update: {:?})", update);
+        let process = async {
+            let failures = self.run_pipes(update, None, None).await?;
+
+            self.handle_pipe_failures(update, datasource_id, failures)
+                .await
+        };
+
+        #[cfg(feature = "tracing")]
+        let process = {
+            use tracing::Instrument;
+
+            let (slot, signature) = update_position(update);
+            let kind = match update {
+                Update::Account(_) => "account",
+                Update::Transaction(_) => "transaction",
+                Update::AccountDeletion(_) => "account_deletion",
+                Update::Block(_) => "block",
+                Update::SlotStatus(_) => "slot_status",
+            };
+            process.instrument(tracing::info_span!(
+                "update",
+                kind,
+                slot,
+                signature = signature.as_ref().map(tracing::field::display),
+                datasource = datasource_id.as_str(),
+            ))
+        };
+
+        process.await
+    }
+
+    /// Runs `update` through the pipes for its type and returns the pipes
+    /// that failed on it.
+    ///
+    /// If `only_pipe` is set, every other pipe is skipped. If
+    /// `only_instruction` is set, instruction pipes only run the instruction
+    /// at that position.
+    async fn run_pipes(
+        &mut self,
+        update: &Update,
+        only_pipe: Option<&str>,
+        only_instruction: Option<usize>,
+    ) -> CarbonResult<Vec<PipeFailure>> {
+        let mut failures = Vec::new();
+        let skip = |pipe: &str| only_pipe.is_some_and(|only_pipe| only_pipe != pipe);
+
+        match update {
+            Update::Account(account_update) => {
+                let account_metadata = AccountMetadata {
+                    slot: account_update.slot,
+                    pubkey: account_update.pubkey,
+                };
+
+                for entry in self.account_pipes.iter_mut() {
+                    let pipe = entry.name.clone();
+                    if skip(&pipe) {
+                        continue;
+                    }
+
+                    let result = run_pipe!(
+                        entry,
+                        &pipe,
+                        &self.metrics,
+                        (&account_metadata, &account_update.account, &self.metrics),
+                        |pipe, (account_metadata, account, metrics)| pipe.run(
+                            ((*account_metadata).clone(), (*account).clone()),
+                            (*metrics).clone(),
+                        )
+                    );
+
+                    if let Err(error) = result {
+                        failures.push(PipeFailure {
+                            pipe,
+                            instruction: None,
+                            error_policy: entry.error_policy.clone(),
+                            error,
+                        });
+                        if entry.error_policy == ErrorPolicy::Halt {
+                            break;
+                        }
+                    }
+                }
+
+                self.update_metrics.account_updates_processed.increment(1);
+            }
+            Update::Transaction(transaction_update) => {
+                let (transaction_metadata, nested_instructions) = {
+                    #[cfg(feature = "tracing")]
+                    let _span = tracing::info_span!("decode").entered();
+
+                    let transaction_metadata: TransactionMetadata =
+                        (**transaction_update).clone().try_into()?;
+
+                    let instructions_with_metadata: InstructionsWithMetadata =
+                        transformers::extract_instructions_with_stack_heights(
+                            &transaction_metadata,
+                            transaction_update,
+                        )?;
+
+                    let nested_instructions: NestedInstructions = instructions_with_metadata.into();
+
+                    (transaction_metadata, nested_instructions)
+                };
+                let transaction_metadata = &transaction_metadata;
+
+                for entry in self.instruction_pipes.iter_mut() {
+                    let pipe = entry.name.clone();
+                    if skip(&pipe) {
+                        continue;
+                    }
+
+                    // A failed instruction does not stop the pipe, so that
+                    // each failure is handled, and replayed, on its own.
+                    for (index, nested_instruction) in nested_instructions.iter().enumerate() {
+                        if only_instruction
+                            .is_some_and(|only_instruction| only_instruction != index)
+                        {
+                            continue;
+                        }
+
+                        let result = run_pipe!(
+                            entry,
+                            &pipe,
+                            &self.metrics,
+                            (nested_instruction, &self.metrics),
+                            |pipe, (nested_instruction, metrics)| pipe
+                                .run(nested_instruction, (*metrics).clone())
+                        );
+
+                        if let Err(error) = result {
+                            failures.push(PipeFailure {
+                                pipe: pipe.clone(),
+                                instruction: Some(index),
+                                error_policy: entry.error_policy.clone(),
+                                error,
+                            });
+                            if entry.error_policy == ErrorPolicy::Halt {
+                                break;
+                            }
+                        }
+                    }
+
+                    if is_halted(&failures) {
+                        break;
+                    }
+                }
+
+                if !is_halted(&failures) {
+                    for entry in self.transaction_pipes.iter_mut() {
+                        let pipe = entry.name.clone();
+                        if skip(&pipe) {
+                            continue;
+                        }
+
+                        let result = run_pipe!(
+                            entry,
+                            &pipe,
+                            &self.metrics,
+                            (transaction_metadata, &nested_instructions, &self.metrics),
+                            |pipe, (transaction_metadata, nested_instructions, metrics)| pipe.run(
+                                (*transaction_metadata).clone(),
+                                nested_instructions,
+                                (*metrics).clone(),
+                            )
+                        );
+
+                        if let Err(error) = result {
+                            failures.push(PipeFailure {
+                                pipe,
+                                instruction: None,
+                                error_policy: entry.error_policy.clone(),
+                                error,
+                            });
+                            if entry.error_policy == ErrorPolicy::Halt {
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                self.update_metrics
+                    .transaction_updates_processed
+                    .increment(1);
+            }
+            Update::AccountDeletion(account_deletion) => {
+                for entry in self.account_deletion_pipes.iter_mut() {
+                    let pipe = entry.name.clone();
+                    if skip(&pipe) {
+                        continue;
+                    }
+
+                    let result = run_pipe!(
+                        entry,
+                        &pipe,
+                        &self.metrics,
+                        (account_deletion, &self.metrics),
+                        |pipe, (account_deletion, metrics)| pipe
+                            .run((*account_deletion).clone(), (*metrics).clone())
+                    );
+
+                    if let Err(error) = result {
+                        failures.push(PipeFailure {
+                            pipe,
+                            instruction: None,
+                            error_policy: entry.error_policy.clone(),
+                            error,
+                        });
+                        if entry.error_policy == ErrorPolicy::Halt {
+                            break;
+                        }
+                    }
+                }
+
+                self.update_metrics.account_deletions_processed.increment(1);
+            }
+            Update::Block(block_details) => {
+                for entry in self.block_pipes.iter_mut() {
+                    let pipe = entry.name.clone();
+                    if skip(&pipe) {
+                        continue;
+                    }
+
+                    let result = run_pipe!(
+                        entry,
+                        &pipe,
+                        &self.metrics,
+                        (block_details, &self.metrics),
+                        |pipe, (block_details, metrics)| pipe
+                            .run((*block_details).clone(), (*metrics).clone())
+                    );
+
+                    if let Err(error) = result {
+                        failures.push(PipeFailure {
+                            pipe,
+                            instruction: None,
+                            error_policy: entry.error_policy.clone(),
+                            error,
+                        });
+                        if entry.error_policy == ErrorPolicy::Halt {
+                            break;
+                        }
+                    }
+                }
+
+                self.update_metrics.block_updates_processed.increment(1);
+            }
+            Update::SlotStatus(slot_status) => {
+                for entry in self.slot_status_pipes.iter_mut() {
+                    let pipe = entry.name.clone();
+                    if skip(&pipe) {
+                        continue;
+                    }
+
+                    let result = run_pipe!(
+                        entry,
+                        &pipe,
+                        &self.metrics,
+                        (slot_status, &self.metrics),
+                        |pipe, (slot_status, metrics)| pipe
+                            .run((*slot_status).clone(), (*metrics).clone())
+                    );
+
+                    if let Err(error) = result {
+                        failures.push(PipeFailure {
+                            pipe,
+                            instruction: None,
+                            error_policy: entry.error_policy.clone(),
+                            error,
+                        });
+                        if entry.error_policy == ErrorPolicy::Halt {
+                            break;
+                        }
+                    }
+                }
+
+                // In finalized only mode, the updates of dead slots never
+                // reached the pipes.
+                if slot_status.status == SlotStatus::Dead
+                    && !self.finalized_only
+                    && only_pipe.is_none()
+                {
+                    self.rollback(slot_status).await?;
+                }
+
+                self.update_metrics
+                    .slot_status_updates_processed
+                    .increment(1);
+            }
+        };
+
+        Ok(failures)
+    }
+
+    /// Applies the `ErrorPolicy` of every pipe that failed on `update`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error::PipelineHalted` if one of the pipes halts the
+    /// pipeline, or the sink's error if a dead letter cannot be delivered.
+    async fn handle_pipe_failures(
+        &self,
+        update: &Update,
+        datasource_id: &DatasourceId,
+        failures: Vec<PipeFailure>,
+    ) -> CarbonResult<()> {
+        for mut failure in failures {
+            self.update_metrics.pipe_errors.increment(1);
+            failure.error = failure.error.with_context(ErrorContext {
+                pipe: Some(failure.pipe.clone()),
+                datasource: Some(datasource_id.clone()),
+                ..update_context(update)
+            });
+
+            match failure.error_policy {
+                ErrorPolicy::Skip | ErrorPolicy::Retry { .. } => {
+                    log::error!(
+                        "pipe {} failed to process update, skipping: {:?}",
+                        failure.pipe,
+                        failure.error
+                    );
+                }
+                ErrorPolicy::DeadLetter => {
+                    let Some(dead_letter_sink) = &self.dead_letter_sink else {
+                        log::error!(
+                            "pipe {} failed to process update and no dead-letter sink is configured: {:?}",
+                            failure.pipe,
+                            failure.error
+                        );
+                        continue;
+                    };
+
+                    log::warn!(
+                        "pipe {} failed to process update, sending it to the dead-letter sink: {:?}",
+                        failure.pipe,
+                        failure.error
+                    );
+                    dead_letter_sink
+                        .send(DeadLetter {
+                            update: update.clone(),
+                            datasource_id: datasource_id.clone(),
+                            pipe: failure.pipe,
+                            instruction: failure.instruction,
+                            error: failure.error.to_string(),
+                            failed_at: SystemTime::now(),
+                        })
+                        .await?;
+                    self.update_metrics.dead_letters.increment(1);
+                }
+                ErrorPolicy::Halt => {
+                    return Err(Error::PipelineHalted(format!(
+                        "pipe {} failed: {}",
+                        failure.pipe, failure.error
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replays the dead letters in `store` through the pipes that failed on
+    /// them.
+    ///
+    /// Each dead letter is run through the pipe named in it, and only that
+    /// pipe, so that pipes which already processed the update do not see it
+    /// twice. Instruction pipes likewise only run the instruction that
+    /// failed. The pipe's `ErrorPolicy` still applies its retries, but a
+    /// dead letter that fails again is left in the store instead of being
+    /// handled by the policy. Dead letters that are processed successfully
+    /// are removed from the store. Dead letters naming a pipe that the
+    /// pipeline does not have are logged and kept, so that they can be
+    /// replayed by a pipeline that has it.
+    ///
+    /// Replay is meant to run on a pipeline built with the same pipes, under
+    /// the same names, as the one that produced the dead letters, and does
+    /// not need the pipeline to be running. Pipes registered without a name
+    /// are named after their position, so they must also be registered in
+    /// the same order. When workers are configured, the
+    /// pipes of the first worker are used.
+    ///
+    /// # Parameters
+    ///
+    /// - `store`: The `DeadLetterStore` to replay.
+    ///
+    /// # Returns
+    ///
+    /// The number of dead letters that were processed successfully and
+    /// removed from the store.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let store = FileDeadLetterStore::new("dead_letters.jsonl", Arc::new(JsonCodec));
+    /// let replayed = pipeline.replay_dead_letters(&store).await?;
+    /// log::info!("replayed {} dead letters", replayed);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store cannot be read or updated, or if an
+    /// update cannot be prepared for the pipes.
+    pub async fn replay_dead_letters(
+        &mut self,
+        store: &dyn DeadLetterStore,
+    ) -> CarbonResult<usize> {
+        log::trace!("replay_dead_letters(self, store: {:?})", stringify!(store));
+        if let Some(worker) = self.workers.first_mut() {
+            return Box::pin(worker.replay_dead_letters(store)).await;
+        }
+
+        let mut replayed = 0;
+        for stored in store.load().await? {
+            let dead_letter = &stored.dead_letter;
+            if !self.has_pipe(&dead_letter.pipe) {
+                log::warn!(
+                    "dead letter {} names unknown pipe {}, keeping it",
+                    stored.id,
+                    dead_letter.pipe
+                );
+                continue;
+            }
+
+            let failures = self
+                .run_pipes(
+                    &dead_letter.update,
+                    Some(&dead_letter.pipe),
+                    dead_letter.instruction,
+                )
+                .await?;
+
+            match failures.first() {
+                None => {
+                    store.remove(stored.id).await?;
+                    replayed += 1;
+                }
+                Some(failure) => log::error!(
+                    "dead letter {} failed again in pipe {}: {:?}",
+                    stored.id,
+                    failure.pipe,
+                    failure.error
+                ),
+            }
+        }
+
+        self.update_metrics
+            .dead_letters_replayed
+            .increment(replayed as u64);
+
+        Ok(replayed)
+    }
+
+    /// Returns whether the pipeline has a pipe named `name`.
+    fn has_pipe(&self, name: &str) -> bool {
+        let mut names = self
+            .account_pipes
+            .iter()
+            .map(|entry| &entry.name)
+            .chain(self.account_deletion_pipes.iter().map(|entry| &entry.name))
+            .chain(self.instruction_pipes.iter().map(|entry| &entry.name))
+            .chain(self.transaction_pipes.iter().map(|entry| &entry.name))
+            .chain(self.block_pipes.iter().map(|entry| &entry.name))
+            .chain(self.slot_status_pipes.iter().map(|entry| &entry.name));
+
+        names.any(|pipe| pipe == name)
+    }
+
+    /// Registers the metrics of every pipe in the pipeline's
+    /// `MetricsCollection`.
+    fn register_pipe_metrics(&mut self) {
+        let metrics = &self.metrics;
+
+        for entry in self.account_pipes.iter_mut() {
+            entry.metrics = Some(PipeMetrics::new(metrics, &entry.name));
+        }
+        for entry in self.account_deletion_pipes.iter_mut() {
+            entry.metrics = Some(PipeMetrics::new(metrics, &entry.name));
+        }
+        for entry in self.instruction_pipes.iter_mut() {
+            entry.metrics = Some(PipeMetrics::new(metrics, &entry.name));
+        }
+        for entry in self.transaction_pipes.iter_mut() {
+            entry.metrics = Some(PipeMetrics::new(metrics, &entry.name));
+        }
+        for entry in self.block_pipes.iter_mut() {
+            entry.metrics = Some(PipeMetrics::new(metrics, &entry.name));
+        }
+        for entry in self.slot_status_pipes.iter_mut() {
+            entry.metrics = Some(PipeMetrics::new(metrics, &entry.name));
+        }
+    }
+
+    /// Initializes every pipe before the pipeline starts.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of a pipe that failed to initialize, once all
+    /// pipes have been asked.
+    async fn initialize_pipes(&mut self) -> CarbonResult<()> {
+        let mut results = Vec::new();
+
+        for entry in self.account_pipes.iter_mut() {
+            results.push(entry.pipe.initialize(self.metrics.clone()).await);
+        }
+        for entry in self.account_deletion_pipes.iter_mut() {
+            results.push(entry.pipe.initialize(self.metrics.clone()).await);
+        }
+        for entry in self.instruction_pipes.iter_mut() {
+            results.push(entry.pipe.initialize(self.metrics.clone()).await);
+        }
+        for entry in self.transaction_pipes.iter_mut() {
+            results.push(entry.pipe.initialize(self.metrics.clone()).await);
+        }
+        for entry in self.block_pipes.iter_mut() {
+            results.push(entry.pipe.initialize(self.metrics.clone()).await);
+        }
+        for entry in self.slot_status_pipes.iter_mut() {
+            results.push(entry.pipe.initialize(self.metrics.clone()).await);
+        }
+
+        results.into_iter().collect()
+    }
+
+    /// Asks every pipe to write out the data it has buffered.
+    ///
+    /// Failures are logged and counted in `pipe_errors`. The data stays with
+    /// the pipe, which decides whether to retry it on the next flush.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of a pipe that failed to flush, once all pipes
+    /// have been asked, so that checkpoints are not committed past data that
+    /// was not written out.
+    async fn flush_pipes(&mut self) -> CarbonResult<()> {
+        let mut results = Vec::new();
+
+        for entry in self.account_pipes.iter_mut() {
+            results.push(entry.pipe.flush(self.metrics.clone()).await);
+        }
+        for entry in self.account_deletion_pipes.iter_mut() {
+            results.push(entry.pipe.flush(self.metrics.clone()).await);
+        }
+        for entry in self.instruction_pipes.iter_mut() {
+            results.push(entry.pipe.flush(self.metrics.clone()).await);
+        }
+        for entry in self.transaction_pipes.iter_mut() {
+            results.push(entry.pipe.flush(self.metrics.clone()).await);
+        }
+        for entry in self.block_pipes.iter_mut() {
+            results.push(entry.pipe.flush(self.metrics.clone()).await);
+        }
+        for entry in self.slot_status_pipes.iter_mut() {
+            results.push(entry.pipe.flush(self.metrics.clone()).await);
+        }
+
+        for error in results.iter().filter_map(|result| result.as_ref().err()) {
+            log::error!("failed to flush pipe: {:?}", error);
+            self.update_metrics.pipe_errors.increment(1);
+        }
+
+        results.into_iter().collect()
+    }
+
+    /// Shuts down every pipe once the pipeline has stopped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of a pipe that failed to shut down, once all
+    /// pipes have been asked.
+    async fn shutdown_pipes(&mut self) -> CarbonResult<()> {
+        let mut results = Vec::new();
+
+        for entry in self.account_pipes.iter_mut() {
+            results.push(entry.pipe.shutdown(self.metrics.clone()).await);
+        }
+        for entry in self.account_deletion_pipes.iter_mut() {
+            results.push(entry.pipe.shutdown(self.metrics.clone()).await);
+        }
+        for entry in self.instruction_pipes.iter_mut() {
+            results.push(entry.pipe.shutdown(self.metrics.clone()).await);
+        }
+        for entry in self.transaction_pipes.iter_mut() {
+            results.push(entry.pipe.shutdown(self.metrics.clone()).await);
+        }
+        for entry in self.block_pipes.iter_mut() {
+            results.push(entry.pipe.shutdown(self.metrics.clone()).await);
+        }
+        for entry in self.slot_status_pipes.iter_mut() {
+            results.push(entry.pipe.shutdown(self.metrics.clone()).await);
+        }
+
+        results.into_iter().collect()
+    }
+
+    /// Asks every pipe to undo the data it processed for a dead slot.
+    ///
+    /// All pipes are rolled back even if one of them fails, and the first
+    /// error is returned afterwards. The error fails the slot status update
+    /// that reported the dead slot, which holds back the checkpoint of its
+    /// datasource.
+    async fn rollback(&mut self, slot_status: &SlotStatusUpdate) -> CarbonResult<()> {
+        log::warn!("slot {} is dead, rolling back pipes.", slot_status.slot);
+        let slot = slot_status.slot;
+        let mut results = Vec::new();
+
+        for entry in self.account_pipes.iter_mut() {
+            results.push(entry.pipe.rollback(slot, self.metrics.clone()).await);
+        }
+        for entry in self.account_deletion_pipes.iter_mut() {
+            results.push(entry.pipe.rollback(slot, self.metrics.clone()).await);
+        }
+        for entry in self.instruction_pipes.iter_mut() {
+            results.push(entry.pipe.rollback(slot, self.metrics.clone()).await);
+        }
+        for entry in self.transaction_pipes.iter_mut() {
+            results.push(entry.pipe.rollback(slot, self.metrics.clone()).await);
+        }
+        for entry in self.block_pipes.iter_mut() {
+            results.push(entry.pipe.rollback(slot, self.metrics.clone()).await);
+        }
+        for entry in self.slot_status_pipes.iter_mut() {
+            results.push(entry.pipe.rollback(slot, self.metrics.clone()).await);
+        }
+
+        self.update_metrics.slots_rolled_back.increment(1);
+
+        results.into_iter().collect()
+    }
+}
+
+/// Returns `initial` doubled `attempt` times, capped at `max`.
+fn backoff_delay(initial: time::Duration, max: time::Duration, attempt: u32) -> time::Duration {
+    initial
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(max)
+}
+
+/// Returns `true` if one of `failures` halts the pipeline.
+fn is_halted(failures: &[PipeFailure]) -> bool {
+    failures
+        .iter()
+        .any(|failure| failure.error_policy == ErrorPolicy::Halt)
+}
+
+/// Returns the slot of an update and, for transactions, its signature.
+fn update_position(update: &Update) -> (u64, Option<Signature>) {
+    match update {
+        Update::SlotStatus(slot_status) => (slot_status.slot, None),
+        Update::Block(block_details) => (block_details.slot, None),
+        Update::Account(account_update) => (account_update.slot, None),
+        Update::AccountDeletion(account_deletion) => (account_deletion.slot, None),
+        Update::Transaction(transaction_update) => {
+            (transaction_update.slot, Some(transaction_update.signature))
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A builder for constructing a `Pipeline` instance with customized data
+/// sources, processing pipes, and metrics.
+///
+/// The `PipelineBuilder` struct offers a flexible way to assemble a `Pipeline`
+/// by allowing configuration of its components, such as data sources, account
+/// and transaction pipes, deletion handling, and metrics. Using the builder
+/// pattern, you can add the desired elements incrementally and then finalize
+/// with a call to `build`.
+///
+/// ## Overview
+///
+/// The `PipelineBuilder` supports the following components:
+/// - **Datasources**: Sources of data updates, such as account information and
+///   transactions.
+/// - **Account Pipes**: For processing account updates from data sources.
+/// - **Account Deletion Pipes**: For handling account deletion updates.
+/// - **Instruction Pipes**: For handling instructions associated with
+///   transactions.
+/// - **Transaction Pipes**: For handling full transaction data.
+/// - **Metrics**: Collects and reports performance data, such as update
+///   processing times.
+/// - **Metrics Flush Interval**: Optional interval defining how often to flush
+///   metrics data.
+///
+/// Each component can be added through method chaining, enhancing code
+/// readability and maintainability.
+///
+/// # Example
+///
+/// ```rust
+/// carbon_core::pipeline::Pipeline::builder()
+/// .datasource(transaction_crawler)
+/// .metrics(Arc::new(LogMetrics::new()))
+/// .metrics(Arc::new(PrometheusMetrics::new()))
+/// .instruction(
+///    TestProgramDecoder,
+///    TestProgramProcessor
+/// )
+/// // ...
+/// ```
+///
+/// # Fields
+///
+/// - `datasources`: A collection of `Datasource` objects wrapped in `Arc` for
+///   shared ownership across threads, each paired with its `DatasourceId`.
+///   Each `Datasource` provides updates to the pipeline.
+/// - `account_pipes`: A collection of `AccountPipes` to handle account updates.
+/// - `account_deletion_pipes`: A collection of `AccountDeletionPipes` for
+///   processing account deletions.
+/// - `instruction_pipes`: A collection of `InstructionPipes` to process
+///   instructions in transactions.
+/// - `transaction_pipes`: A collection of `TransactionPipes` to process full
+///   transaction data.
+/// - `block_pipes`: A collection of `BlockPipes` to process block-level data.
+/// - `slot_status_pipes`: A collection of `SlotStatusPipes` to process slot
+///   status updates.
+/// - `metrics`: A vector of `Metrics` implementations for tracking pipeline
+///   performance.
+/// - `metrics_flush_interval`: An optional interval (in seconds) for flushing
+///   metrics data. If not set, a default flush interval will be used.
+/// - `channel_capacity`: An optional limit on the number of queued updates. If
+///   not set, the queue is unbounded.
+/// - `channel_overflow_policy`: The `OverflowPolicy` applied when the queue is
+///   full.
+/// - `workers`: An optional worker count together with the function that
+///   registers each worker's pipes.
+/// - `transaction_shard_key`: The key used to assign transactions to workers.
+/// - `shutdown_drain_timeout`: An optional limit on how long a graceful
+///   shutdown waits for pending updates.
+/// - `checkpointer`: An optional `Checkpointer` for resuming datasources.
+/// - `checkpoint_interval`: An optional interval for committing checkpoints.
+///   If not set, checkpoints are committed every second.
+/// - `flush_interval`: An optional interval for flushing pipes. If not set,
+///   pipes are flushed every second.
+/// - `dedup_window`: An optional `DedupWindow`. If set, duplicate updates are
+///   dropped before they are processed.
+/// - `dedup_max_entries`: An optional limit on the number of updates
+///   remembered for deduplication.
+/// - `finalized_only`: Whether updates are held back until their slot is
+///   rooted.
+/// - `error_policy`: The `ErrorPolicy` given to pipes registered from now on.
+/// - `dead_letter_sink`: An optional `DeadLetterSink` for updates that failed
+///   in pipes with `ErrorPolicy::DeadLetter`.
+/// - `datasource_restart_policy`: An optional `RestartPolicy`. If not set,
+///   failed datasources are not restarted.
+/// - `stall_policy`: The `StallPolicy` given to datasources registered from
+///   now on, if any.
+/// - `stall_policies`: The `StallPolicy` of each registered datasource that is
+///   watched for stalls.
+/// - `layers`: The `Layer`s given to pipes registered from now on.
+///
+/// # Returns
+///
+/// After configuring the builder, call `build` to create a `Pipeline` instance.
+/// The builder will return a `CarbonResult<Pipeline>`, which will either
+/// contain the configured pipeline or an error if configuration failed.
+///
+/// # Notes
+///
+/// - The builder pattern allows for method chaining, making it easy to
+///   incrementally add components to the `Pipeline`.
+/// - Ensure that each component matches the data and update types expected by
+///   your application.
+#[derive(Default)]
+pub struct PipelineBuilder {
+    pub datasources: Vec<(DatasourceId, Arc<dyn Datasource + Send + Sync>)>,
+    pub account_pipes: Vec<PipeEntry<dyn AccountPipes>>,
+    pub account_deletion_pipes: Vec<PipeEntry<dyn AccountDeletionPipes>>,
+    pub instruction_pipes: Vec<PipeEntry<dyn for<'a> InstructionPipes<'a>>>,
+    pub transaction_pipes: Vec<PipeEntry<dyn for<'a> TransactionPipes<'a>>>,
+    pub block_pipes: Vec<PipeEntry<dyn BlockPipes>>,
+    pub slot_status_pipes: Vec<PipeEntry<dyn SlotStatusPipes>>,
+    pub metrics: MetricsCollection,
+    pub metrics_flush_interval: Option<u64>,
+    pub shutdown_strategy: ShutdownStrategy,
+    pub channel_capacity: Option<usize>,
+    pub channel_overflow_policy: OverflowPolicy<(Update, DatasourceId)>,
+    pub workers: Option<(usize, WorkerPipes)>,
+    pub transaction_shard_key: TransactionShardKey,
+    pub shutdown_drain_timeout: Option<time::Duration>,
+    pub checkpointer: Option<Arc<dyn Checkpointer>>,
+    pub checkpoint_interval: Option<time::Duration>,
+    pub flush_interval: Option<time::Duration>,
+    pub dedup_window: Option<DedupWindow>,
+    pub dedup_max_entries: Option<usize>,
+    pub finalized_only: bool,
+    pub error_policy: ErrorPolicy,
+    pub dead_letter_sink: Option<Arc<dyn DeadLetterSink>>,
+    pub datasource_restart_policy: Option<RestartPolicy>,
+    pub stall_policy: Option<StallPolicy>,
+    pub stall_policies: HashMap<DatasourceId, StallPolicy>,
+    pub layers: Vec<Arc<dyn Layer>>,
+}
+
+impl PipelineBuilder {
+    /// Creates a new `PipelineBuilder` with empty collections for datasources,
+    /// pipes, and metrics.
+    ///
+    /// This method initializes a `PipelineBuilder` instance, allowing you to
+    /// configure each component of a `Pipeline` before building it. The
+    /// builder pattern offers flexibility in adding data sources, account
+    /// and transaction handling pipes, deletion processing, and metrics
+    /// collection features.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new();
+    /// ```
+    pub fn new() -> Self {
+        log::trace!("PipelineBuilder::new()");
+        Self::default()
+    }
+
+    /// Adds a datasource to the pipeline.
+    ///
+    /// The datasource is responsible for providing updates, such as account and
+    /// transaction data, to the pipeline. Multiple datasources can be added
+    /// to handle various types of updates.
+    ///
+    /// # Parameters
+    ///
+    /// - `datasource`: The data source to add, implementing the `Datasource`
+    ///   trait.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .datasource(MyDatasource::new());
+    /// ```
+    pub fn datasource(self, datasource: impl Datasource + 'static) -> Self {
+        log::trace!("datasource(self, datasource: {:?})", stringify!(datasource));
+        let datasource_id = DatasourceId::new(format!("datasource_{}", self.datasources.len()));
+        self.datasource_with_id(datasource, datasource_id)
+    }
+
+    /// Adds a datasource to the pipeline under the given id.
+    ///
+    /// The id identifies the datasource in logs and in checkpoints. Unlike
+    /// the ids assigned by `datasource`, it does not change when datasources
+    /// are added or reordered, so checkpoints keep matching across
+    /// configuration changes.
+    ///
+    /// # Parameters
+    ///
+    /// - `datasource`: The data source to add, implementing the `Datasource`
+    ///   trait.
+    /// - `datasource_id`: The id of the datasource.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .datasource_with_id(MyDatasource::new(), DatasourceId::new("mainnet-rpc"));
+    /// ```
+    pub fn datasource_with_id(
+        mut self,
+        datasource: impl Datasource + 'static,
+        datasource_id: DatasourceId,
+    ) -> Self {
+        log::trace!(
+            "datasource_with_id(self, datasource: {:?}, datasource_id: {:?})",
+            stringify!(datasource),
+            datasource_id
+        );
+        if let Some(stall_policy) = &self.stall_policy {
+            self.stall_policies
+                .insert(datasource_id.clone(), stall_policy.clone());
+        }
+        self.datasources.push((datasource_id, Arc::new(datasource)));
+        self
+    }
+
+    /// Watches the datasources registered after this call for stalls.
+    ///
+    /// Like `error_policy`, the policy applies to every datasource added
+    /// afterwards, so datasources with different expected update rates can
+    /// be given different timeouts.
+    ///
+    /// # Parameters
+    ///
+    /// - `stall_policy`: The `StallPolicy` for the datasources registered next.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .stall_policy(StallPolicy {
+    ///         timeout: Duration::from_secs(10),
+    ///         action: StallAction::Restart,
+    ///     })
+    ///     .datasource_with_id(WebsocketDatasource::new(), DatasourceId::new("ws"))
+    ///     .stall_policy(StallPolicy {
+    ///         timeout: Duration::from_secs(300),
+    ///         action: StallAction::Warn,
+    ///     })
+    ///     .datasource_with_id(PollingDatasource::new(), DatasourceId::new("poll"));
+    /// ```
+    pub fn stall_policy(mut self, stall_policy: StallPolicy) -> Self {
+        log::trace!("stall_policy(self, stall_policy: {:?})", stall_policy);
+        self.stall_policy = Some(stall_policy);
+        self
+    }
+
+    /// Sets the shutdown strategy for the pipeline.
     ///
-    /// This method initializes a `PipelineBuilder` instance, allowing you to
-    /// configure each component of a `Pipeline` before building it. The
-    /// builder pattern offers flexibility in adding data sources, account
-    /// and transaction handling pipes, deletion processing, and metrics
-    /// collection features.
+    /// This method configures how the pipeline should handle shutdowns. The
+    /// shutdown strategy defines whether the pipeline should terminate
+    /// immediately or continue processing pending updates after terminating
+    /// the data sources.
+    ///
+    /// # Parameters
+    ///
+    /// - `shutdown_strategy`: A variant of [`ShutdownStrategy`] that determines
+    ///   how the pipeline should handle shutdowns.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Self`, allowing for method chaining.
+    ///
+    /// # Notes
+    ///
+    /// - Use `ShutdownStrategy::Immediate` to stop the entire pipeline
+    ///   instantly, including all active processing tasks.
+    /// - Use `ShutdownStrategy::ProcessPending` (the default) to terminate data
+    ///   sources first and allow the pipeline to finish processing any updates
+    ///   that are still pending.
+    pub fn shutdown_strategy(mut self, shutdown_strategy: ShutdownStrategy) -> Self {
+        log::trace!(
+            "shutdown_strategy(self, shutdown_strategy: {:?})",
+            shutdown_strategy
+        );
+        self.shutdown_strategy = shutdown_strategy;
+        self
+    }
+
+    /// Sets how long a `ShutdownStrategy::ProcessPending` shutdown may take.
+    ///
+    /// Once the datasources have been stopped, the pipeline keeps processing
+    /// pending updates for at most `timeout`. Updates still pending after that
+    /// are abandoned and the pipeline returns from `run`. Without a timeout
+    /// the pipeline waits until every pending update has been processed.
+    ///
+    /// # Parameters
+    ///
+    /// - `timeout`: The maximum time spent draining pending updates.
     ///
     /// # Example
     ///
     /// ```rust
-    /// let builder = PipelineBuilder::new();
+    /// let builder = PipelineBuilder::new()
+    ///     .shutdown_strategy(ShutdownStrategy::ProcessPending)
+    ///     .shutdown_drain_timeout(Duration::from_secs(30));
     /// ```
-    pub fn new() -> Self {
-        log::trace!("PipelineBuilder::new()");
-        Self::default()
+    pub fn shutdown_drain_timeout(mut self, timeout: time::Duration) -> Self {
+        log::trace!("shutdown_drain_timeout(self, timeout: {:?})", timeout);
+        self.shutdown_drain_timeout = Some(timeout);
+        self
     }
 
-    /// Adds a datasource to the pipeline.
+    /// Enables deduplication of updates across datasources.
     ///
-    /// The datasource is responsible for providing updates, such as account and
-    /// transaction data, to the pipeline. Multiple datasources can be added
-    /// to handle various types of updates.
+    /// Updates seen within `window` are remembered, and further copies of
+    /// them are dropped before they reach any pipe. Transactions are matched
+    /// by signature and account updates by pubkey, slot and write version, or
+    /// account state when the datasource reports no write version.
+    /// Dropped copies are counted in the `updates_deduplicated` counter.
+    ///
+    /// # Parameters
+    ///
+    /// - `window`: How long updates are remembered.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .datasource(primary_datasource)
+    ///     .datasource(backup_datasource)
+    ///     .deduplication(DedupWindow::Slots(150));
+    /// ```
+    pub fn deduplication(mut self, window: DedupWindow) -> Self {
+        log::trace!("deduplication(self, window: {:?})", window);
+        self.dedup_window = Some(window);
+        self
+    }
+
+    /// Sets the maximum number of updates remembered for deduplication.
+    ///
+    /// Once the limit is reached, the oldest updates are forgotten even if
+    /// they are still within the window. Has no effect unless
+    /// `deduplication` is set.
+    ///
+    /// # Parameters
+    ///
+    /// - `max_entries`: The maximum number of remembered updates. Defaults to
+    ///   `DEFAULT_DEDUP_MAX_ENTRIES`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .deduplication(DedupWindow::Duration(Duration::from_secs(60)))
+    ///     .dedup_max_entries(1_000_000);
+    /// ```
+    pub fn dedup_max_entries(mut self, max_entries: usize) -> Self {
+        log::trace!("dedup_max_entries(self, max_entries: {:?})", max_entries);
+        self.dedup_max_entries = Some(max_entries);
+        self
+    }
+
+    /// Holds back updates until their slot is rooted.
+    ///
+    /// In finalized only mode, updates are buffered per slot and only reach
+    /// the pipes once a datasource reports their slot as `SlotStatus::Rooted`.
+    /// Updates arriving after their slot was rooted are processed right away.
+    /// Updates of slots reported as dead are discarded and counted in the
+    /// `updates_rolled_back` counter, so processors never see data that is
+    /// rolled back.
+    ///
+    /// Without this mode, updates are processed as soon as they arrive, and
+    /// pipes are rolled back through `Processor::rollback` when their slot
+    /// dies.
+    ///
+    /// # Parameters
+    ///
+    /// - `finalized_only`: Whether to hold back updates until finality.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .finalized_only(true);
+    /// ```
+    ///
+    /// # Notes
+    ///
+    /// - At least one datasource must deliver rooted slot status updates, or
+    ///   no update is ever processed.
+    /// - Slots that are neither rooted nor reported as dead stay buffered, and
+    ///   are counted in the `updates_awaiting_finality` gauge until the
+    ///   pipeline stops.
+    pub fn finalized_only(mut self, finalized_only: bool) -> Self {
+        log::trace!("finalized_only(self, finalized_only: {:?})", finalized_only);
+        self.finalized_only = finalized_only;
+        self
+    }
+
+    /// Sets the `ErrorPolicy` for the pipes registered after this call.
+    ///
+    /// Pipes keep the policy that was in effect when they were registered,
+    /// so different policies can be given to different pipes by calling
+    /// this method between registrations. Pipes registered before any call
+    /// use `ErrorPolicy::Skip`.
+    ///
+    /// # Parameters
+    ///
+    /// - `error_policy`: The policy for the pipes registered next.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .error_policy(ErrorPolicy::DeadLetter)
+    ///     .account(TokenProgramDecoder, TokenAccountProcessor)
+    ///     .account(SystemProgramDecoder, SystemAccountProcessor)
+    ///     .error_policy(ErrorPolicy::Skip)
+    ///     .account_deletions(MyAccountDeletionProcessor);
+    /// ```
+    pub fn error_policy(mut self, error_policy: ErrorPolicy) -> Self {
+        log::trace!("error_policy(self, error_policy: {:?})", error_policy);
+        self.error_policy = error_policy;
+        self
+    }
+
+    /// Adds a `Layer` around the pipes registered after this call.
+    ///
+    /// Like `error_policy`, layers apply to the pipes registered after them,
+    /// so a layer can be limited to some pipes by registering it between
+    /// them. Layers run in the order they are added, the first one being the
+    /// outermost.
+    ///
+    /// # Parameters
+    ///
+    /// - `layer`: The layer to run the next pipes in.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .layer(TracingLayer)
+    ///     .account(TokenProgramDecoder, TokenAccountProcessor)
+    ///     .layer(TimeoutLayer::new(Duration::from_secs(5)))
+    ///     .transaction(MyTransactionProcessor, Some(MY_SCHEMA.clone()));
+    /// ```
+    ///
+    /// # Notes
+    ///
+    /// - Layers wrap the whole pipe, including its decoder. A pipe that
+    ///   ignores an update still passes it through its layers.
+    pub fn layer(mut self, layer: impl Layer + 'static) -> Self {
+        log::trace!("layer(self, layer: {:?})", stringify!(layer));
+        self.layers.push(Arc::new(layer));
+        self
+    }
+
+    /// Sets the `DeadLetterSink` that receives the updates failed by pipes
+    /// with `ErrorPolicy::DeadLetter`.
+    ///
+    /// When workers are configured, the sink is shared by all of them.
+    ///
+    /// # Parameters
+    ///
+    /// - `dead_letter_sink`: The sink for failed updates.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .dead_letter_sink(Arc::new(MyDeadLetterSink::new()))
+    ///     .error_policy(ErrorPolicy::DeadLetter)
+    ///     .account(TokenProgramDecoder, TokenAccountProcessor);
+    /// ```
+    pub fn dead_letter_sink(mut self, dead_letter_sink: Arc<dyn DeadLetterSink>) -> Self {
+        log::trace!(
+            "dead_letter_sink(self, dead_letter_sink: {:?})",
+            stringify!(dead_letter_sink)
+        );
+        self.dead_letter_sink = Some(dead_letter_sink);
+        self
+    }
+
+    /// Wraps `pipe` in a `PipeEntry` with the current error policy and layers.
+    fn pipe_entry<P: ?Sized>(&self, name: String, pipe: Box<P>) -> PipeEntry<P> {
+        PipeEntry::new(name, pipe, self.error_policy.clone()).with_layers(self.layers.clone())
+    }
+
+    /// Adds an account pipe to process account updates.
+    ///
+    /// Account pipes decode and process updates to accounts within the
+    /// pipeline. This method requires both an `AccountDecoder` and a
+    /// `Processor` to handle decoded account data.
+    ///
+    /// # Parameters
+    ///
+    /// - `decoder`: An `AccountDecoder` that decodes the account data.
+    /// - `processor`: A `Processor` that processes the decoded account data.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .account(MyAccountDecoder, MyAccountProcessor);
+    /// ```
+    ///
+    /// # Notes
+    ///
+    /// - The pipe is named `account_<n>`, where `<n>` is the number of
+    ///   account pipes registered before it. Use `account_with_name` to give
+    ///   it a stable name.
+    pub fn account<T: Send + Sync + 'static>(
+        self,
+        decoder: impl for<'a> AccountDecoder<'a, AccountType = T> + Send + Sync + 'static,
+        processor: impl Processor<InputType = AccountProcessorInputType<T>> + Send + Sync + 'static,
+    ) -> Self {
+        log::trace!(
+            "account(self, decoder: {:?}, processor: {:?})",
+            stringify!(decoder),
+            stringify!(processor)
+        );
+        let name = format!("account_{}", self.account_pipes.len());
+        self.account_with_name(decoder, processor, name)
+    }
+
+    /// Adds an account pipe under the given name.
+    ///
+    /// The name identifies the pipe in logs, in the `pipe` label of per-pipe
+    /// metrics such as `pipe_updates_failed`, and in dead letters. Unlike the names
+    /// assigned by `account`, it does not change when pipes are added or
+    /// reordered.
+    ///
+    /// # Parameters
+    ///
+    /// - `decoder`: An `AccountDecoder` that decodes the account data.
+    /// - `processor`: A `Processor` that processes the decoded account data.
+    /// - `name`: The name of the pipe. It must be unique within the pipeline.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .account_with_name(TokenProgramDecoder, TokenAccountProcessor, "token_accounts");
+    /// ```
+    pub fn account_with_name<T: Send + Sync + 'static>(
+        mut self,
+        decoder: impl for<'a> AccountDecoder<'a, AccountType = T> + Send + Sync + 'static,
+        processor: impl Processor<InputType = AccountProcessorInputType<T>> + Send + Sync + 'static,
+        name: impl Into<String>,
+    ) -> Self {
+        let name = name.into();
+        log::trace!(
+            "account_with_name(self, decoder: {:?}, processor: {:?}, name: {:?})",
+            stringify!(decoder),
+            stringify!(processor),
+            name
+        );
+        self.account_pipes.push(self.pipe_entry(
+            name,
+            Box::new(AccountPipe {
+                decoder: Box::new(decoder),
+                processor: Box::new(processor),
+            }),
+        ));
+        self
+    }
+
+    /// Adds an account deletion pipe to handle account deletion events.
+    ///
+    /// Account deletion pipes process deletions of accounts, with a `Processor`
+    /// to handle the deletion events as they occur.
+    ///
+    /// # Parameters
+    ///
+    /// - `processor`: A `Processor` that processes account deletion events.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .account_deletions(MyAccountDeletionProcessor);
+    /// ```
+    ///
+    /// # Notes
+    ///
+    /// - The pipe is named `account_deletion_<n>`. Use
+    ///   `account_deletions_with_name` to give it a stable name.
+    pub fn account_deletions(
+        self,
+        processor: impl Processor<InputType = AccountDeletion> + Send + Sync + 'static,
+    ) -> Self {
+        log::trace!(
+            "account_deletions(self, processor: {:?})",
+            stringify!(processor)
+        );
+        let name = format!("account_deletion_{}", self.account_deletion_pipes.len());
+        self.account_deletions_with_name(processor, name)
+    }
+
+    /// Adds an account deletion pipe under the given name.
+    ///
+    /// # Parameters
+    ///
+    /// - `processor`: A `Processor` that processes account deletion events.
+    /// - `name`: The name of the pipe. It must be unique within the pipeline.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .account_deletions_with_name(MyAccountDeletionProcessor, "closed_accounts");
+    /// ```
+    pub fn account_deletions_with_name(
+        mut self,
+        processor: impl Processor<InputType = AccountDeletion> + Send + Sync + 'static,
+        name: impl Into<String>,
+    ) -> Self {
+        let name = name.into();
+        log::trace!(
+            "account_deletions_with_name(self, processor: {:?}, name: {:?})",
+            stringify!(processor),
+            name
+        );
+        self.account_deletion_pipes.push(self.pipe_entry(
+            name,
+            Box::new(AccountDeletionPipe {
+                processor: Box::new(processor),
+            }),
+        ));
+        self
+    }
+
+    /// Adds a block pipe to handle block-level data.
+    ///
+    /// Block pipes receive the `BlockDetails` of every block a datasource
+    /// reports, such as its blockhash, parent slot, block time and rewards.
+    ///
+    /// # Parameters
+    ///
+    /// - `processor`: A `Processor` that processes `BlockDetails`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .block(MyBlockProcessor);
+    /// ```
+    ///
+    /// # Notes
+    ///
+    /// - The pipe is named `block_<n>`. Use `block_with_name` to give it a
+    ///   stable name.
+    pub fn block(
+        self,
+        processor: impl Processor<InputType = BlockDetails> + Send + Sync + 'static,
+    ) -> Self {
+        log::trace!("block(self, processor: {:?})", stringify!(processor));
+        let name = format!("block_{}", self.block_pipes.len());
+        self.block_with_name(processor, name)
+    }
+
+    /// Adds a block pipe under the given name.
     ///
     /// # Parameters
     ///
-    /// - `datasource`: The data source to add, implementing the `Datasource`
-    ///   trait.
+    /// - `processor`: A `Processor` that processes `BlockDetails`.
+    /// - `name`: The name of the pipe. It must be unique within the pipeline.
     ///
     /// # Example
     ///
     /// ```rust
     /// let builder = PipelineBuilder::new()
-    ///     .datasource(MyDatasource::new());
+    ///     .block_with_name(MyBlockProcessor, "block_times");
     /// ```
-    pub fn datasource(mut self, datasource: impl Datasource + 'static) -> Self {
-        log::trace!("datasource(self, datasource: {:?})", stringify!(datasource));
-        self.datasources.push(Arc::new(datasource));
+    pub fn block_with_name(
+        mut self,
+        processor: impl Processor<InputType = BlockDetails> + Send + Sync + 'static,
+        name: impl Into<String>,
+    ) -> Self {
+        let name = name.into();
+        log::trace!(
+            "block_with_name(self, processor: {:?}, name: {:?})",
+            stringify!(processor),
+            name
+        );
+        self.block_pipes.push(self.pipe_entry(
+            name,
+            Box::new(BlockPipe {
+                processor: Box::new(processor),
+            }),
+        ));
         self
     }
 
-    /// Sets the shutdown strategy for the pipeline.
+    /// Adds a slot status pipe to handle slot status transitions.
     ///
-    /// This method configures how the pipeline should handle shutdowns. The
-    /// shutdown strategy defines whether the pipeline should terminate
-    /// immediately or continue processing pending updates after terminating
-    /// the data sources.
+    /// Slot status pipes receive every `SlotStatusUpdate` a datasource
+    /// reports, independently of the rollbacks the pipeline performs for
+    /// dead slots. When workers are configured, only the slot status pipes of
+    /// the first worker are run.
     ///
     /// # Parameters
     ///
-    /// - `shutdown_strategy`: A variant of [`ShutdownStrategy`] that determines
-    ///   how the pipeline should handle shutdowns.
+    /// - `processor`: A `Processor` that processes `SlotStatusUpdate`s.
     ///
-    /// # Returns
+    /// # Example
     ///
-    /// Returns `Self`, allowing for method chaining.
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .slot_status(MySlotStatusProcessor);
+    /// ```
     ///
     /// # Notes
     ///
-    /// - Use `ShutdownStrategy::Immediate` to stop the entire pipeline
-    ///   instantly, including all active processing tasks.
-    /// - Use `ShutdownStrategy::ProcessPending` (the default) to terminate data
-    ///   sources first and allow the pipeline to finish processing any updates
-    ///   that are still pending.
-    pub fn shutdown_strategy(mut self, shutdown_strategy: ShutdownStrategy) -> Self {
-        log::trace!(
-            "shutdown_strategy(self, shutdown_strategy: {:?})",
-            shutdown_strategy
-        );
-        self.shutdown_strategy = shutdown_strategy;
-        self
+    /// - The pipe is named `slot_status_<n>`. Use `slot_status_with_name` to
+    ///   give it a stable name.
+    pub fn slot_status(
+        self,
+        processor: impl Processor<InputType = SlotStatusUpdate> + Send + Sync + 'static,
+    ) -> Self {
+        log::trace!("slot_status(self, processor: {:?})", stringify!(processor));
+        let name = format!("slot_status_{}", self.slot_status_pipes.len());
+        self.slot_status_with_name(processor, name)
     }
 
-    /// Adds an account pipe to process account updates.
-    ///
-    /// Account pipes decode and process updates to accounts within the
-    /// pipeline. This method requires both an `AccountDecoder` and a
-    /// `Processor` to handle decoded account data.
+    /// Adds a slot status pipe under the given name.
     ///
     /// # Parameters
     ///
-    /// - `decoder`: An `AccountDecoder` that decodes the account data.
-    /// - `processor`: A `Processor` that processes the decoded account data.
+    /// - `processor`: A `Processor` that processes `SlotStatusUpdate`s.
+    /// - `name`: The name of the pipe. It must be unique within the pipeline.
     ///
     /// # Example
     ///
     /// ```rust
     /// let builder = PipelineBuilder::new()
-    ///     .account(MyAccountDecoder, MyAccountProcessor);
+    ///     .slot_status_with_name(MySlotStatusProcessor, "slot_tracker");
     /// ```
-    pub fn account<T: Send + Sync + 'static>(
+    pub fn slot_status_with_name(
         mut self,
-        decoder: impl for<'a> AccountDecoder<'a, AccountType = T> + Send + Sync + 'static,
-        processor: impl Processor<InputType = AccountProcessorInputType<T>> + Send + Sync + 'static,
+        processor: impl Processor<InputType = SlotStatusUpdate> + Send + Sync + 'static,
+        name: impl Into<String>,
     ) -> Self {
+        let name = name.into();
         log::trace!(
-            "account(self, decoder: {:?}, processor: {:?})",
-            stringify!(decoder),
-            stringify!(processor)
+            "slot_status_with_name(self, processor: {:?}, name: {:?})",
+            stringify!(processor),
+            name
         );
-        self.account_pipes.push(Box::new(AccountPipe {
-            decoder: Box::new(decoder),
-            processor: Box::new(processor),
-        }));
+        self.slot_status_pipes.push(self.pipe_entry(
+            name,
+            Box::new(SlotStatusPipe {
+                processor: Box::new(processor),
+            }),
+        ));
         self
     }
 
-    /// Adds an account deletion pipe to handle account deletion events.
+    /// Adds an instruction pipe to process instructions within transactions.
     ///
-    /// Account deletion pipes process deletions of accounts, with a `Processor`
-    /// to handle the deletion events as they occur.
+    /// Instruction pipes decode and process individual instructions,
+    /// enabling specialized handling of various instruction types.
     ///
     /// # Parameters
     ///
-    /// - `processor`: A `Processor` that processes account deletion events.
+    /// - `decoder`: An `InstructionDecoder` for decoding instructions from
+    ///   transaction data.
+    /// - `processor`: A `Processor` that processes decoded instruction data.
     ///
     /// # Example
     ///
     /// ```rust
     /// let builder = PipelineBuilder::new()
-    ///     .account_deletions(MyAccountDeletionProcessor);
+    ///     .instruction(MyDecoder, MyInstructionProcessor);
     /// ```
-    pub fn account_deletions(
-        mut self,
-        processor: impl Processor<InputType = AccountDeletion> + Send + Sync + 'static,
+    ///
+    /// # Notes
+    ///
+    /// - The pipe is named `instruction_<n>`. Use `instruction_with_name` to
+    ///   give it a stable name.
+    pub fn instruction<T: Send + Sync + 'static>(
+        self,
+        decoder: impl for<'a> InstructionDecoder<'a, InstructionType = T> + Send + Sync + 'static,
+        processor: impl Processor<InputType = InstructionProcessorInputType<T>> + Send + Sync + 'static,
     ) -> Self {
         log::trace!(
-            "account_deletions(self, processor: {:?})",
+            "instruction(self, decoder: {:?}, processor: {:?})",
+            stringify!(decoder),
             stringify!(processor)
         );
-        self.account_deletion_pipes
-            .push(Box::new(AccountDeletionPipe {
-                processor: Box::new(processor),
-            }));
-        self
+        let name = format!("instruction_{}", self.instruction_pipes.len());
+        self.instruction_with_name(decoder, processor, name)
     }
 
-    /// Adds an instruction pipe to process instructions within transactions.
-    ///
-    /// Instruction pipes decode and process individual instructions,
-    /// enabling specialized handling of various instruction types.
+    /// Adds an instruction pipe under the given name.
     ///
     /// # Parameters
     ///
     /// - `decoder`: An `InstructionDecoder` for decoding instructions from
     ///   transaction data.
     /// - `processor`: A `Processor` that processes decoded instruction data.
+    /// - `name`: The name of the pipe. It must be unique within the pipeline.
     ///
     /// # Example
     ///
     /// ```rust
     /// let builder = PipelineBuilder::new()
-    ///     .instruction(MyDecoder, MyInstructionProcessor);
+    ///     .instruction_with_name(JupiterDecoder, SwapProcessor, "jupiter_swaps");
     /// ```
-    pub fn instruction<T: Send + Sync + 'static>(
+    pub fn instruction_with_name<T: Send + Sync + 'static>(
         mut self,
         decoder: impl for<'a> InstructionDecoder<'a, InstructionType = T> + Send + Sync + 'static,
         processor: impl Processor<InputType = InstructionProcessorInputType<T>> + Send + Sync + 'static,
+        name: impl Into<String>,
     ) -> Self {
+        let name = name.into();
         log::trace!(
-            "instruction(self, decoder: {:?}, processor: {:?})",
+            "instruction_with_name(self, decoder: {:?}, processor: {:?}, name: {:?})",
             stringify!(decoder),
-            stringify!(processor)
+            stringify!(processor),
+            name
         );
-        self.instruction_pipes.push(Box::new(InstructionPipe {
-            decoder: Box::new(decoder),
-            processor: Box::new(processor),
-        }));
+        self.instruction_pipes.push(self.pipe_entry(
+            name,
+            Box::new(InstructionPipe {
+                decoder: Box::new(decoder),
+                processor: Box::new(processor),
+            }),
+        ));
         self
     }
 
@@ -805,10 +3560,15 @@ impl PipelineBuilder {
     ///
     /// ```rust
     /// let builder = PipelineBuilder::new()
-    ///     .transaction(MY_SCHEMA.clone(), MyTransactionProcessor);
+    ///     .transaction(MyTransactionProcessor, Some(MY_SCHEMA.clone()));
     /// ```
+    ///
+    /// # Notes
+    ///
+    /// - The pipe is named `transaction_<n>`. Use `transaction_with_name` to
+    ///   give it a stable name.
     pub fn transaction<T, U>(
-        mut self,
+        self,
         processor: impl Processor<InputType = TransactionProcessorInputType<T, U>>
             + Send
             + Sync
@@ -824,51 +3584,327 @@ impl PipelineBuilder {
             stringify!(schema),
             stringify!(processor)
         );
-        self.transaction_pipes
-            .push(Box::new(TransactionPipe::<T, U>::new(schema, processor)));
+        let name = format!("transaction_{}", self.transaction_pipes.len());
+        self.transaction_with_name(processor, schema, name)
+    }
+
+    /// Adds a transaction pipe under the given name.
+    ///
+    /// # Parameters
+    ///
+    /// - `processor`: A `Processor` that processes the decoded transaction
+    ///   data.
+    /// - `schema`: A `TransactionSchema` used to match and interpret
+    ///   transaction data.
+    /// - `name`: The name of the pipe. It must be unique within the pipeline.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .transaction_with_name(MyTransactionProcessor, Some(MY_SCHEMA.clone()), "swaps");
+    /// ```
+    pub fn transaction_with_name<T, U>(
+        mut self,
+        processor: impl Processor<InputType = TransactionProcessorInputType<T, U>>
+            + Send
+            + Sync
+            + 'static,
+        schema: Option<TransactionSchema<T>>,
+        name: impl Into<String>,
+    ) -> Self
+    where
+        T: InstructionDecoderCollection + 'static,
+        U: DeserializeOwned + Send + Sync + 'static,
+    {
+        let name = name.into();
+        log::trace!(
+            "transaction_with_name(self, schema: {:?}, processor: {:?}, name: {:?})",
+            stringify!(schema),
+            stringify!(processor),
+            name
+        );
+        self.transaction_pipes.push(self.pipe_entry(
+            name,
+            Box::new(TransactionPipe::<T, U>::new(schema, processor)),
+        ));
+        self
+    }
+
+    /// Adds a metrics component to the pipeline for performance tracking.
+    ///
+    /// This component collects and reports on pipeline metrics, providing
+    /// insights into performance and operational statistics.
+    ///
+    /// # Parameters
+    ///
+    /// - `metrics`: An instance of a `Metrics` implementation, used to gather
+    ///   and report metrics.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .metrics(Arc::new(LogMetrics::new()));
+    /// ```
+    pub fn metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        log::trace!("metrics(self, metrics: {:?})", stringify!(metrics));
+        self.metrics.metrics.push(metrics);
+        self
+    }
+
+    /// Sets the interval for flushing metrics data.
+    ///
+    /// This value defines the frequency, in seconds, at which metrics data is
+    /// flushed from memory. If not set, a default interval is used.
+    ///
+    /// # Parameters
+    ///
+    /// - `interval`: The flush interval for metrics, in seconds.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .metrics_flush_interval(60);
+    /// ```
+    pub fn metrics_flush_interval(mut self, interval: u64) -> Self {
+        log::trace!("metrics_flush_interval(self, interval: {:?})", interval);
+        self.metrics_flush_interval = Some(interval);
+        self
+    }
+
+    /// Sets the `Checkpointer` used to persist datasource progress.
+    ///
+    /// On startup, the pipeline loads the checkpoint of every datasource and
+    /// passes it to `Datasource::consume` as the resume point. While running,
+    /// it periodically commits the highest slot of each datasource whose
+    /// updates have all been processed by every pipe. A failed update holds
+    /// back the checkpoint of its datasource, so that its slot is processed
+    /// again after a restart.
+    ///
+    /// Updates discarded by `OverflowPolicy::DropOldest` or `DropNewest` do
+    /// not hold back checkpoints, so they are lost for good once a later
+    /// checkpoint has been committed.
+    ///
+    /// # Parameters
+    ///
+    /// - `checkpointer`: The `Checkpointer` implementation to use.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .checkpointer(Arc::new(FileCheckpointer::new("checkpoints.json")));
+    /// ```
+    pub fn checkpointer(mut self, checkpointer: Arc<dyn Checkpointer>) -> Self {
+        log::trace!(
+            "checkpointer(self, checkpointer: {:?})",
+            stringify!(checkpointer)
+        );
+        self.checkpointer = Some(checkpointer);
+        self
+    }
+
+    /// Sets how often checkpoints are committed.
+    ///
+    /// Checkpoints are also committed once more when the pipeline shuts down.
+    ///
+    /// # Parameters
+    ///
+    /// - `interval`: The interval between commits. Defaults to one second.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .checkpoint_interval(Duration::from_secs(10));
+    /// ```
+    pub fn checkpoint_interval(mut self, interval: time::Duration) -> Self {
+        log::trace!("checkpoint_interval(self, interval: {:?})", interval);
+        self.checkpoint_interval = Some(interval);
+        self
+    }
+
+    /// Sets how often pipes are flushed.
+    ///
+    /// On every flush, the processors of all pipes are asked to write out the
+    /// data they buffer, such as the pending batch of a `Batched` processor.
+    /// Pipes are also flushed once more when the pipeline shuts down, under
+    /// either `ShutdownStrategy`, and before checkpoints are committed.
+    ///
+    /// # Parameters
+    ///
+    /// - `interval`: The interval between flushes. Defaults to one second.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .account(MyAccountDecoder, Batched::new(MyBatchProcessor, 500))
+    ///     .flush_interval(Duration::from_millis(200));
+    /// ```
+    ///
+    /// # Notes
+    ///
+    /// - When workers are configured, each worker flushes its own pipes on
+    ///   this interval. Before checkpoints are committed, every worker is
+    ///   also asked to flush, and the commit waits for all of them.
+    /// - If a pipe fails to flush, checkpoints are held back until a later
+    ///   flush succeeds.
+    pub fn flush_interval(mut self, interval: time::Duration) -> Self {
+        log::trace!("flush_interval(self, interval: {:?})", interval);
+        self.flush_interval = Some(interval);
+        self
+    }
+
+    /// Sets how failed datasources are restarted.
+    ///
+    /// When a datasource's `consume` returns an error, it is restarted after
+    /// an exponentially growing backoff, until it has used up the restarts of
+    /// the policy. Each restart is counted in the `datasource_restarts`
+    /// counter.
+    ///
+    /// # Parameters
+    ///
+    /// - `restart_policy`: The `RestartPolicy` applied to every datasource.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .datasource(MyDatasource::new())
+    ///     .datasource_restart_policy(RestartPolicy::default());
+    /// ```
+    pub fn datasource_restart_policy(mut self, restart_policy: RestartPolicy) -> Self {
+        log::trace!(
+            "datasource_restart_policy(self, restart_policy: {:?})",
+            restart_policy
+        );
+        self.datasource_restart_policy = Some(restart_policy);
+        self
+    }
+
+    /// Sets the capacity of the update queue between the datasources and the
+    /// pipeline.
+    ///
+    /// Without a capacity the queue is unbounded, so a slow processor lets it
+    /// grow without limit. Once the queue holds `capacity` updates, the
+    /// configured `channel_overflow_policy` decides what happens to new ones.
+    ///
+    /// # Parameters
+    ///
+    /// - `capacity`: The maximum number of updates held in memory. Must be
+    ///   greater than zero.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .channel_capacity(10_000);
+    /// ```
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        log::trace!("channel_capacity(self, capacity: {:?})", capacity);
+        self.channel_capacity = Some(capacity);
+        self
+    }
+
+    /// Sets the policy applied when the update queue is full.
+    ///
+    /// This only has an effect together with `channel_capacity`.
+    ///
+    /// # Parameters
+    ///
+    /// - `policy`: A variant of [`OverflowPolicy`]. `OverflowPolicy::Block`
+    ///   (the default) makes datasources wait until there is room,
+    ///   `DropOldest` and `DropNewest` discard updates, and `SpillToDisk`
+    ///   writes the overflow to a file.
+    ///
+    /// # Notes
+    ///
+    /// - Discarded updates do not hold back checkpoints. With a
+    ///   `checkpointer`, prefer `Block` or `SpillToDisk`, which never lose an
+    ///   update.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let builder = PipelineBuilder::new()
+    ///     .channel_capacity(10_000)
+    ///     .channel_overflow_policy(OverflowPolicy::DropOldest);
+    /// ```
+    pub fn channel_overflow_policy(
+        mut self,
+        policy: OverflowPolicy<(Update, DatasourceId)>,
+    ) -> Self {
+        log::trace!("channel_overflow_policy(self, policy: {:?})", policy);
+        self.channel_overflow_policy = policy;
         self
     }
 
-    /// Adds a metrics component to the pipeline for performance tracking.
+    /// Processes updates concurrently on a pool of workers.
     ///
-    /// This component collects and reports on pipeline metrics, providing
-    /// insights into performance and operational statistics.
+    /// Each worker owns its own set of pipes, registered by calling `pipes`
+    /// with an empty builder once per worker, so processors can hold
+    /// per-worker state such as a database connection. Every update is
+    /// assigned to a worker by its shard key, and a worker processes its
+    /// updates one at a time, which keeps updates for the same key in order
+    /// while a slow update only holds up its own worker.
+    ///
+    /// When workers are configured, pipes must be registered inside `pipes`
+    /// rather than on this builder.
     ///
     /// # Parameters
     ///
-    /// - `metrics`: An instance of a `Metrics` implementation, used to gather
-    ///   and report metrics.
+    /// - `count`: The number of workers. Must be greater than zero.
+    /// - `pipes`: A function that registers one worker's pipes on the given
+    ///   builder and returns it.
     ///
     /// # Example
     ///
     /// ```rust
     /// let builder = PipelineBuilder::new()
-    ///     .metrics(Arc::new(LogMetrics::new()));
+    ///     .datasource(MyDatasource::new())
+    ///     .workers(8, move |builder| {
+    ///         builder
+    ///             .account(MyAccountDecoder, MyAccountProcessor::new(pool.clone()))
+    ///             .instruction(MyDecoder, MyInstructionProcessor::new(pool.clone()))
+    ///     });
     /// ```
-    pub fn metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
-        log::trace!("metrics(self, metrics: {:?})", stringify!(metrics));
-        self.metrics.metrics.push(metrics);
+    pub fn workers(
+        mut self,
+        count: usize,
+        pipes: impl Fn(PipelineBuilder) -> PipelineBuilder + Send + Sync + 'static,
+    ) -> Self {
+        log::trace!(
+            "workers(self, count: {:?}, pipes: {:?})",
+            count,
+            stringify!(pipes)
+        );
+        self.workers = Some((count, Box::new(pipes)));
         self
     }
 
-    /// Sets the interval for flushing metrics data.
+    /// Sets the key used to assign transaction updates to workers.
     ///
-    /// This value defines the frequency, in seconds, at which metrics data is
-    /// flushed from memory. If not set, a default interval is used.
+    /// This only has an effect together with `workers`.
     ///
     /// # Parameters
     ///
-    /// - `interval`: The flush interval for metrics, in seconds.
+    /// - `shard_key`: A variant of [`TransactionShardKey`]. Defaults to the
+    ///   fee payer.
     ///
     /// # Example
     ///
     /// ```rust
     /// let builder = PipelineBuilder::new()
-    ///     .metrics_flush_interval(60);
+    ///     .transaction_shard_key(TransactionShardKey::Custom(Arc::new(|update| {
+    ///         update.transaction.message.static_account_keys()[1]
+    ///     })));
     /// ```
-    pub fn metrics_flush_interval(mut self, interval: u64) -> Self {
-        log::trace!("metrics_flush_interval(self, interval: {:?})", interval);
-        self.metrics_flush_interval = Some(interval);
+    pub fn transaction_shard_key(mut self, shard_key: TransactionShardKey) -> Self {
+        log::trace!("transaction_shard_key(self, shard_key: {:?})", shard_key);
+        self.transaction_shard_key = shard_key;
         self
     }
 
@@ -899,21 +3935,1013 @@ impl PipelineBuilder {
     ///     TestProgramDecoder,
     ///     TestProgramAccountProcessor
     /// )
-    /// .transaction(TEST_SCHEMA.clone(), TestProgramTransactionProcessor)
+    /// .transaction(TestProgramTransactionProcessor, Some(TEST_SCHEMA.clone()))
     /// .account_deletions(TestProgramAccountDeletionProcessor)
     /// .build()?
     /// ```
     pub fn build(self) -> CarbonResult<Pipeline> {
         log::trace!("build(self)");
-        Ok(Pipeline {
+        let metrics = Arc::new(self.metrics);
+        let checkpoint_tracker = self
+            .checkpointer
+            .as_ref()
+            .map(|_| Arc::new(Mutex::new(CheckpointTracker::default())));
+        let shutdown_sender = Arc::new(tokio::sync::watch::channel(None).0);
+        let fatal_error = Arc::new(Mutex::new(None));
+        let chain_tip = Arc::new(Mutex::new(ChainTip::default()));
+
+        if self.checkpointer.is_some()
+            && self.channel_capacity.is_some()
+            && matches!(
+                self.channel_overflow_policy,
+                OverflowPolicy::DropOldest | OverflowPolicy::DropNewest
+            )
+        {
+            log::warn!(
+                "{:?} may drop updates that later checkpoints skip over.",
+                self.channel_overflow_policy
+            );
+        }
+
+        if self.dedup_max_entries == Some(0) {
+            return Err(Error::Custom(
+                "dedup max entries must be greater than zero".to_string(),
+            ));
+        }
+
+        let mut pipe_names = HashSet::new();
+        let mut names = self
+            .account_pipes
+            .iter()
+            .map(|entry| &entry.name)
+            .chain(self.account_deletion_pipes.iter().map(|entry| &entry.name))
+            .chain(self.instruction_pipes.iter().map(|entry| &entry.name))
+            .chain(self.transaction_pipes.iter().map(|entry| &entry.name))
+            .chain(self.block_pipes.iter().map(|entry| &entry.name))
+            .chain(self.slot_status_pipes.iter().map(|entry| &entry.name));
+        if let Some(name) = names.find(|name| !pipe_names.insert(*name)) {
+            return Err(Error::Custom(format!(
+                "pipe name {} is used by more than one pipe",
+                name
+            )));
+        }
+
+        let mut workers = Vec::new();
+        if let Some((count, pipes)) = self.workers {
+            if count == 0 {
+                return Err(Error::Custom(
+                    "worker count must be greater than zero".to_string(),
+                ));
+            }
+
+            if !self.account_pipes.is_empty()
+                || !self.account_deletion_pipes.is_empty()
+                || !self.instruction_pipes.is_empty()
+                || !self.transaction_pipes.is_empty()
+                || !self.block_pipes.is_empty()
+                || !self.slot_status_pipes.is_empty()
+            {
+                return Err(Error::Custom(
+                    "pipes must be registered per worker when workers are configured".to_string(),
+                ));
+            }
+
+            for index in 0..count {
+                let mut worker = pipes(PipelineBuilder::new()).build()?;
+                // Slot status updates reach every worker, but only the first
+                // one passes them to slot status pipes.
+                if index > 0 {
+                    worker.slot_status_pipes.clear();
+                }
+                worker.finalized_only = self.finalized_only;
+                worker.flush_interval = self.flush_interval.unwrap_or(DEFAULT_FLUSH_INTERVAL);
+                worker.metrics = metrics.clone();
+                worker.update_metrics = PipelineMetrics::new(&metrics);
+                worker.register_pipe_metrics();
+                worker.checkpoint_tracker = checkpoint_tracker.clone();
+                worker.dead_letter_sink = self.dead_letter_sink.clone();
+                worker.shutdown_sender = shutdown_sender.clone();
+                worker.fatal_error = fatal_error.clone();
+                worker.chain_tip = chain_tip.clone();
+                workers.push(worker);
+            }
+        }
+
+        let mut pipeline = Pipeline {
             datasources: self.datasources,
             account_pipes: self.account_pipes,
             account_deletion_pipes: self.account_deletion_pipes,
             instruction_pipes: self.instruction_pipes,
             transaction_pipes: self.transaction_pipes,
+            block_pipes: self.block_pipes,
+            slot_status_pipes: self.slot_status_pipes,
             shutdown_strategy: self.shutdown_strategy,
-            metrics: Arc::new(self.metrics),
             metrics_flush_interval: self.metrics_flush_interval,
+            channel_capacity: self.channel_capacity,
+            channel_overflow_policy: self.channel_overflow_policy,
+            workers,
+            transaction_shard_key: self.transaction_shard_key,
+            shutdown_drain_timeout: self.shutdown_drain_timeout,
+            checkpointer: self.checkpointer,
+            checkpoint_interval: self
+                .checkpoint_interval
+                .unwrap_or(DEFAULT_CHECKPOINT_INTERVAL),
+            flush_interval: self.flush_interval.unwrap_or(DEFAULT_FLUSH_INTERVAL),
+            deduplicator: self.dedup_window.map(|window| {
+                Deduplicator::new(
+                    window,
+                    self.dedup_max_entries.unwrap_or(DEFAULT_DEDUP_MAX_ENTRIES),
+                )
+            }),
+            finalized_only: self.finalized_only,
+            dead_letter_sink: self.dead_letter_sink,
+            datasource_restart_policy: self.datasource_restart_policy,
+            stall_policies: self.stall_policies,
+            shutdown_sender,
+            checkpoint_tracker,
+            finality_buffer: FinalityBuffer::default(),
+            fatal_error,
+            update_metrics: PipelineMetrics::new(&metrics),
+            chain_tip,
+            metrics,
+        };
+        pipeline.register_pipe_metrics();
+
+        Ok(pipeline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            datasource::{AccountUpdate, UpdateType},
+            instruction::{DecodedInstruction, InstructionMetadata, NestedInstruction},
+            metrics::InMemoryMetrics,
+        },
+        async_trait::async_trait,
+        solana_sdk::{
+            instruction::{CompiledInstruction, Instruction},
+            message::{Message, VersionedMessage},
+            transaction::VersionedTransaction,
+        },
+        std::collections::VecDeque,
+    };
+
+    const WORKERS: usize = 4;
+
+    /// A datasource sending `updates` after `run_time` on every run. A run then
+    /// fails with the next error of `failures`. Once they are used up, it
+    /// returns, or waits until it is cancelled if `wait` is set.
+    #[derive(Clone, Default)]
+    struct ScriptedDatasource {
+        updates: Vec<Update>,
+        failures: Arc<Mutex<VecDeque<Error>>>,
+        run_time: time::Duration,
+        wait: bool,
+        runs: Arc<Mutex<Vec<tokio::time::Instant>>>,
+    }
+
+    impl ScriptedDatasource {
+        fn new(updates: Vec<Update>) -> Self {
+            Self {
+                updates,
+                ..Self::default()
+            }
+        }
+
+        fn failing(self, failures: impl IntoIterator<Item = Error>) -> Self {
+            lock(&self.failures).extend(failures);
+            self
+        }
+
+        fn running_for(self, run_time: time::Duration) -> Self {
+            Self { run_time, ..self }
+        }
+
+        fn waiting(self) -> Self {
+            Self { wait: true, ..self }
+        }
+
+        fn runs(&self) -> Vec<tokio::time::Instant> {
+            lock(&self.runs).clone()
+        }
+    }
+
+    #[async_trait]
+    impl Datasource for ScriptedDatasource {
+        async fn consume(
+            &self,
+            id: DatasourceId,
+            sender: &channel::UpdateSender<(Update, DatasourceId)>,
+            _resume_from: Option<Checkpoint>,
+            cancellation_token: CancellationToken,
+            _metrics: Arc<MetricsCollection>,
+        ) -> CarbonResult<()> {
+            lock(&self.runs).push(tokio::time::Instant::now());
+            tokio::time::sleep(self.run_time).await;
+            for update in &self.updates {
+                sender.send((update.clone(), id.clone())).await?;
+            }
+
+            let failure = lock(&self.failures).pop_front();
+            if let Some(error) = failure {
+                return Err(error);
+            }
+            if self.wait {
+                cancellation_token.cancelled().await;
+            }
+            Ok(())
+        }
+
+        fn update_types(&self) -> Vec<UpdateType> {
+            vec![UpdateType::BlockDetails]
+        }
+    }
+
+    /// A block processor recording the slots it processes, taking `delay` for
+    /// each, and the slots it rolls back. It fails with the errors of
+    /// `failures`, in order, before succeeding.
+    #[derive(Clone, Default)]
+    struct BlockRecorder {
+        processed: Arc<Mutex<Vec<u64>>>,
+        rolled_back: Arc<Mutex<Vec<u64>>>,
+        failures: Arc<Mutex<VecDeque<Error>>>,
+        delay: time::Duration,
+    }
+
+    impl BlockRecorder {
+        fn taking(self, delay: time::Duration) -> Self {
+            Self { delay, ..self }
+        }
+
+        fn failing(self, failures: impl IntoIterator<Item = Error>) -> Self {
+            lock(&self.failures).extend(failures);
+            self
+        }
+
+        fn processed(&self) -> Vec<u64> {
+            lock(&self.processed).clone()
+        }
+
+        fn rolled_back(&self) -> Vec<u64> {
+            lock(&self.rolled_back).clone()
+        }
+    }
+
+    #[async_trait]
+    impl Processor for BlockRecorder {
+        type InputType = BlockDetails;
+
+        async fn process(
+            &mut self,
+            block_details: BlockDetails,
+            _metrics: Arc<MetricsCollection>,
+        ) -> CarbonResult<()> {
+            tokio::time::sleep(self.delay).await;
+            let failure = lock(&self.failures).pop_front();
+            if let Some(error) = failure {
+                return Err(error);
+            }
+            lock(&self.processed).push(block_details.slot);
+            Ok(())
+        }
+
+        async fn rollback(
+            &mut self,
+            slot: u64,
+            _metrics: Arc<MetricsCollection>,
+        ) -> CarbonResult<()> {
+            lock(&self.rolled_back).push(slot);
+            Ok(())
+        }
+    }
+
+    fn block(slot: u64) -> Update {
+        Update::Block(BlockDetails {
+            slot,
+            ..Default::default()
+        })
+    }
+
+    fn slot_status(slot: u64, status: SlotStatus) -> Update {
+        Update::SlotStatus(SlotStatusUpdate {
+            slot,
+            parent: None,
+            status,
+        })
+    }
+
+    fn restart_policy(max_restarts: u32) -> RestartPolicy {
+        RestartPolicy {
+            max_restarts,
+            initial_backoff: time::Duration::from_millis(10),
+            max_backoff: time::Duration::from_millis(40),
+            fail_pipeline: false,
+            reset_after: time::Duration::from_secs(1),
+        }
+    }
+
+    fn account_update(pubkey: Pubkey) -> Update {
+        Update::Account(AccountUpdate {
+            pubkey,
+            account: Default::default(),
+            slot: 1,
+            write_version: None,
         })
     }
+
+    fn transaction_update(account_keys: Vec<Pubkey>) -> TransactionUpdate {
+        TransactionUpdate {
+            signature: Signature::new_unique(),
+            transaction: VersionedTransaction {
+                signatures: vec![],
+                message: VersionedMessage::Legacy(Message {
+                    account_keys,
+                    ..Default::default()
+                }),
+            },
+            meta: Default::default(),
+            is_vote: false,
+            slot: 1,
+            block_time: None,
+        }
+    }
+
+    #[test]
+    fn same_account_maps_to_same_worker() {
+        let pipeline = Pipeline::builder().build().unwrap();
+        let pubkey = Pubkey::new_unique();
+
+        let update = account_update(pubkey);
+        let worker = pipeline.worker_index(&update, WORKERS);
+        let deletion = Update::AccountDeletion(AccountDeletion { pubkey, slot: 2 });
+
+        assert!(worker < WORKERS);
+        assert_eq!(pipeline.worker_index(&update, WORKERS), worker);
+        assert_eq!(pipeline.worker_index(&deletion, WORKERS), worker);
+    }
+
+    #[test]
+    fn accounts_spread_across_workers() {
+        let pipeline = Pipeline::builder().build().unwrap();
+
+        let workers = (0..64)
+            .map(|_| pipeline.worker_index(&account_update(Pubkey::new_unique()), WORKERS))
+            .collect::<HashSet<_>>();
+
+        assert_eq!(workers.len(), WORKERS);
+    }
+
+    #[test]
+    fn transactions_are_keyed_by_fee_payer() {
+        let pipeline = Pipeline::builder().build().unwrap();
+        let fee_payer = Pubkey::new_unique();
+
+        let first = Update::Transaction(Box::new(transaction_update(vec![
+            fee_payer,
+            Pubkey::new_unique(),
+        ])));
+        let second = Update::Transaction(Box::new(transaction_update(vec![
+            fee_payer,
+            Pubkey::new_unique(),
+        ])));
+
+        assert_eq!(
+            pipeline.worker_index(&first, WORKERS),
+            pipeline.worker_index(&account_update(fee_payer), WORKERS)
+        );
+        assert_eq!(
+            pipeline.worker_index(&first, WORKERS),
+            pipeline.worker_index(&second, WORKERS)
+        );
+    }
+
+    #[test]
+    fn transactions_use_custom_shard_key() {
+        let key = Pubkey::new_unique();
+        let pipeline = Pipeline::builder()
+            .transaction_shard_key(TransactionShardKey::Custom(Arc::new(move |_| key)))
+            .build()
+            .unwrap();
+
+        let update = Update::Transaction(Box::new(transaction_update(vec![Pubkey::new_unique()])));
+
+        assert_eq!(
+            pipeline.worker_index(&update, WORKERS),
+            pipeline.worker_index(&account_update(key), WORKERS)
+        );
+    }
+
+    #[test]
+    fn chain_tip_follows_slot_status_updates() {
+        let mut chain_tip = ChainTip::default();
+
+        chain_tip.observe(&account_update(Pubkey::new_unique()));
+        assert_eq!(chain_tip.slot(), 1);
+
+        chain_tip.observe(&slot_status(10, SlotStatus::Processed));
+        chain_tip.observe(&slot_status(8, SlotStatus::Processed));
+        assert_eq!(chain_tip.slot(), 10);
+
+        let mut update = transaction_update(vec![Pubkey::new_unique()]);
+        update.slot = 12;
+        chain_tip.observe(&Update::Transaction(Box::new(update)));
+        assert_eq!(chain_tip.slot(), 10);
+    }
+
+    #[tokio::test]
+    async fn retry_policy_retries_unclassified_errors() {
+        let recorder = BlockRecorder::default().failing([Error::Custom("down".to_string())]);
+        let metrics = Arc::new(InMemoryMetrics::new());
+
+        Pipeline::builder()
+            .datasource(ScriptedDatasource::new(vec![block(7)]))
+            .metrics(metrics.clone())
+            .error_policy(ErrorPolicy::Retry {
+                max_retries: 2,
+                initial_backoff: time::Duration::from_millis(1),
+                max_backoff: time::Duration::from_millis(1),
+            })
+            .block(recorder.clone())
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(recorder.processed(), [7]);
+        assert_eq!(metrics.counter("pipe_errors"), 0);
+    }
+
+    #[tokio::test]
+    async fn restart_policy_restarts_on_unclassified_errors() {
+        let datasource =
+            ScriptedDatasource::default().failing([Error::Custom("disconnected".to_string())]);
+        let metrics = Arc::new(InMemoryMetrics::new());
+
+        Pipeline::builder()
+            .datasource(datasource.clone())
+            .metrics(metrics.clone())
+            .datasource_restart_policy(restart_policy(3))
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(datasource.runs().len(), 2);
+        assert_eq!(
+            metrics.counter_with_labels("datasource_restarts", &[("datasource", "datasource_0")]),
+            1
+        );
+    }
+
+    fn disconnected() -> Error {
+        Error::FailedToConsumeDatasource("disconnected".to_string())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_datasources_restart_with_backoff() {
+        let datasource = ScriptedDatasource::default().failing([
+            disconnected(),
+            disconnected(),
+            disconnected(),
+            disconnected(),
+        ]);
+        let metrics = Arc::new(InMemoryMetrics::new());
+
+        Pipeline::builder()
+            .datasource(datasource.clone())
+            .metrics(metrics.clone())
+            .datasource_restart_policy(restart_policy(5))
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        let runs = datasource.runs();
+        let backoffs = runs
+            .windows(2)
+            .map(|runs| runs[1] - runs[0])
+            .collect::<Vec<_>>();
+        assert_eq!(backoffs, [10, 20, 40, 40].map(time::Duration::from_millis));
+        assert_eq!(
+            metrics.counter_with_labels("datasource_restarts", &[("datasource", "datasource_0")]),
+            4
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn datasources_stay_stopped_once_restarts_are_used_up() {
+        let datasource =
+            ScriptedDatasource::new(vec![block(1)]).failing([disconnected(), disconnected()]);
+        let recorder = BlockRecorder::default();
+
+        Pipeline::builder()
+            .datasource(datasource.clone())
+            .datasource_restart_policy(restart_policy(1))
+            .block(recorder.clone())
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(datasource.runs().len(), 2);
+        assert_eq!(recorder.processed(), [1, 1]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn used_up_restarts_fail_the_pipeline_if_configured() {
+        let datasource = ScriptedDatasource::default().failing([disconnected(), disconnected()]);
+
+        let result = Pipeline::builder()
+            .datasource(datasource.clone())
+            .datasource_restart_policy(RestartPolicy {
+                fail_pipeline: true,
+                ..restart_policy(1)
+            })
+            .build()
+            .unwrap()
+            .run()
+            .await;
+
+        assert!(matches!(result, Err(Error::FailedToConsumeDatasource(_))));
+        assert_eq!(datasource.runs().len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restarts_start_over_after_a_stable_run() {
+        let datasource = ScriptedDatasource::new(vec![block(1)])
+            .running_for(time::Duration::from_secs(2))
+            .failing([disconnected(), disconnected(), disconnected()]);
+
+        Pipeline::builder()
+            .datasource(datasource.clone())
+            .datasource_restart_policy(restart_policy(1))
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        let runs = datasource.runs();
+        assert_eq!(runs.len(), 4);
+        assert_eq!(
+            runs[3] - runs[2],
+            time::Duration::from_secs(2) + time::Duration::from_millis(10)
+        );
+    }
+
+    fn stall_policy(action: StallAction) -> StallPolicy {
+        StallPolicy {
+            timeout: time::Duration::from_secs(30),
+            action,
+        }
+    }
+
+    fn stalls(metrics: &InMemoryMetrics) -> u64 {
+        metrics.counter_with_labels("datasource_stalls", &[("datasource", "datasource_0")])
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_datasources_are_reported() {
+        let datasource = ScriptedDatasource::new(vec![block(1)]).waiting();
+        let metrics = Arc::new(InMemoryMetrics::new());
+        let mut pipeline = Pipeline::builder()
+            .stall_policy(stall_policy(StallAction::Warn))
+            .datasource(datasource.clone())
+            .metrics(metrics.clone())
+            .build()
+            .unwrap();
+        let handle = pipeline.handle();
+
+        let pipeline_task = tokio::spawn(async move { pipeline.run().await });
+        tokio::time::sleep(time::Duration::from_secs(95)).await;
+        handle.shutdown(ShutdownStrategy::Immediate);
+        pipeline_task.await.unwrap().unwrap();
+
+        assert_eq!(stalls(&metrics), 3);
+        assert_eq!(datasource.runs().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_datasources_are_restarted() {
+        let datasource = ScriptedDatasource::new(vec![block(1)]).waiting();
+        let metrics = Arc::new(InMemoryMetrics::new());
+
+        Pipeline::builder()
+            .stall_policy(stall_policy(StallAction::Restart))
+            .datasource(datasource.clone())
+            .metrics(metrics.clone())
+            .datasource_restart_policy(restart_policy(2))
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        let runs = datasource.runs();
+        assert_eq!(runs.len(), 3);
+        assert_eq!(
+            runs[1] - runs[0],
+            time::Duration::from_secs(30) + time::Duration::from_millis(10)
+        );
+        assert_eq!(stalls(&metrics), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_datasources_fail_the_pipeline_if_configured() {
+        let datasource = ScriptedDatasource::new(vec![block(1)]).waiting();
+        let metrics = Arc::new(InMemoryMetrics::new());
+
+        let result = Pipeline::builder()
+            .stall_policy(stall_policy(StallAction::Fail))
+            .datasource(datasource.clone())
+            .metrics(metrics.clone())
+            .datasource_restart_policy(restart_policy(2))
+            .build()
+            .unwrap()
+            .run()
+            .await;
+
+        assert!(matches!(result, Err(Error::DatasourceStalled(_))));
+        assert_eq!(datasource.runs().len(), 1);
+        assert_eq!(stalls(&metrics), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn handle_shuts_down_a_running_pipeline() {
+        let datasource = ScriptedDatasource::new(vec![block(1), block(2), block(3)]).waiting();
+        let recorder = BlockRecorder::default();
+        let mut pipeline = Pipeline::builder()
+            .datasource(datasource.clone())
+            .block(recorder.clone())
+            .build()
+            .unwrap();
+        let handle = pipeline.handle();
+
+        let pipeline_task = tokio::spawn(async move { pipeline.run().await });
+        tokio::time::sleep(time::Duration::from_secs(1)).await;
+        assert!(!pipeline_task.is_finished());
+
+        handle.shutdown(ShutdownStrategy::ProcessPending);
+        pipeline_task.await.unwrap().unwrap();
+
+        assert_eq!(recorder.processed(), [1, 2, 3]);
+        assert_eq!(datasource.runs().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drain_timeout_abandons_pending_updates() {
+        let datasource = ScriptedDatasource::new((1..=10).map(block).collect()).waiting();
+        let recorder = BlockRecorder::default().taking(time::Duration::from_secs(10));
+        let mut pipeline = Pipeline::builder()
+            .datasource(datasource)
+            .shutdown_drain_timeout(time::Duration::from_secs(25))
+            .block(recorder.clone())
+            .build()
+            .unwrap();
+        pipeline.handle().shutdown(ShutdownStrategy::ProcessPending);
+
+        let start = tokio::time::Instant::now();
+        pipeline.run().await.unwrap();
+
+        // The update being processed when the timeout elapses is finished, so
+        // the pipeline stops within one update of the timeout.
+        let processed = recorder.processed().len();
+        assert!(
+            (2..10).contains(&processed),
+            "processed {processed} updates"
+        );
+        assert!(start.elapsed() <= time::Duration::from_secs(40));
+    }
+
+    #[tokio::test]
+    async fn dead_slots_roll_back_the_pipes() {
+        let recorder = BlockRecorder::default();
+
+        Pipeline::builder()
+            .datasource(ScriptedDatasource::new(vec![
+                block(5),
+                slot_status(5, SlotStatus::Dead),
+            ]))
+            .block(recorder.clone())
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(recorder.processed(), [5]);
+        assert_eq!(recorder.rolled_back(), [5]);
+    }
+
+    #[tokio::test]
+    async fn dead_slots_roll_back_every_worker() {
+        let recorder = BlockRecorder::default();
+        let worker_recorder = recorder.clone();
+
+        Pipeline::builder()
+            .datasource(ScriptedDatasource::new(vec![
+                block(5),
+                slot_status(5, SlotStatus::Dead),
+            ]))
+            .workers(2, move |builder| builder.block(worker_recorder.clone()))
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(recorder.processed(), [5]);
+        assert_eq!(recorder.rolled_back(), [5, 5]);
+    }
+
+    #[tokio::test]
+    async fn finalized_only_releases_rooted_slots_and_discards_dead_ones() {
+        let recorder = BlockRecorder::default();
+        let metrics = Arc::new(InMemoryMetrics::new());
+
+        Pipeline::builder()
+            .datasource(ScriptedDatasource::new(vec![
+                block(1),
+                block(2),
+                block(3),
+                slot_status(3, SlotStatus::Confirmed),
+                slot_status(2, SlotStatus::Dead),
+                slot_status(1, SlotStatus::Rooted),
+            ]))
+            .finalized_only(true)
+            .metrics(metrics.clone())
+            .block(recorder.clone())
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(recorder.processed(), [1]);
+        assert!(recorder.rolled_back().is_empty());
+        assert_eq!(metrics.counter("updates_rolled_back"), 1);
+        assert_eq!(metrics.gauge("updates_awaiting_finality"), Some(1.0));
+    }
+
+    #[test]
+    fn finality_buffer_releases_late_updates_of_rooted_slots() {
+        let received = |update| ReceivedUpdate {
+            update,
+            datasource_id: DatasourceId::new("rpc"),
+            sent_at: SystemTime::now(),
+        };
+        let mut finality_buffer = FinalityBuffer::default();
+
+        let (released, _) = finality_buffer.receive(received(block(1)));
+        assert!(released.is_empty());
+
+        let (released, _) = finality_buffer.receive(received(slot_status(1, SlotStatus::Rooted)));
+        assert_eq!(released.len(), 1);
+
+        let (released, _) = finality_buffer.receive(received(block(1)));
+        assert_eq!(released.len(), 1);
+        assert_eq!(finality_buffer.len(), 0);
+    }
+
+    /// Collects the dead letters it receives.
+    #[derive(Clone, Default)]
+    struct DeadLetters(Arc<Mutex<Vec<DeadLetter>>>);
+
+    #[async_trait]
+    impl DeadLetterSink for DeadLetters {
+        async fn send(&self, dead_letter: DeadLetter) -> CarbonResult<()> {
+            lock(&self.0).push(dead_letter);
+            Ok(())
+        }
+    }
+
+    /// Decodes every instruction into its data.
+    struct DataDecoder;
+
+    impl InstructionDecoder<'_> for DataDecoder {
+        type InstructionType = Vec<u8>;
+
+        fn decode_instruction(
+            &self,
+            instruction: &Instruction,
+        ) -> Option<DecodedInstruction<Vec<u8>>> {
+            Some(DecodedInstruction {
+                program_id: instruction.program_id,
+                data: instruction.data.clone(),
+                accounts: instruction.accounts.clone(),
+            })
+        }
+    }
+
+    /// Records the data of the instructions it processes, failing on the ones
+    /// whose data is empty.
+    #[derive(Clone, Default)]
+    struct InstructionRecorder(Arc<Mutex<Vec<Vec<u8>>>>);
+
+    #[async_trait]
+    impl Processor for InstructionRecorder {
+        type InputType = (
+            InstructionMetadata,
+            DecodedInstruction<Vec<u8>>,
+            Vec<NestedInstruction>,
+        );
+
+        async fn process(
+            &mut self,
+            (_, instruction, _): Self::InputType,
+            _metrics: Arc<MetricsCollection>,
+        ) -> CarbonResult<()> {
+            if instruction.data.is_empty() {
+                return Err(Error::MissingInstructionData);
+            }
+            lock(&self.0).push(instruction.data);
+            Ok(())
+        }
+    }
+
+    fn invalid() -> Error {
+        Error::fatal("invalid block", std::io::Error::other("invalid"))
+    }
+
+    #[tokio::test]
+    async fn skipped_failures_do_not_stop_other_pipes_or_updates() {
+        let failing = BlockRecorder::default().failing([invalid()]);
+        let recorder = BlockRecorder::default();
+        let metrics = Arc::new(InMemoryMetrics::new());
+
+        Pipeline::builder()
+            .datasource(ScriptedDatasource::new(vec![block(1), block(2)]))
+            .metrics(metrics.clone())
+            .block(failing.clone())
+            .block(recorder.clone())
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(failing.processed(), [2]);
+        assert_eq!(recorder.processed(), [1, 2]);
+        assert_eq!(metrics.counter("pipe_errors"), 1);
+    }
+
+    #[tokio::test]
+    async fn retried_pipes_skip_the_update_once_retries_run_out() {
+        let recorder = BlockRecorder::default().failing([
+            Error::Custom("down".to_string()),
+            Error::Custom("down".to_string()),
+        ]);
+        let metrics = Arc::new(InMemoryMetrics::new());
+
+        Pipeline::builder()
+            .datasource(ScriptedDatasource::new(vec![block(1), block(2)]))
+            .metrics(metrics.clone())
+            .error_policy(ErrorPolicy::Retry {
+                max_retries: 1,
+                initial_backoff: time::Duration::from_millis(1),
+                max_backoff: time::Duration::from_millis(1),
+            })
+            .block(recorder.clone())
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(recorder.processed(), [2]);
+        assert_eq!(metrics.counter("pipe_errors"), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_updates_are_sent_to_the_dead_letter_sink() {
+        let recorder = BlockRecorder::default().failing([invalid()]);
+        let dead_letters = DeadLetters::default();
+        let metrics = Arc::new(InMemoryMetrics::new());
+
+        Pipeline::builder()
+            .datasource(ScriptedDatasource::new(vec![block(1), block(2)]))
+            .metrics(metrics.clone())
+            .dead_letter_sink(Arc::new(dead_letters.clone()))
+            .error_policy(ErrorPolicy::DeadLetter)
+            .block(recorder.clone())
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(recorder.processed(), [2]);
+        assert_eq!(metrics.counter("dead_letters"), 1);
+
+        let dead_letters = lock(&dead_letters.0);
+        assert_eq!(dead_letters.len(), 1);
+        assert!(matches!(&dead_letters[0].update, Update::Block(block) if block.slot == 1));
+        assert_eq!(dead_letters[0].pipe, "block_0");
+        assert_eq!(
+            dead_letters[0].datasource_id,
+            DatasourceId::new("datasource_0")
+        );
+        assert_eq!(dead_letters[0].instruction, None);
+    }
+
+    #[tokio::test]
+    async fn halting_pipes_stop_the_pipeline() {
+        let recorder = BlockRecorder::default().failing([invalid()]);
+        let other = BlockRecorder::default();
+
+        let result = Pipeline::builder()
+            .datasource(ScriptedDatasource::new(vec![block(1), block(2), block(3)]))
+            .error_policy(ErrorPolicy::Halt)
+            .block(recorder.clone())
+            .block(other.clone())
+            .build()
+            .unwrap()
+            .run()
+            .await;
+
+        assert!(matches!(result, Err(Error::PipelineHalted(_))));
+        assert!(recorder.processed().is_empty());
+        assert!(other.processed().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_instructions_are_handled_on_their_own() {
+        let recorder = InstructionRecorder::default();
+        let dead_letters = DeadLetters::default();
+        let mut transaction = transaction_update(vec![Pubkey::new_unique(), Pubkey::new_unique()]);
+        if let VersionedMessage::Legacy(message) = &mut transaction.transaction.message {
+            message.instructions = [vec![1], vec![], vec![3]]
+                .into_iter()
+                .map(|data| CompiledInstruction::new_from_raw_parts(1, data, vec![0]))
+                .collect();
+        }
+
+        Pipeline::builder()
+            .datasource(ScriptedDatasource::new(vec![Update::Transaction(
+                Box::new(transaction),
+            )]))
+            .dead_letter_sink(Arc::new(dead_letters.clone()))
+            .error_policy(ErrorPolicy::DeadLetter)
+            .instruction(DataDecoder, recorder.clone())
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(*lock(&recorder.0), [vec![1], vec![3]]);
+
+        let dead_letters = lock(&dead_letters.0);
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].pipe, "instruction_0");
+        assert_eq!(dead_letters[0].instruction, Some(1));
+    }
+
+    #[tokio::test]
+    async fn pipe_metrics_are_labeled_with_the_pipe() {
+        let metrics = Arc::new(InMemoryMetrics::new());
+
+        Pipeline::builder()
+            .datasource(ScriptedDatasource::new(vec![block(1), block(2)]))
+            .metrics(metrics.clone())
+            .block(BlockRecorder::default().failing([invalid()]))
+            .block_with_name(BlockRecorder::default(), "blocks")
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        let block_0 = [("pipe", "block_0")];
+        let blocks = [("pipe", "blocks")];
+        assert_eq!(
+            metrics.counter_with_labels("pipe_updates_successful", &block_0),
+            1
+        );
+        assert_eq!(
+            metrics.counter_with_labels("pipe_updates_failed", &block_0),
+            1
+        );
+        assert_eq!(
+            metrics.counter_with_labels("pipe_updates_successful", &blocks),
+            2
+        );
+        assert_eq!(
+            metrics.counter_with_labels("pipe_updates_failed", &blocks),
+            0
+        );
+        assert_eq!(
+            metrics
+                .histogram_with_labels("pipe_process_time_nanoseconds", &blocks)
+                .len(),
+            2
+        );
+    }
 }
@@ -30,6 +30,29 @@
 //!   asynchronous and should be implemented to define how data should be
 //!   processed in your specific use case.
 //!
+//! ### Provided Methods
+//!
+//! - `rollback`: Called when a slot the processor may have received data for
+//!   is abandoned by a fork. The default implementation does nothing.
+//! - `initialize`: Called once when the pipeline starts, before any data is
+//!   processed, for example to open connections. The default implementation
+//!   does nothing.
+//! - `flush`: Called periodically and when the pipeline stops, so that
+//!   processors buffering data can write it out. The default implementation
+//!   does nothing.
+//! - `shutdown`: Called once when the pipeline stops, after the final
+//!   `flush`, for example to close connections. The default implementation
+//!   does nothing.
+//!
+//! ## Batching
+//!
+//! Sinks that write to a database are usually much faster when they insert
+//! many rows at once. Such sinks implement `BatchProcessor` instead, and are
+//! wrapped in `Batched`, which collects the data passed to `process` and hands
+//! it over in batches. Since `Batched` is itself a `Processor`, it can be
+//! registered with `PipelineBuilder::account`, `instruction` or `transaction`
+//! like any other processor.
+//!
 //! ## Parameters
 //!
 //! - `data`: An instance of the type specified by `InputType`. This represents
@@ -76,6 +99,15 @@ use {
 /// - `process`: Processes the specified `InputType` data asynchronously,
 ///   optionally updating associated metrics.
 ///
+/// # Provided Methods
+///
+/// - `rollback`: Undoes the effects of data processed for a dead slot. Sinks
+///   that write updates received before finality should override it.
+/// - `initialize`: Prepares the processor before the pipeline starts.
+/// - `flush`: Writes out data the processor has buffered.
+/// - `shutdown`: Releases the resources of the processor once the pipeline
+///   stops.
+///
 /// # Example
 ///
 /// ```rust
@@ -110,4 +142,333 @@ pub trait Processor {
         data: Self::InputType,
         metrics: Arc<MetricsCollection>,
     ) -> CarbonResult<()>;
+
+    /// Prepares the processor before the pipeline processes any data.
+    ///
+    /// The pipeline calls this once at the start of `Pipeline::run`, right
+    /// after initializing its metrics and before starting the datasources. An
+    /// error stops the pipeline before it starts.
+    async fn initialize(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+
+    /// Undoes the effects of all data processed for `slot`.
+    ///
+    /// The pipeline calls this when a datasource reports `slot` as dead,
+    /// after every update of that slot received earlier has been processed.
+    /// It is called even if the processor received no data for `slot`.
+    async fn rollback(&mut self, slot: u64, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = (slot, metrics);
+        Ok(())
+    }
+
+    /// Writes out any data the processor has buffered.
+    ///
+    /// The pipeline calls this every `PipelineBuilder::flush_interval` and
+    /// once more when it stops, under either `ShutdownStrategy`, after the
+    /// last update has been passed to `process`.
+    async fn flush(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+
+    /// Releases the resources of the processor once the pipeline stops.
+    ///
+    /// The pipeline calls this once at the end of `Pipeline::run`, after the
+    /// final `flush` and before shutting down its metrics. No data is passed
+    /// to the processor afterwards.
+    async fn shutdown(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+}
+
+/// A trait for processors that handle data in batches.
+///
+/// `BatchProcessor` is the batched counterpart of `Processor`. It receives
+/// the data collected by `Batched` as a single slice, in the order the data
+/// was passed to the pipeline.
+///
+/// # Example
+///
+/// ```rust
+/// struct PostgresSink {
+///     client: tokio_postgres::Client,
+/// }
+///
+/// #[async_trait]
+/// impl BatchProcessor for PostgresSink {
+///     type InputType = AccountProcessorInputType<MyAccount>;
+///
+///     async fn process_batch(
+///         &mut self,
+///         batch: &[Self::InputType],
+///         metrics: Arc<MetricsCollection>,
+///     ) -> CarbonResult<()> {
+///         // Insert all rows with a single statement
+///         Ok(())
+///     }
+/// }
+///
+/// let builder = PipelineBuilder::new()
+///     .account(MyAccountDecoder, Batched::new(PostgresSink { client }, 1_000));
+/// ```
+#[async_trait]
+pub trait BatchProcessor {
+    type InputType;
+
+    async fn process_batch(
+        &mut self,
+        batch: &[Self::InputType],
+        metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()>;
+
+    /// Prepares the processor before the pipeline processes any data.
+    ///
+    /// See `Processor::initialize`.
+    async fn initialize(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+
+    /// Undoes the effects of all data processed for `slot`.
+    ///
+    /// See `Processor::rollback`. Any pending batch is processed before this
+    /// is called.
+    async fn rollback(&mut self, slot: u64, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = (slot, metrics);
+        Ok(())
+    }
+
+    /// Releases the resources of the processor once the pipeline stops.
+    ///
+    /// See `Processor::shutdown`. The last batch has been processed when this
+    /// is called.
+    async fn shutdown(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+}
+
+/// Adapts a `BatchProcessor` into a `Processor`.
+///
+/// Data passed to `process` is collected into a batch, which is handed to the
+/// `BatchProcessor` once it holds `max_batch_size` items, and otherwise
+/// whenever the pipeline flushes its pipes. The time a batch waits is
+/// therefore bounded by the pipeline's flush interval.
+///
+/// # Notes
+///
+/// - Data is reported as processed once it is in the batch. If a batch fails,
+///   it is kept whole and retried on the next flush, whose error holds back
+///   the pipeline's checkpoints until the batch goes through.
+/// - While a failed batch is full, `process` retries it before accepting more
+///   data. If the retry fails, the new data is not added to the batch and the
+///   error is reported for it, so that the pipe's `ErrorPolicy` decides what
+///   happens to it.
+pub struct Batched<P: BatchProcessor> {
+    processor: P,
+    max_batch_size: usize,
+    batch: Vec<P::InputType>,
+}
+
+impl<P: BatchProcessor> Batched<P> {
+    /// Wraps `processor`, handing it batches of at most `max_batch_size`
+    /// items. A `max_batch_size` of zero is treated as one.
+    pub fn new(processor: P, max_batch_size: usize) -> Self {
+        let max_batch_size = max_batch_size.max(1);
+
+        Self {
+            processor,
+            max_batch_size,
+            batch: Vec::with_capacity(max_batch_size),
+        }
+    }
+
+    /// Returns the number of items waiting in the current batch.
+    pub fn pending(&self) -> usize {
+        self.batch.len()
+    }
+}
+
+impl<P> Batched<P>
+where
+    P: BatchProcessor + Send,
+    P::InputType: Send + Sync,
+{
+    async fn process_pending(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        self.processor
+            .process_batch(&self.batch, metrics.clone())
+            .await?;
+        self.batch.clear();
+
+        metrics.counter("batches_processed").increment(1);
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<P> Processor for Batched<P>
+where
+    P: BatchProcessor + Send + Sync,
+    P::InputType: Send + Sync,
+{
+    type InputType = P::InputType;
+
+    async fn process(
+        &mut self,
+        data: Self::InputType,
+        metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()> {
+        if self.batch.len() >= self.max_batch_size {
+            self.process_pending(metrics.clone()).await?;
+        }
+
+        self.batch.push(data);
+        if self.batch.len() < self.max_batch_size {
+            return Ok(());
+        }
+
+        if let Err(error) = self.process_pending(metrics).await {
+            log::warn!(
+                "batch of {} items failed, retrying on the next flush: {:?}",
+                self.batch.len(),
+                error
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn initialize(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        self.processor.initialize(metrics).await
+    }
+
+    async fn rollback(&mut self, slot: u64, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        self.process_pending(metrics.clone()).await?;
+        self.processor.rollback(slot, metrics).await
+    }
+
+    async fn flush(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        self.process_pending(metrics).await
+    }
+
+    async fn shutdown(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        self.process_pending(metrics.clone()).await?;
+        self.processor.shutdown(metrics).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::error::Error,
+        std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+    };
+
+    /// Records the batches it receives, failing the first `failures` of them.
+    #[derive(Default)]
+    struct Recorder {
+        batches: Arc<Mutex<Vec<Vec<u32>>>>,
+        failures: Arc<AtomicUsize>,
+        shut_down: bool,
+    }
+
+    #[async_trait]
+    impl BatchProcessor for Recorder {
+        type InputType = u32;
+
+        async fn process_batch(
+            &mut self,
+            batch: &[u32],
+            _metrics: Arc<MetricsCollection>,
+        ) -> CarbonResult<()> {
+            let failures = self.failures.load(Ordering::SeqCst);
+            if failures > 0 {
+                self.failures.store(failures - 1, Ordering::SeqCst);
+                return Err(Error::transient("batch failed", "down"));
+            }
+
+            self.batches.lock().unwrap().push(batch.to_vec());
+            Ok(())
+        }
+
+        async fn shutdown(&mut self, _metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+            self.shut_down = true;
+            Ok(())
+        }
+    }
+
+    fn metrics() -> Arc<MetricsCollection> {
+        Arc::new(MetricsCollection::new(vec![]))
+    }
+
+    #[tokio::test]
+    async fn processes_batch_once_full() {
+        let recorder = Recorder::default();
+        let batches = recorder.batches.clone();
+        let mut batched = Batched::new(recorder, 2);
+
+        for data in 0..5 {
+            batched.process(data, metrics()).await.unwrap();
+        }
+
+        assert_eq!(*batches.lock().unwrap(), vec![vec![0, 1], vec![2, 3]]);
+        assert_eq!(batched.pending(), 1);
+    }
+
+    #[tokio::test]
+    async fn flush_and_shutdown_process_pending_batch() {
+        let recorder = Recorder::default();
+        let batches = recorder.batches.clone();
+        let mut batched = Batched::new(recorder, 10);
+
+        batched.process(1, metrics()).await.unwrap();
+        batched.flush(metrics()).await.unwrap();
+        assert_eq!(*batches.lock().unwrap(), vec![vec![1]]);
+        assert_eq!(batched.pending(), 0);
+
+        batched.process(2, metrics()).await.unwrap();
+        batched.shutdown(metrics()).await.unwrap();
+        assert_eq!(*batches.lock().unwrap(), vec![vec![1], vec![2]]);
+        assert!(batched.processor.shut_down);
+    }
+
+    #[tokio::test]
+    async fn failed_batch_is_kept_and_retried() {
+        let recorder = Recorder::default();
+        let batches = recorder.batches.clone();
+        recorder.failures.store(2, Ordering::SeqCst);
+        let mut batched = Batched::new(recorder, 2);
+
+        batched.process(1, metrics()).await.unwrap();
+        batched.process(2, metrics()).await.unwrap();
+        assert_eq!(batched.pending(), 2);
+
+        // The full batch is retried first and fails again, so the new data is
+        // rejected rather than added to it.
+        assert!(batched.process(3, metrics()).await.is_err());
+        assert_eq!(batched.pending(), 2);
+
+        batched.flush(metrics()).await.unwrap();
+        assert_eq!(*batches.lock().unwrap(), vec![vec![1, 2]]);
+        assert_eq!(batched.pending(), 0);
+    }
+
+    #[test]
+    fn zero_batch_size_is_treated_as_one() {
+        let batched = Batched::new(Recorder::default(), 0);
+
+        assert_eq!(batched.max_batch_size, 1);
+    }
 }
@@ -0,0 +1,195 @@
+//! Defines transaction schemas, which describe the shape of the transactions a
+//! transaction pipe is interested in.
+//!
+//! A `TransactionSchema` is a tree of `SchemaNode`s mirroring the nesting of
+//! the instructions in a transaction. Matching a schema against the parsed
+//! instructions of a transaction collects the instructions that matched each
+//! named node, which are then deserialized into a user-defined output type.
+//!
+//! # Overview
+//!
+//! - **`SchemaNode`**: Either an `InstructionSchemaNode` matching a single
+//!   instruction by type, or `Any`, which skips any number of instructions
+//!   until the next node matches.
+//! - **`InstructionSchemaNode`**: Matches an instruction of a given type and,
+//!   optionally, its inner instructions.
+//! - **`ParsedInstruction`**: An instruction decoded with an
+//!   `InstructionDecoderCollection`, together with its inner instructions.
+//! - **`TransactionSchema`**: The root nodes of a schema, with the matching
+//!   logic.
+//!
+//! # Example
+//!
+//! ```rust
+//! let schema = TransactionSchema::<AllInstructions> {
+//!     root: vec![
+//!         SchemaNode::Any,
+//!         SchemaNode::Instruction(InstructionSchemaNode {
+//!             ix_type: AllInstructionTypes::JupSwap(JupiterInstructionType::Route),
+//!             name: "route".to_string(),
+//!             inner_instructions: vec![],
+//!         }),
+//!     ],
+//! };
+//! ```
+//!
+//! # Notes
+//!
+//! - The output type is filled through `serde`, so its field names must match
+//!   the names of the schema nodes.
+
+use {
+    crate::{collection::InstructionDecoderCollection, instruction::DecodedInstruction},
+    serde::de::DeserializeOwned,
+    solana_sdk::{instruction::AccountMeta, pubkey::Pubkey},
+    std::collections::HashMap,
+};
+
+/// A node of a `TransactionSchema`.
+///
+/// # Variants
+///
+/// - `Instruction`: Matches a single instruction.
+/// - `Any`: Matches any number of instructions, including none, before the
+///   next node.
+#[derive(Debug, Clone)]
+pub enum SchemaNode<T: InstructionDecoderCollection> {
+    Instruction(InstructionSchemaNode<T>),
+    Any,
+}
+
+/// A schema node matching a single instruction.
+///
+/// # Fields
+///
+/// - `ix_type`: The type of instruction the node matches.
+/// - `name`: The name under which the matched instruction is collected.
+/// - `inner_instructions`: The nodes the inner instructions of the matched
+///   instruction must match.
+#[derive(Debug, Clone)]
+pub struct InstructionSchemaNode<T: InstructionDecoderCollection> {
+    pub ix_type: T::InstructionType,
+    pub name: String,
+    pub inner_instructions: Vec<SchemaNode<T>>,
+}
+
+/// An instruction decoded with an `InstructionDecoderCollection`.
+///
+/// # Fields
+///
+/// - `program_id`: The program that owns the instruction.
+/// - `instruction`: The decoded instruction.
+/// - `inner_instructions`: The decoded inner instructions.
+#[derive(Debug)]
+pub struct ParsedInstruction<T: InstructionDecoderCollection> {
+    pub program_id: Pubkey,
+    pub instruction: DecodedInstruction<T>,
+    pub inner_instructions: Vec<ParsedInstruction<T>>,
+}
+
+/// Describes the instructions of the transactions a transaction pipe is
+/// interested in.
+///
+/// # Fields
+///
+/// - `root`: The nodes matching the top-level instructions of a transaction.
+#[derive(Debug, Clone)]
+pub struct TransactionSchema<T: InstructionDecoderCollection> {
+    pub root: Vec<SchemaNode<T>>,
+}
+
+impl<T: InstructionDecoderCollection> TransactionSchema<T> {
+    /// Matches `instructions` against the schema and deserializes the matched
+    /// instructions into `U`.
+    ///
+    /// # Returns
+    ///
+    /// The matched instructions as `U`, keyed by node name, or `None` if the
+    /// instructions do not match the schema or do not deserialize into `U`.
+    pub fn match_schema<U>(&self, instructions: &[ParsedInstruction<T>]) -> Option<U>
+    where
+        U: DeserializeOwned,
+    {
+        log::trace!(
+            "TransactionSchema::match_schema(self: {:?}, instructions: {:?})",
+            self,
+            instructions
+        );
+
+        let value = serde_json::to_value(self.match_nodes(instructions)).ok()?;
+
+        serde_json::from_value::<U>(value).ok()
+    }
+
+    /// Matches `instructions` against the schema.
+    ///
+    /// # Returns
+    ///
+    /// The data and accounts of the instructions matched by each named node,
+    /// or an empty map if the instructions do not match the schema.
+    pub fn match_nodes(
+        &self,
+        instructions: &[ParsedInstruction<T>],
+    ) -> HashMap<String, (T, Vec<AccountMeta>)> {
+        let mut output = HashMap::new();
+        if Self::match_into(&self.root, instructions, &mut output) {
+            output
+        } else {
+            HashMap::new()
+        }
+    }
+
+    /// Matches `instructions` against `nodes`, collecting the matched
+    /// instructions into `output`. Returns whether the instructions matched.
+    fn match_into(
+        nodes: &[SchemaNode<T>],
+        instructions: &[ParsedInstruction<T>],
+        output: &mut HashMap<String, (T, Vec<AccountMeta>)>,
+    ) -> bool {
+        let mut remaining = instructions.iter();
+        let mut any = false;
+
+        for node in nodes {
+            let SchemaNode::Instruction(instruction_node) = node else {
+                any = true;
+                continue;
+            };
+
+            let matched = loop {
+                let Some(instruction) = remaining.next() else {
+                    break None;
+                };
+
+                if instruction.instruction.data.get_type() == instruction_node.ix_type {
+                    break Some(instruction);
+                }
+                if !any {
+                    break None;
+                }
+            };
+
+            let Some(instruction) = matched else {
+                return false;
+            };
+
+            output.insert(
+                instruction_node.name.clone(),
+                (
+                    instruction.instruction.data.clone(),
+                    instruction.instruction.accounts.clone(),
+                ),
+            );
+
+            if !Self::match_into(
+                &instruction_node.inner_instructions,
+                &instruction.inner_instructions,
+                output,
+            ) {
+                return false;
+            }
+            any = false;
+        }
+
+        true
+    }
+}
@@ -0,0 +1,152 @@
+//! Defines slot status updates, which report how far a slot has progressed
+//! towards finality.
+//!
+//! Updates received at `processed` or `confirmed` commitment belong to slots
+//! that may still be abandoned when the cluster switches forks. Datasources
+//! that can observe slot progress send `SlotStatusUpdate`s through the
+//! pipeline, which uses them to roll back the writes of dead slots or, in
+//! finalized only mode, to hold back updates until their slot is rooted.
+//!
+//! # Overview
+//!
+//! - **`SlotStatus`**: The stages a slot goes through, ending in either
+//!   `Rooted` or `Dead`.
+//! - **`SlotStatusUpdate`**: A status change of a single slot, as delivered by
+//!   a datasource.
+//! - **`SlotStatusPipe`**: Passes slot status updates to a `Processor`, for
+//!   applications that track slot progress themselves.
+//!
+//! # Notes
+//!
+//! - Once a slot is rooted it can no longer be rolled back, and neither can
+//!   any of its ancestors.
+//! - A slot reported as `Dead` will never be rooted. Processors that already
+//!   wrote data for it are asked to undo those writes through
+//!   `Processor::rollback`.
+
+use {
+    crate::{error::CarbonResult, metrics::MetricsCollection, processor::Processor},
+    async_trait::async_trait,
+    serde::{Deserialize, Serialize},
+    std::sync::Arc,
+};
+
+/// The progress of a slot towards finality.
+///
+/// # Variants
+///
+/// - `Processed`: The slot has been processed by the node.
+/// - `Confirmed`: The slot has been voted on by a supermajority of the
+///   cluster.
+/// - `Rooted`: The slot has been finalized and can no longer be rolled back.
+/// - `Dead`: The slot belongs to an abandoned fork and will never be rooted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SlotStatus {
+    Processed,
+    Confirmed,
+    Rooted,
+    Dead,
+}
+
+/// A status change of a slot.
+///
+/// # Fields
+///
+/// - `slot`: The slot whose status changed.
+/// - `parent`: The parent slot, if known.
+/// - `status`: The new `SlotStatus` of the slot.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SlotStatusUpdate {
+    pub slot: u64,
+    pub parent: Option<u64>,
+    pub status: SlotStatus,
+}
+
+/// A processing pipe that passes slot status updates to a `Processor`.
+///
+/// # Fields
+///
+/// - `processor`: A `Processor` that handles `SlotStatusUpdate`s.
+pub struct SlotStatusPipe {
+    pub processor: Box<dyn Processor<InputType = SlotStatusUpdate> + Send + Sync>,
+}
+
+/// A trait for processing slot status updates in the pipeline asynchronously.
+///
+/// `SlotStatusPipes` defines the `run` method for processing slot status
+/// updates. The `rollback` method is called when a slot is abandoned by a
+/// fork, and the `initialize`, `flush` and `shutdown` methods at the matching
+/// points of the pipeline's lifecycle. All of them do nothing by default.
+///
+/// # Parameters
+///
+/// - `slot_status`: The `SlotStatusUpdate` to process.
+/// - `metrics`: A list of `Metrics` objects for recording and tracking metrics.
+#[async_trait]
+pub trait SlotStatusPipes: Send + Sync {
+    async fn run(
+        &mut self,
+        slot_status: SlotStatusUpdate,
+        metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()>;
+
+    async fn rollback(&mut self, slot: u64, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = (slot, metrics);
+        Ok(())
+    }
+
+    async fn initialize(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+
+    async fn flush(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+
+    async fn shutdown(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SlotStatusPipes for SlotStatusPipe {
+    async fn run(
+        &mut self,
+        slot_status: SlotStatusUpdate,
+        metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()> {
+        log::trace!(
+            "SlotStatusPipe::run(slot_status: {:?}, metrics)",
+            slot_status,
+        );
+
+        self.processor.process(slot_status, metrics).await
+    }
+
+    async fn rollback(&mut self, slot: u64, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("SlotStatusPipe::rollback(slot: {:?}, metrics)", slot);
+
+        self.processor.rollback(slot, metrics).await
+    }
+
+    async fn initialize(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("SlotStatusPipe::initialize(metrics)");
+
+        self.processor.initialize(metrics).await
+    }
+
+    async fn flush(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("SlotStatusPipe::flush(metrics)");
+
+        self.processor.flush(metrics).await
+    }
+
+    async fn shutdown(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("SlotStatusPipe::shutdown(metrics)");
+
+        self.processor.shutdown(metrics).await
+    }
+}
@@ -0,0 +1,300 @@
+//! Provides structures and traits for processing whole transactions within
+//! the pipeline.
+//!
+//! This module extracts the metadata of transactions, decodes their
+//! instructions with an `InstructionDecoderCollection` and, if a
+//! `TransactionSchema` is set, matches the decoded instructions against it
+//! before handing everything to a `Processor`.
+//!
+//! # Overview
+//!
+//! - **`TransactionMetadata`**: The metadata of a transaction, such as its
+//!   slot, signature, fee payer and status metadata.
+//! - **`TransactionPipe`**: Decodes the instructions of a transaction, matches
+//!   them against an optional schema and passes the result to a `Processor`.
+//! - **`TransactionPipes`**: An async trait for processing transactions in
+//!   the pipeline.
+//!
+//! # Example
+//!
+//! ```rust
+//! struct SwapProcessor;
+//!
+//! #[async_trait]
+//! impl Processor for SwapProcessor {
+//!     type InputType = TransactionProcessorInputType<AllInstructions, SwapOutput>;
+//!
+//!     async fn process(
+//!         &mut self,
+//!         (metadata, instructions, matched): Self::InputType,
+//!         metrics: Arc<MetricsCollection>,
+//!     ) -> CarbonResult<()> {
+//!         if let Some(swap) = matched {
+//!             log::info!("swap in {}: {:?}", metadata.signature, swap);
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//! ```
+//!
+//! # Notes
+//!
+//! - Instructions that none of the decoders of the collection recognize are
+//!   left out, and their decoded inner instructions take their place.
+
+use {
+    crate::{
+        collection::InstructionDecoderCollection,
+        datasource::TransactionUpdate,
+        error::{CarbonResult, Error},
+        instruction::NestedInstruction,
+        metrics::MetricsCollection,
+        processor::Processor,
+        schema::{ParsedInstruction, TransactionSchema},
+    },
+    async_trait::async_trait,
+    serde::de::DeserializeOwned,
+    solana_sdk::{pubkey::Pubkey, signature::Signature},
+    std::sync::Arc,
+};
+
+/// Holds the metadata of a transaction.
+///
+/// # Fields
+///
+/// - `slot`: The slot in which the transaction was recorded.
+/// - `signature`: The signature of the transaction.
+/// - `fee_payer`: The account that paid the transaction fee.
+/// - `meta`: The status metadata of the transaction, such as its fee, logs and
+///   inner instructions.
+/// - `message`: The message of the transaction.
+/// - `block_time`: The Unix timestamp of the block containing the transaction,
+///   if known.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionMetadata {
+    pub slot: u64,
+    pub signature: Signature,
+    pub fee_payer: Pubkey,
+    pub meta: solana_transaction_status::TransactionStatusMeta,
+    pub message: solana_sdk::message::VersionedMessage,
+    pub block_time: Option<i64>,
+}
+
+impl TryFrom<TransactionUpdate> for TransactionMetadata {
+    type Error = Error;
+
+    /// Extracts the metadata of a transaction update.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::MissingFeePayer` if the transaction has no account
+    /// keys.
+    fn try_from(value: TransactionUpdate) -> Result<Self, Self::Error> {
+        log::trace!("try_from(transaction_update: {:?})", value);
+
+        let fee_payer = *value
+            .transaction
+            .message
+            .static_account_keys()
+            .first()
+            .ok_or(Error::MissingFeePayer)?;
+
+        Ok(TransactionMetadata {
+            slot: value.slot,
+            signature: value.signature,
+            fee_payer,
+            meta: value.meta,
+            message: value.transaction.message,
+            block_time: value.block_time,
+        })
+    }
+}
+
+/// The input type for the transaction processor.
+///
+/// - `T`: The instruction decoder collection used to decode the instructions.
+/// - `U`: The output type of the schema match, if a schema is set.
+pub type TransactionProcessorInputType<T, U = ()> =
+    (TransactionMetadata, Vec<ParsedInstruction<T>>, Option<U>);
+
+/// A processing pipe that decodes whole transactions and passes them to a
+/// `Processor`.
+///
+/// # Type Parameters
+///
+/// - `T`: The instruction decoder collection used to decode the instructions.
+/// - `U`: The output type of the schema match.
+pub struct TransactionPipe<T: InstructionDecoderCollection, U> {
+    schema: Option<TransactionSchema<T>>,
+    processor: Box<dyn Processor<InputType = TransactionProcessorInputType<T, U>> + Send + Sync>,
+}
+
+impl<T: InstructionDecoderCollection, U> TransactionPipe<T, U> {
+    /// Creates a `TransactionPipe` that matches transactions against `schema`,
+    /// if set, and passes them to `processor`.
+    pub fn new(
+        schema: Option<TransactionSchema<T>>,
+        processor: impl Processor<InputType = TransactionProcessorInputType<T, U>>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        log::trace!("TransactionPipe::new(schema: {:?}, processor)", schema);
+
+        Self {
+            schema,
+            processor: Box::new(processor),
+        }
+    }
+
+    fn matches_schema(&self, instructions: &[ParsedInstruction<T>]) -> Option<U>
+    where
+        U: DeserializeOwned,
+    {
+        self.schema
+            .as_ref()
+            .and_then(|schema| schema.match_schema(instructions))
+    }
+}
+
+/// Decodes nested instructions with the instruction decoder collection `T`.
+///
+/// Instructions that `T` does not recognize are left out, and their decoded
+/// inner instructions are returned in their place.
+pub fn parse_instructions<T: InstructionDecoderCollection>(
+    nested_instructions: &[NestedInstruction],
+) -> Vec<ParsedInstruction<T>> {
+    log::trace!(
+        "parse_instructions(nested_instructions: {:?})",
+        nested_instructions
+    );
+
+    let mut parsed_instructions = Vec::new();
+
+    for nested_instruction in nested_instructions {
+        let inner_instructions = parse_instructions(&nested_instruction.inner_instructions);
+
+        match T::parse_instruction(&nested_instruction.instruction) {
+            Some(instruction) => parsed_instructions.push(ParsedInstruction {
+                program_id: nested_instruction.instruction.program_id,
+                instruction,
+                inner_instructions,
+            }),
+            None => parsed_instructions.extend(inner_instructions),
+        }
+    }
+
+    parsed_instructions
+}
+
+/// A trait for processing transactions in the pipeline asynchronously.
+///
+/// `TransactionPipes` defines the `run` method for processing a transaction
+/// together with its nested instructions. The `rollback` method is called when
+/// a slot is abandoned by a fork, and the `initialize`, `flush` and `shutdown`
+/// methods at the matching points of the pipeline's lifecycle. All of them do
+/// nothing by default.
+///
+/// # Parameters
+///
+/// - `transaction_metadata`: The metadata of the transaction.
+/// - `instructions`: The instructions of the transaction, nested by stack
+///   height.
+/// - `metrics`: A list of `Metrics` objects for recording and tracking metrics.
+#[async_trait]
+pub trait TransactionPipes<'a>: Send + Sync {
+    async fn run(
+        &mut self,
+        transaction_metadata: TransactionMetadata,
+        instructions: &[NestedInstruction],
+        metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()>;
+
+    async fn rollback(&mut self, slot: u64, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = (slot, metrics);
+        Ok(())
+    }
+
+    async fn initialize(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+
+    async fn flush(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+
+    async fn shutdown(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        let _ = metrics;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T, U> TransactionPipes<'_> for TransactionPipe<T, U>
+where
+    T: InstructionDecoderCollection,
+    U: DeserializeOwned + Send + Sync + 'static,
+{
+    async fn run(
+        &mut self,
+        transaction_metadata: TransactionMetadata,
+        instructions: &[NestedInstruction],
+        metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()> {
+        log::trace!(
+            "TransactionPipe::run(transaction_metadata: {:?}, instructions: {:?}, metrics)",
+            transaction_metadata,
+            instructions,
+        );
+
+        let (parsed_instructions, matched_data) = {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!("decode").entered();
+
+            let parsed_instructions = parse_instructions::<T>(instructions);
+            let matched_data = self.matches_schema(&parsed_instructions);
+
+            (parsed_instructions, matched_data)
+        };
+
+        let process = self.processor.process(
+            (transaction_metadata, parsed_instructions, matched_data),
+            metrics,
+        );
+
+        #[cfg(feature = "tracing")]
+        let process = {
+            use tracing::Instrument;
+
+            process.instrument(tracing::info_span!("process"))
+        };
+
+        process.await
+    }
+
+    async fn rollback(&mut self, slot: u64, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("TransactionPipe::rollback(slot: {:?}, metrics)", slot);
+
+        self.processor.rollback(slot, metrics).await
+    }
+
+    async fn initialize(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("TransactionPipe::initialize(metrics)");
+
+        self.processor.initialize(metrics).await
+    }
+
+    async fn flush(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("TransactionPipe::flush(metrics)");
+
+        self.processor.flush(metrics).await
+    }
+
+    async fn shutdown(&mut self, metrics: Arc<MetricsCollection>) -> CarbonResult<()> {
+        log::trace!("TransactionPipe::shutdown(metrics)");
+
+        self.processor.shutdown(metrics).await
+    }
+}
@@ -21,12 +21,21 @@
 //!   loaded addresses and inner instructions.
 
 use {
-    crate::error::{CarbonResult, Error},
+    crate::{
+        datasource::TransactionUpdate,
+        error::{CarbonResult, Error},
+        instruction::{InstructionMetadata, InstructionsWithMetadata},
+        transaction::TransactionMetadata,
+    },
     solana_sdk::{
-        address_lookup_table::instruction, inner_instruction, instruction::{AccountMeta, CompiledInstruction}, message::{
+        instruction::{AccountMeta, CompiledInstruction},
+        message::{
             v0::{LoadedAddresses, LoadedMessage},
             VersionedMessage,
-        }, pubkey::Pubkey, reserved_account_keys::ReservedAccountKeys, transaction_context::TransactionReturnData
+        },
+        pubkey::Pubkey,
+        reserved_account_keys::ReservedAccountKeys,
+        transaction_context::TransactionReturnData,
     },
     solana_transaction_status::{
         option_serializer::OptionSerializer, InnerInstruction, InnerInstructions, Reward,
@@ -229,6 +238,127 @@ pub fn extract_instructions_with_metadata(
     Ok(instructions)
 }
 
+/// Extracts the instructions of a transaction update, each with its
+/// `InstructionMetadata`.
+///
+/// Top-level instructions get a stack height of 1, and inner instructions the
+/// stack height recorded in the transaction status metadata. The result can be
+/// turned into `NestedInstructions` to rebuild the invocation tree.
+///
+/// # Parameters
+///
+/// - `transaction_metadata`: The metadata of the transaction, attached to
+///   every instruction.
+/// - `transaction_update`: The `TransactionUpdate` containing the transaction's
+///   message and status metadata.
+///
+/// # Returns
+///
+/// A `CarbonResult<InstructionsWithMetadata>` with the instructions in
+/// execution order.
+///
+/// # Notes
+///
+/// - Inner instructions recorded before stack heights were tracked are given a
+///   stack height of 2.
+/// - Account indexes that do not resolve to an account key are mapped to the
+///   default public key, so that the positions of the remaining accounts are
+///   kept.
+pub fn extract_instructions_with_stack_heights(
+    transaction_metadata: &TransactionMetadata,
+    transaction_update: &TransactionUpdate,
+) -> CarbonResult<InstructionsWithMetadata> {
+    log::trace!(
+        "extract_instructions_with_stack_heights(transaction_metadata: {:?}, transaction_update: {:?})",
+        transaction_metadata,
+        transaction_update
+    );
+
+    let message = &transaction_update.transaction.message;
+    let meta = &transaction_update.meta;
+
+    let account_metas: Vec<AccountMeta> = match message {
+        VersionedMessage::Legacy(legacy) => legacy
+            .account_keys
+            .iter()
+            .enumerate()
+            .map(|(index, pubkey)| AccountMeta {
+                pubkey: *pubkey,
+                is_writable: legacy.is_maybe_writable(index, None),
+                is_signer: legacy.is_signer(index),
+            })
+            .collect(),
+        VersionedMessage::V0(v0) => {
+            let loaded_message = LoadedMessage::new(
+                v0.clone(),
+                meta.loaded_addresses.clone(),
+                &ReservedAccountKeys::empty_key_set(),
+            );
+
+            loaded_message
+                .account_keys()
+                .iter()
+                .enumerate()
+                .map(|(index, pubkey)| AccountMeta {
+                    pubkey: *pubkey,
+                    is_writable: loaded_message.is_writable(index),
+                    is_signer: loaded_message.is_signer(index),
+                })
+                .collect()
+        }
+    };
+
+    let to_instruction = |compiled_instruction: &CompiledInstruction| {
+        let account_meta = |index: u8| {
+            account_metas
+                .get(index as usize)
+                .cloned()
+                .unwrap_or(AccountMeta {
+                    pubkey: Pubkey::default(),
+                    is_writable: false,
+                    is_signer: false,
+                })
+        };
+
+        solana_sdk::instruction::Instruction {
+            program_id: account_meta(compiled_instruction.program_id_index).pubkey,
+            accounts: compiled_instruction
+                .accounts
+                .iter()
+                .map(|index| account_meta(*index))
+                .collect(),
+            data: compiled_instruction.data.clone(),
+        }
+    };
+
+    let instruction_metadata = |stack_height: u32| InstructionMetadata {
+        transaction_metadata: transaction_metadata.clone(),
+        stack_height,
+    };
+
+    let mut instructions = InstructionsWithMetadata::with_capacity(message.instructions().len());
+
+    for (index, compiled_instruction) in message.instructions().iter().enumerate() {
+        instructions.push((instruction_metadata(1), to_instruction(compiled_instruction)));
+
+        let inner_instructions = meta
+            .inner_instructions
+            .iter()
+            .flatten()
+            .filter(|inner_instructions| inner_instructions.index as usize == index)
+            .flat_map(|inner_instructions| inner_instructions.instructions.iter());
+
+        for inner_instruction in inner_instructions {
+            instructions.push((
+                instruction_metadata(inner_instruction.stack_height.unwrap_or(2)),
+                to_instruction(&inner_instruction.instruction),
+            ));
+        }
+    }
+
+    Ok(instructions)
+}
+
 
 
 /// Extracts instructions with metadata from a transaction update.
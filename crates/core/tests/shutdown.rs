@@ -0,0 +1,99 @@
+//! Shuts a running pipeline down with SIGTERM.
+//!
+//! The signal is delivered to the whole process, so this test lives in its own
+//! test binary rather than next to the other pipeline tests.
+
+#![cfg(unix)]
+
+use {
+    async_trait::async_trait,
+    carbon_core::{
+        block::BlockDetails,
+        channel::UpdateSender,
+        checkpoint::Checkpoint,
+        datasource::{Datasource, DatasourceId, Update, UpdateType},
+        error::CarbonResult,
+        metrics::MetricsCollection,
+        pipeline::{Pipeline, ShutdownStrategy},
+        processor::Processor,
+    },
+    std::sync::{Arc, Mutex},
+    tokio::sync::Notify,
+    tokio_util::sync::CancellationToken,
+};
+
+/// Sends three blocks, then waits until it is cancelled.
+struct Blocks;
+
+#[async_trait]
+impl Datasource for Blocks {
+    async fn consume(
+        &self,
+        id: DatasourceId,
+        sender: &UpdateSender<(Update, DatasourceId)>,
+        _resume_from: Option<Checkpoint>,
+        cancellation_token: CancellationToken,
+        _metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()> {
+        for slot in 1..=3 {
+            let block = BlockDetails {
+                slot,
+                ..Default::default()
+            };
+            sender.send((Update::Block(block), id.clone())).await?;
+        }
+
+        cancellation_token.cancelled().await;
+        Ok(())
+    }
+
+    fn update_types(&self) -> Vec<UpdateType> {
+        vec![UpdateType::BlockDetails]
+    }
+}
+
+/// Records the slots it processes and notifies `processed` after each.
+#[derive(Clone, Default)]
+struct Recorder {
+    slots: Arc<Mutex<Vec<u64>>>,
+    processed: Arc<Notify>,
+}
+
+#[async_trait]
+impl Processor for Recorder {
+    type InputType = BlockDetails;
+
+    async fn process(
+        &mut self,
+        block_details: BlockDetails,
+        _metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()> {
+        self.slots.lock().unwrap().push(block_details.slot);
+        self.processed.notify_one();
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn sigterm_shuts_down_with_the_configured_strategy() {
+    let recorder = Recorder::default();
+    let mut pipeline = Pipeline::builder()
+        .datasource(Blocks)
+        .shutdown_strategy(ShutdownStrategy::ProcessPending)
+        .block(recorder.clone())
+        .build()
+        .unwrap();
+
+    let pipeline_task = tokio::spawn(async move { pipeline.run().await });
+
+    // The pipeline listens for signals before it processes any update.
+    recorder.processed.notified().await;
+    let status = std::process::Command::new("kill")
+        .args(["-TERM", &std::process::id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    pipeline_task.await.unwrap().unwrap();
+    assert_eq!(*recorder.slots.lock().unwrap(), [1, 2, 3]);
+}
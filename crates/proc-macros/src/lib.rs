@@ -80,6 +80,19 @@ use {
 /// }
 /// ```
 ///
+/// Add `allow_trailing` to the attribute to tolerate bytes left over after
+/// the type has been decoded, such as reserved padding or fields added by a
+/// later program upgrade. `try_deserialize_with_trailing` reports how many
+/// bytes were left unconsumed.
+///
+/// ```ignore
+/// #[derive(CarbonDeserialize)]
+/// #[carbon(discriminator = "0x1234", allow_trailing)]
+/// struct MyAccount {
+///     id: u32,
+/// }
+/// ```
+///
 /// # Example
 ///
 /// ```rust
@@ -93,6 +106,11 @@ use {
 /// let bytes = vec![0x01, 0x00, 0x10, 0x20, 0x30]; // Serialized data
 /// let message = Message::deserialize(&bytes)
 ///     .expect("Failed to deserialize `Message`");
+///
+/// match Message::try_deserialize(&bytes) {
+///     Ok(message) => println!("header: {}", message.header),
+///     Err(error) => println!("failed to decode at offset {}: {}", error.offset, error.reason),
+/// }
 /// ```
 ///
 /// # Parameters
@@ -111,6 +129,8 @@ use {
 ///
 /// - The `#[carbon(discriminator = "0x...")]` attribute is optional. If not
 ///   provided, the deserialization proceeds without a discriminator check.
+/// - Trailing bytes can also be allowed for every type with a discriminator
+///   at once with `carbon_core::deserialize::set_allow_trailing_bytes`.
 /// - Ensure the discriminator matches the data's format exactly, as the
 ///   deserialization will return `None` if there is a mismatch.
 /// - The macro will panic if the discriminator is invalid or not provided
@@ -121,6 +141,9 @@ use {
 /// - The macro will return `None` during deserialization if the data is shorter
 ///   than the discriminator or if there is a mismatch between the provided and
 ///   expected discriminators.
+/// - The generated `try_deserialize` returns a `DecodeError` instead, holding
+///   the offset at which decoding failed and whether the discriminator did not
+///   match, the data did not match the Borsh layout, or bytes were left over.
 #[proc_macro_derive(CarbonDeserialize, attributes(carbon))]
 pub fn carbon_deserialize_derive(input_token_stream: TokenStream) -> TokenStream {
     let derive_input = input_token_stream.clone();
@@ -128,6 +151,7 @@ pub fn carbon_deserialize_derive(input_token_stream: TokenStream) -> TokenStream
     let name = &input.ident;
 
     let discriminator = get_discriminator(&input.attrs).unwrap_or(quote! { &[] });
+    let allow_trailing = has_carbon_flag(&input.attrs, "allow_trailing");
 
     let deser = gen_borsh_deserialize(input_token_stream);
 
@@ -136,18 +160,22 @@ pub fn carbon_deserialize_derive(input_token_stream: TokenStream) -> TokenStream
 
         #[automatically_derived]
         impl carbon_core::deserialize::CarbonDeserialize for #name {
-            fn deserialize(data: &[u8]) -> Option<Self> {
-                let discriminator: &[u8] = #discriminator;
-                if data.len() < discriminator.len() {
-                    return None;
-                }
+            fn try_deserialize(
+                data: &[u8],
+            ) -> ::core::result::Result<Self, carbon_core::deserialize::DecodeError> {
+                Self::try_deserialize_with_trailing(data).map(|(value, _)| value)
+            }
 
-                let (disc, rest) = data.split_at(discriminator.len());
-                if disc != discriminator {
-                    return None;
-                }
+            fn try_deserialize_with_trailing(
+                data: &[u8],
+            ) -> ::core::result::Result<(Self, usize), carbon_core::deserialize::DecodeError> {
+                let discriminator: &[u8] = #discriminator;
 
-                carbon_core::borsh::BorshDeserialize::try_from_slice(rest).ok()
+                carbon_core::deserialize::deserialize_with_discriminator(
+                    discriminator,
+                    data,
+                    #allow_trailing,
+                )
             }
         }
     };
@@ -312,6 +340,30 @@ fn get_discriminator(attrs: &[syn::Attribute]) -> Option<quote::__private::Token
     })
 }
 
+/// Returns whether a `carbon` attribute contains the flag `name`, as in
+/// `#[carbon(allow_trailing)]`.
+///
+/// # Parameters
+///
+/// - `attrs`: The attributes of the item.
+/// - `name`: The name of the flag.
+fn has_carbon_flag(attrs: &[syn::Attribute], name: &str) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("carbon"))
+        .filter_map(|attr| attr.parse_meta().ok())
+        .any(|meta| {
+            let Meta::List(list) = meta else {
+                return false;
+            };
+
+            list.nested.iter().any(|nested| match nested {
+                NestedMeta::Meta(Meta::Path(path)) => path.is_ident(name),
+                _ => false,
+            })
+        })
+}
+
 /// Represents the parsed input for the `instruction_decoder_collection!` macro.
 ///
 /// The `InstructionMacroInput` struct holds the essential elements required
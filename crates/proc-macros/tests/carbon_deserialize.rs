@@ -0,0 +1,70 @@
+//! Derives `CarbonDeserialize` and checks how the flags of the `carbon`
+//! attribute change the generated decoding.
+
+use {
+    carbon_core::{
+        borsh,
+        deserialize::{CarbonDeserialize, DecodeError, DecodeErrorReason},
+    },
+    carbon_proc_macros::CarbonDeserialize,
+};
+
+#[derive(Debug, PartialEq, CarbonDeserialize)]
+#[carbon(discriminator = "0x01")]
+struct Strict {
+    amount: u16,
+}
+
+#[derive(Debug, PartialEq, CarbonDeserialize)]
+#[carbon(discriminator = "0x01", allow_trailing)]
+struct Lenient {
+    amount: u16,
+}
+
+#[derive(Debug, PartialEq, CarbonDeserialize)]
+#[carbon(allow_trailing)]
+struct LenientWithoutDiscriminator {
+    amount: u16,
+}
+
+#[test]
+fn trailing_bytes_are_rejected_without_the_flag() {
+    assert_eq!(
+        Strict::try_deserialize(&[1, 5, 0]),
+        Ok(Strict { amount: 5 })
+    );
+    assert_eq!(
+        Strict::try_deserialize_with_trailing(&[1, 5, 0, 9]),
+        Err(DecodeError {
+            offset: 3,
+            reason: DecodeErrorReason::TrailingBytes { remaining: 1 },
+        })
+    );
+}
+
+#[test]
+fn allow_trailing_tolerates_trailing_bytes() {
+    assert_eq!(
+        Lenient::try_deserialize_with_trailing(&[1, 5, 0, 9, 9]),
+        Ok((Lenient { amount: 5 }, 2))
+    );
+    assert_eq!(
+        Lenient::try_deserialize(&[1, 5, 0, 9]),
+        Ok(Lenient { amount: 5 })
+    );
+    assert!(matches!(
+        Lenient::try_deserialize(&[2, 5, 0]),
+        Err(DecodeError {
+            offset: 0,
+            reason: DecodeErrorReason::DiscriminatorMismatch { .. },
+        })
+    ));
+}
+
+#[test]
+fn allow_trailing_applies_without_a_discriminator() {
+    assert_eq!(
+        LenientWithoutDiscriminator::try_deserialize_with_trailing(&[5, 0, 9]),
+        Ok((LenientWithoutDiscriminator { amount: 5 }, 1))
+    );
+}